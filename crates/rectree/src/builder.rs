@@ -0,0 +1,160 @@
+use alloc::vec::Vec;
+
+use crate::node::RectNode;
+use crate::{NodeId, Rectree};
+
+/// Describes a subtree of [`RectNode`]s to be inserted at once,
+/// without threading parent ids manually.
+///
+/// ```
+/// use rectree::Rectree;
+/// use rectree::builder::TreeBuilder;
+/// use rectree::node::RectNode;
+///
+/// let mut tree = Rectree::new();
+/// let root = TreeBuilder::new()
+///     .node(RectNode::from_size((100.0, 100.0)))
+///     .child(|b| b.node(RectNode::from_size((50.0, 50.0))))
+///     .child(|b| b.child(|b| b).child(|b| b))
+///     .build_into(&mut tree);
+///
+/// assert_eq!(tree.child_count(&root), 2);
+/// ```
+#[derive(Default, Debug, Clone)]
+pub struct TreeBuilder {
+    node: RectNode,
+    children: Vec<TreeBuilder>,
+}
+
+impl TreeBuilder {
+    /// Creates a builder for a single default [`RectNode`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the node at the root of this subtree.
+    ///
+    /// The parent of the node is only kept for the root of the
+    /// whole subtree, allowing it to be attached to an existing
+    /// node, see [`RectNode::with_parent()`].
+    pub fn node(mut self, node: RectNode) -> Self {
+        self.node = node;
+        self
+    }
+
+    /// Appends a child subtree, described by `f` from an empty
+    /// builder.
+    pub fn child(mut self, f: impl FnOnce(Self) -> Self) -> Self {
+        self.children.push(f(Self::new()));
+        self
+    }
+
+    /// Inserts the whole subtree into the tree, parents before
+    /// their children and children in the order they were added.
+    ///
+    /// Returns the [`NodeId`] of the root of the subtree.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as [`Rectree::insert()`].
+    pub fn build_into(self, tree: &mut Rectree) -> NodeId {
+        let id = tree.insert(self.node);
+        for mut child in self.children {
+            child.node.parent = Some(id);
+            child.build_into(tree);
+        }
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use kurbo::Rect;
+
+    use super::*;
+    use crate::solvers::PassthroughWorld;
+
+    fn rect_node(x: f64, y: f64, w: f64, h: f64) -> RectNode {
+        RectNode::from_rect(Rect::new(x, y, x + w, y + h))
+    }
+
+    #[test]
+    fn test_build_into_matches_manual() {
+        // Manual insertion.
+        let mut manual = Rectree::new();
+        let root = manual.insert(rect_node(0.0, 0.0, 200.0, 100.0));
+        let left = manual.insert(
+            rect_node(0.0, 0.0, 100.0, 100.0).with_parent(root),
+        );
+        manual.insert(
+            rect_node(5.0, 5.0, 20.0, 20.0).with_parent(left),
+        );
+        manual.insert(
+            rect_node(30.0, 5.0, 20.0, 20.0).with_parent(left),
+        );
+        let right = manual.insert(
+            rect_node(100.0, 0.0, 100.0, 100.0).with_parent(root),
+        );
+        manual.insert(
+            rect_node(10.0, 10.0, 50.0, 50.0).with_parent(right),
+        );
+
+        // Builder.
+        let mut built = Rectree::new();
+        let built_root = TreeBuilder::new()
+            .node(rect_node(0.0, 0.0, 200.0, 100.0))
+            .child(|b| {
+                b.node(rect_node(0.0, 0.0, 100.0, 100.0))
+                    .child(|b| {
+                        b.node(rect_node(5.0, 5.0, 20.0, 20.0))
+                    })
+                    .child(|b| {
+                        b.node(rect_node(30.0, 5.0, 20.0, 20.0))
+                    })
+            })
+            .child(|b| {
+                b.node(rect_node(100.0, 0.0, 100.0, 100.0)).child(
+                    |b| b.node(rect_node(10.0, 10.0, 50.0, 50.0)),
+                )
+            })
+            .build_into(&mut built);
+
+        assert_eq!(built_root, root);
+
+        manual.layout(&PassthroughWorld);
+        built.layout(&PassthroughWorld);
+
+        let mut manual_rects = vec![];
+        manual.collect_world_rects(&mut manual_rects);
+        manual_rects.sort_by_key(|(id, _)| *id);
+        let mut built_rects = vec![];
+        built.collect_world_rects(&mut built_rects);
+        built_rects.sort_by_key(|(id, _)| *id);
+        assert_eq!(built_rects, manual_rects);
+
+        for (id, _) in manual_rects {
+            let (manual, built) = (manual.get(&id), built.get(&id));
+            assert_eq!(built.parent(), manual.parent());
+            assert_eq!(built.children(), manual.children());
+            assert_eq!(built.depth(), manual.depth());
+        }
+    }
+
+    #[test]
+    fn test_build_into_existing_parent() {
+        let mut tree = Rectree::new();
+        let root = tree.insert(RectNode::new());
+
+        let subtree = TreeBuilder::new()
+            .node(RectNode::new().with_parent(root))
+            .child(|b| b.child(|b| b))
+            .build_into(&mut tree);
+
+        assert_eq!(tree.get(&subtree).parent(), Some(root));
+        assert_eq!(tree.get(&subtree).depth(), 1);
+        let child =
+            *tree.get(&subtree).children().iter().next().unwrap();
+        assert_eq!(tree.child_count(&child), 1);
+    }
+}
@@ -1,16 +1,25 @@
 use alloc::collections::btree_set::BTreeSet;
+use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
-use kurbo::{Size, Vec2};
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
+use hashbrown::HashMap;
+use kurbo::{Insets, Rect, Size, Vec2};
 
 use crate::node::RectNode;
 use crate::{NodeId, Rectree};
 
 /// Layout execution.
 impl Rectree {
+    /// Maximum number of [`LayoutSolver::finalize()`] calls per
+    /// build, preventing solvers from looping indefinitely.
+    pub const MAX_FINALIZE_PASSES: usize = 4;
+
     /// Check if we need to call [`Self::layout()`].
     pub fn needs_relayout(&self) -> bool {
         !self.scheduled_relayout.is_empty()
+            || !self.layout_pass.is_empty()
     }
 
     /// Schedules a node for relayout.
@@ -20,42 +29,489 @@ impl Rectree {
     pub fn schedule_relayout(&mut self, id: NodeId) -> bool {
         if let Some(node) = self.nodes.get_mut(&id) {
             node.state.reset();
-            return self
-                .scheduled_relayout
-                .insert(DepthNode::new(node.depth, id));
+            node.mark_relayout(RelayoutReason::Scheduled);
+            let depth_node = DepthNode::new(node, id);
+            self.clear_memos(id);
+            return self.scheduled_relayout.insert(depth_node);
         }
 
         false
     }
 
+    /// Takes all nodes scheduled for relayout, deepest first, for
+    /// use by an external layout engine.
+    ///
+    /// Nodes of equal depth are yielded in their insertion order.
+    /// The drained nodes bypass [`Self::layout()`] entirely, leaving
+    /// their state flags and geometry for the caller to resolve.
+    pub fn drain_scheduled_relayout(
+        &mut self,
+    ) -> impl Iterator<Item = NodeId> + use<> {
+        self.drain_scheduled_relayout_topdown().rev()
+    }
+
+    /// Same as [`Self::drain_scheduled_relayout()`], but yields the
+    /// shallowest nodes first.
+    pub fn drain_scheduled_relayout_topdown(
+        &mut self,
+    ) -> impl DoubleEndedIterator<Item = NodeId> + use<> {
+        core::mem::take(&mut self.scheduled_relayout)
+            .into_iter()
+            .map(|depth_node| depth_node.id)
+    }
+
+    /// Returns `true` if a node has pending layout work, see
+    /// [`Self::relayout_reason()`].
+    pub fn needs_relayout_node(&self, id: &NodeId) -> bool {
+        self.relayout_reason(id).is_some()
+    }
+
+    /// Returns why a node has pending layout work, or `None` if it
+    /// is fully laid out or does not exist.
+    ///
+    /// This is mostly useful for debugging why a node does not
+    /// update as expected.
+    pub fn relayout_reason(
+        &self,
+        id: &NodeId,
+    ) -> Option<RelayoutReason> {
+        self.try_get(id).and_then(|node| node.relayout_reason)
+    }
+
+    /// Sets the [`LayoutParams`] of a node, scheduling its parent
+    /// for relayout if they changed.
+    ///
+    /// Returns `false` if the node does not exist.
+    pub fn set_layout_params(
+        &mut self,
+        id: NodeId,
+        params: LayoutParams,
+    ) -> bool {
+        let Some(node) = self.try_get_mut(&id) else {
+            return false;
+        };
+        if node.layout_params != params {
+            node.layout_params = params;
+            if let Some(parent) = node.parent {
+                self.schedule_relayout(parent);
+            }
+        }
+
+        true
+    }
+
+    /// Returns the children of a node taking part in its layout, in
+    /// document order. See [`LayoutChildren`].
+    pub fn layout_children(
+        &self,
+        node: &RectNode,
+    ) -> LayoutChildren<'_> {
+        let ids = node
+            .children()
+            .iter()
+            .filter(|id| !self.get(id).is_absolute())
+            .copied()
+            .collect();
+
+        LayoutChildren { tree: self, ids }
+    }
+
+    /// Returns the committed sizes of the children of a node taking
+    /// part in its layout, including their margins, in document
+    /// order, see [`Self::layout_children()`].
+    ///
+    /// Children are built before their parent, so the sizes are
+    /// final by the time the solver of the parent runs, e.g. to
+    /// distribute the remaining space between them.
+    ///
+    /// Non-existent nodes are treated as having no children.
+    pub fn children_sizes(
+        &self,
+        id: &NodeId,
+    ) -> impl Iterator<Item = (NodeId, Size)> + '_ {
+        let ids = self
+            .try_get(id)
+            .map(|node| self.layout_children(node).ids)
+            .unwrap_or_default();
+        ids.into_iter().map(|id| (id, self.get(&id).outer_size()))
+    }
+
+    /// Sets the constraint imposed onto a root node, e.g. the size
+    /// of the window it is displayed in.
+    ///
+    /// Root nodes are fully flexible by default. The constraint is
+    /// deflated by the root's margin like for any other node, see
+    /// [`RectNode::parent_constraint()`]. Only the given root is
+    /// scheduled for relayout, and only if the constraint changed.
+    ///
+    /// Returns `false` if the node does not exist or is not a root.
+    pub fn set_root_constraint(
+        &mut self,
+        root: NodeId,
+        constraint: Constraint,
+    ) -> bool {
+        let Some(node) = self.try_get_mut(&root) else {
+            return false;
+        };
+        if !node.is_root() {
+            return false;
+        }
+
+        let constraint = constraint.deflate(node.margin);
+        if node.parent_constraint != constraint {
+            node.parent_constraint = constraint;
+            self.schedule_relayout(root);
+        }
+
+        true
+    }
+
+    /// Schedules a node and all of its descendants for relayout,
+    /// e.g. when the metrics of every widget within a branch have
+    /// changed.
+    ///
+    /// Unlike [`Self::schedule_relayout()`], every node is rebuilt
+    /// even if the constraint imposed on it remains unchanged.
+    ///
+    /// Returns the number of scheduled nodes, or `0` if the node
+    /// does not exist.
+    pub fn schedule_relayout_subtree(&mut self, id: NodeId) -> usize {
+        if !self.nodes.contains(&id) {
+            return 0;
+        }
+
+        // Ancestors could have memoized results depending on this
+        // branch.
+        self.clear_memos(id);
+
+        let mut count = 0;
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let node = Self::get_node_mut(&mut self.nodes, &id);
+            node.state.reset();
+            node.invalidate();
+            if let Some(memo) = &mut node.memo {
+                memo.clear();
+            }
+            stack.extend(node.children.iter().copied());
+
+            self.scheduled_relayout.insert(DepthNode::new(node, id));
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Schedules every node in the tree for relayout, e.g. when the
+    /// scale factor has changed.
+    ///
+    /// See [`Self::schedule_relayout_subtree()`]. Returns the number
+    /// of scheduled nodes.
+    pub fn schedule_relayout_all(&mut self) -> usize {
+        let root_ids =
+            self.root_ids.iter().copied().collect::<Vec<_>>();
+
+        root_ids
+            .into_iter()
+            .map(|id| self.schedule_relayout_subtree(id))
+            .sum()
+    }
+
+    /// Scales the geometry of every node by a factor, e.g. when the
+    /// scale factor of the window has changed.
+    ///
+    /// Translations, sizes, margins, paddings and constraints
+    /// (including root constraints, see
+    /// [`Self::set_root_constraint()`]) are all multiplied by the
+    /// factor. The previous geometry is scaled as well so that no
+    /// transition is reported. All roots are then scheduled for
+    /// relayout, letting constraint-driven solvers converge on the
+    /// new metrics. Nodes whose constraint remains unchanged after
+    /// scaling keep their scaled size without being rebuilt.
+    ///
+    /// Only nodes whose geometry actually changed are marked for
+    /// repositioning and redraw, or advance the
+    /// [epoch](Self::current_epoch()). A factor of `1.0` leaves the
+    /// tree untouched.
+    pub fn scale_all(&mut self, factor: f64) {
+        if factor == 1.0 {
+            return;
+        }
+
+        let scale_insets = |insets: Insets| {
+            Insets::new(
+                insets.x0 * factor,
+                insets.y0 * factor,
+                insets.x1 * factor,
+                insets.y1 * factor,
+            )
+        };
+
+        let mut stack =
+            self.root_ids.iter().copied().collect::<Vec<_>>();
+        while let Some(id) = stack.pop() {
+            let node = Self::get_node_mut(&mut self.nodes, &id);
+            let old_rect = node.world_rect();
+
+            node.translation *= factor;
+            if node.size * factor != node.size {
+                self.epoch += 1;
+                node.size_epoch = self.epoch;
+            }
+            node.size *= factor;
+            node.built_size *= factor;
+            node.size_override =
+                node.size_override.map(|s| s * factor);
+            node.parent_constraint =
+                node.parent_constraint.scale(factor);
+            node.world_translation *= factor;
+            node.margin = scale_insets(node.margin);
+            node.padding = scale_insets(node.padding);
+            node.prev_size *= factor;
+            node.prev_world_translation *= factor;
+            // Memoized results are no longer in the same units.
+            if let Some(memo) = &mut node.memo {
+                memo.clear();
+            }
+            node.invalidate();
+
+            let new_rect = node.world_rect();
+            stack.extend(node.children.iter().copied());
+            if new_rect != old_rect {
+                node.state.needs_reposition();
+                node.mark_relayout(RelayoutReason::TranslationOnly);
+                self.mark_dirty(old_rect.union(new_rect));
+            }
+        }
+
+        let root_ids =
+            self.root_ids.iter().copied().collect::<Vec<_>>();
+        for id in root_ids {
+            self.schedule_relayout(id);
+        }
+    }
+
+    /// Clears the [`LayoutMemo`] of a node and all of its
+    /// ancestors, since their memoized results could depend on it.
+    /// Their last builds are invalidated for the same reason.
+    pub(crate) fn clear_memos(&mut self, id: NodeId) {
+        let mut current = Some(id);
+
+        while let Some(id) = current {
+            let Some(node) = self.try_get_mut(&id) else {
+                break;
+            };
+
+            if let Some(memo) = &mut node.memo {
+                memo.clear();
+            }
+            node.invalidate();
+            current = node.parent;
+        }
+    }
+
+    /// Takes the [`LayoutChanges`] recorded by the last layout pass.
+    ///
+    /// Changes that are not taken are discarded once the next pass
+    /// starts, so they never accumulate.
+    pub fn take_layout_changes(&mut self) -> LayoutChanges {
+        core::mem::take(&mut self.layout_changes)
+    }
+
+    /// Returns the current epoch, which advances whenever the size
+    /// of a node changes.
+    ///
+    /// Unlike [`Self::take_layout_changes()`], which can only be
+    /// consumed once, any number of observers can each record the
+    /// epoch they last synced at and poll
+    /// [`Self::size_changed_since()`] independently.
+    pub fn current_epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Returns `true` if the size of a node changed after `epoch`,
+    /// see [`Self::current_epoch()`]. Nodes inserted after `epoch`
+    /// count as changed.
+    ///
+    /// Returns `None` if the node does not exist.
+    pub fn size_changed_since(
+        &self,
+        id: &NodeId,
+        epoch: u64,
+    ) -> Option<bool> {
+        self.try_get(id).map(|node| node.size_epoch > epoch)
+    }
+
+    /// Takes the [`Overflow`]s detected by the last layout pass.
+    ///
+    /// Like [`Self::take_layout_changes()`], overflows that are not
+    /// taken are discarded once the next pass starts.
+    pub fn take_overflows(&mut self) -> Vec<Overflow> {
+        core::mem::take(&mut self.overflows)
+    }
+
+    /// Sets how sizes that do not satisfy their constraint are
+    /// enforced during layout, see [`ConstraintPolicy`].
+    pub fn set_constraint_policy(
+        &mut self,
+        policy: ConstraintPolicy,
+    ) {
+        self.constraint_policy = policy;
+    }
+
+    /// See [`Self::set_constraint_policy()`].
+    pub fn constraint_policy(&self) -> ConstraintPolicy {
+        self.constraint_policy
+    }
+
+    /// Default value of [`Self::max_rebuilds()`].
+    pub const DEFAULT_MAX_REBUILDS: u32 = 8;
+
+    /// Sets how many times a node can be built within a single
+    /// layout pass before it is considered non-converging.
+    ///
+    /// Beyond the limit, size changes of the node no longer cause
+    /// its parent or children to be rebuilt, guaranteeing that the
+    /// pass terminates even with solvers that never settle. See
+    /// [`Self::take_non_converged()`].
+    pub fn set_max_rebuilds(&mut self, max_rebuilds: u32) {
+        self.max_rebuilds = Some(max_rebuilds);
+    }
+
+    /// Returns how many times a node can be built within a single
+    /// layout pass, see [`Self::set_max_rebuilds()`].
+    pub fn max_rebuilds(&self) -> u32 {
+        self.max_rebuilds.unwrap_or(Self::DEFAULT_MAX_REBUILDS)
+    }
+
+    /// Default value of [`Self::max_pass_builds()`].
+    pub const DEFAULT_MAX_PASS_BUILDS: u32 = 1 << 20;
+
+    /// Sets how many builds a single layout pass can perform in
+    /// total before it is aborted.
+    ///
+    /// This guards against pathological solvers that keep
+    /// rescheduling each other in ways that
+    /// [`Self::set_max_rebuilds()`] does not catch. Once the limit
+    /// is reached, a [`NonConvergence`] is recorded for the last
+    /// built node and all pending nodes are scheduled for the next
+    /// pass, see [`Self::needs_relayout()`].
+    pub fn set_max_pass_builds(&mut self, max_pass_builds: u32) {
+        self.max_pass_builds = Some(max_pass_builds);
+    }
+
+    /// Returns how many builds a single layout pass can perform, see
+    /// [`Self::set_max_pass_builds()`].
+    pub fn max_pass_builds(&self) -> u32 {
+        self.max_pass_builds
+            .unwrap_or(Self::DEFAULT_MAX_PASS_BUILDS)
+    }
+
+    /// Takes the [`NonConvergence`]s detected by the last layout
+    /// pass.
+    ///
+    /// Like [`Self::take_layout_changes()`], entries that are not
+    /// taken are discarded once the next pass starts.
+    pub fn take_non_converged(&mut self) -> Vec<NonConvergence> {
+        core::mem::take(&mut self.non_converged)
+    }
+
     /// Executes the layout pass using the provided [`LayoutWorld`].
+    ///
+    /// Nodes whose size or world translation changed are recorded
+    /// and can be retrieved via [`Self::take_layout_changes()`].
     pub fn layout<W>(&mut self, world: &W)
     where
         W: LayoutWorld,
     {
-        let scheduled_relayout =
-            core::mem::take(&mut self.scheduled_relayout);
-        let mut child_stack = Vec::<NodeId>::new();
-        let mut build_stack = BTreeSet::<DepthNode>::new();
+        self.layout_budgeted(world, usize::MAX);
+    }
 
-        for DepthNode { id, .. } in scheduled_relayout.iter() {
-            let Some(node) = self.try_get_mut(id) else {
-                continue;
-            };
-            // Check constrain flag, if it has already been
-            // constrained, skip the entire process.
-            if node.state.constrained() {
-                continue;
-            }
+    /// Same as [`Self::layout()`], but invokes the given
+    /// [`LayoutHooks`] at each step of the layout pass.
+    pub fn layout_with_hooks<W>(
+        &mut self,
+        world: &W,
+        hooks: &mut dyn LayoutHooks,
+    ) where
+        W: LayoutWorld,
+    {
+        self.layout_inner(world, usize::MAX, Some(hooks));
+    }
+
+    /// Same as [`Self::layout()`], but solvers are retrieved via
+    /// [`LayoutWorld::get_solver_mut()`] and built via
+    /// [`LayoutSolver::build_mut()`], allowing them to update their
+    /// internal state (e.g. caches) during the layout pass.
+    ///
+    /// Nodes without a mutable solver fall back to
+    /// [`LayoutWorld::get_solver()`].
+    pub fn layout_mut<W>(&mut self, world: &mut W)
+    where
+        W: LayoutWorld,
+    {
+        self.layout_inner(MutSolvers(world), usize::MAX, None);
+    }
+
+    /// Executes the layout pass using the provided [`LayoutWorld`],
+    /// constraining and building at most `max_nodes` nodes.
+    ///
+    /// If the budget runs out, the remaining work is persisted
+    /// inside the tree and the next call continues where this one
+    /// stopped. Nodes that have not been reached yet retain their
+    /// previous geometry, so a partially laid-out tree is always
+    /// safe to render.
+    pub fn layout_budgeted<W>(
+        &mut self,
+        world: &W,
+        max_nodes: usize,
+    ) -> LayoutProgress
+    where
+        W: LayoutWorld,
+    {
+        self.layout_inner(world, max_nodes, None)
+    }
+
+    fn layout_inner<S>(
+        &mut self,
+        mut solvers: S,
+        max_nodes: usize,
+        mut hooks: Option<&mut dyn LayoutHooks>,
+    ) -> LayoutProgress
+    where
+        S: SolverAccess,
+    {
+        // A new layout pass is starting.
+        if self.layout_pass.is_empty() {
+            self.settle_transitions();
+            self.layout_changes = LayoutChanges::default();
+            self.overflows.clear();
+            self.non_converged.clear();
+        }
+
+        let mut pass = core::mem::take(&mut self.layout_pass);
+        // Pick up nodes that were scheduled since the last call.
+        pass.scheduled.append(&mut self.scheduled_relayout);
 
-            child_stack.push(*id);
+        let mut positioner = Positioner::default();
+        let mut budget = max_nodes;
 
+        while budget > 0 {
             // Recursively propagate constraint from parent to child.
-            while let Some(id) = child_stack.pop() {
-                let node = self.get(&id);
-                let solver = world.get_solver(&id);
-                let constraint =
-                    solver.constraint(node.parent_constraint);
+            if let Some(id) = pass.child_stack.pop() {
+                budget -= 1;
+
+                let Some(node) = self.try_get(&id) else {
+                    continue;
+                };
+                if let Some(hooks) = hooks.as_deref_mut() {
+                    hooks.on_constrain(id);
+                }
+                let constraint = Self::children_constraint(
+                    node,
+                    &mut solvers,
+                    &id,
+                );
 
                 self.nodes.scope(&id, |nodes, node| {
                     node.state.has_recontrained();
@@ -64,61 +520,280 @@ impl Rectree {
                         let child_node =
                             Self::get_node_mut(nodes, child);
 
+                        // Make room for the child's margin.
+                        let constraint =
+                            constraint.deflate(child_node.margin);
+
                         // Skip if constraint is still the same.
                         if child_node.parent_constraint != constraint
                         {
                             child_node.parent_constraint = constraint;
-                            child_stack.push(*child);
+                            child_node.mark_relayout(
+                                RelayoutReason::ConstraintChanged,
+                            );
+                            pass.child_stack.push(*child);
                         }
                     }
                 });
 
                 let node = self.get_mut(&id);
+                // Neither the constraint nor the node has changed
+                // since the last build, children whose constraint did
+                // change propagate their new size on their own.
+                if node.is_up_to_date() {
+                    node.state.has_rebuilt();
+                    node.relayout_reason = (!node.state.positioned())
+                        .then_some(RelayoutReason::TranslationOnly);
+                    continue;
+                }
                 node.state.needs_rebuild();
-                build_stack.insert(DepthNode::new(node.depth, id));
+                pass.build_stack.insert(DepthNode::new(node, id));
+                continue;
             }
-        }
 
-        let mut positioner = Positioner::default();
-        let mut translation_stack = scheduled_relayout;
+            if let Some(depth_node) = pass.scheduled.pop_first() {
+                let Some(node) = self.try_get(&depth_node.id) else {
+                    continue;
+                };
+                pass.translation_stack.push(depth_node);
 
-        // Propagate size from child to parent.
-        while let Some(DepthNode { id, .. }) = build_stack.pop_last()
-        {
-            let solver = world.get_solver(&id);
-            let size =
-                solver.build(self.get(&id), self, &mut positioner);
-            positioner.apply(self);
-
-            self.nodes.scope(&id, |nodes, node| {
-                node.state.has_rebuilt();
-                // Parent needs to be rebuilt if size changes.
-                if node.size != size {
-                    if let Some(parent) = node.parent {
-                        let parent_node =
-                            Self::get_node_mut(nodes, &parent);
-                        // Insert only if parent node is not already set to
-                        // be rebuilt.
-                        if parent_node.state.built() {
-                            parent_node.state.needs_reposition();
-                            parent_node.state.needs_rebuild();
-
-                            let depth_node = DepthNode::new(
-                                parent_node.depth,
-                                parent,
-                            );
-                            translation_stack.insert(depth_node);
-                            build_stack.insert(depth_node);
+                // Check constrain flag, if it has already been
+                // constrained, skip the entire process.
+                if !node.state.constrained() {
+                    pass.child_stack.push(depth_node.id);
+                }
+                continue;
+            }
+
+            // Propagate size from child to parent.
+            if let Some(DepthNode { id, .. }) =
+                pass.build_stack.pop_last()
+            {
+                budget -= 1;
+
+                let Some(node) = self.try_get_mut(&id) else {
+                    continue;
+                };
+                // Nothing relevant has changed since the last build,
+                // e.g. the constraint changed and reverted before the
+                // pass could resolve.
+                if node.is_up_to_date() {
+                    node.state.has_rebuilt();
+                    node.relayout_reason = (!node.state.positioned())
+                        .then_some(RelayoutReason::TranslationOnly);
+                    continue;
+                }
+                let prev_built_size = node.built_size;
+                let builds = pass.build_counts.entry(id).or_default();
+                *builds += 1;
+                pass.total_builds += 1;
+                // Stop rescheduling others once the node exceeds its
+                // rebuild limit.
+                let converged = *builds <= self.max_rebuilds();
+                let first_diverged =
+                    *builds == self.max_rebuilds() + 1;
+
+                if let Some(hooks) = hooks.as_deref_mut() {
+                    hooks.on_build_start(id);
+                }
+                // Only sizes resolved during this pass are exposed.
+                let parent_size = self
+                    .get(&id)
+                    .parent
+                    .filter(|parent| {
+                        pass.build_counts.contains_key(parent)
+                    })
+                    .map(|parent| self.get(&parent).size);
+                let (size, memoized) = self.build_node(
+                    &id,
+                    self.get(&id),
+                    &mut solvers,
+                    &mut positioner,
+                    parent_size,
+                );
+
+                #[cfg(feature = "debug-layout")]
+                if !memoized {
+                    self.sanitize_build(
+                        &id,
+                        &mut solvers,
+                        size,
+                        &positioner,
+                        parent_size,
+                    );
+                }
+
+                if !memoized {
+                    let node = self.get_mut(&id);
+                    if let Some(memo) = &mut node.memo {
+                        memo.insert(
+                            node.parent_constraint,
+                            size,
+                            &positioner,
+                        );
+                    }
+                }
+                positioner.apply(self);
+
+                // Rebuild children whose dictated size has changed.
+                self.nodes.scope(&id, |nodes, node| {
+                    if !converged {
+                        return;
+                    }
+                    for child in node.children() {
+                        let child_node =
+                            Self::get_node_mut(nodes, child);
+                        let size_override = positioner
+                            .size_of(child)
+                            .filter(|_| !child_node.is_absolute());
+                        if child_node.size_override == size_override {
+                            continue;
+                        }
+
+                        child_node.size_override = size_override;
+                        child_node.invalidate();
+                        child_node.state.needs_rebuild();
+                        child_node.state.needs_reposition();
+                        child_node.mark_relayout(
+                            RelayoutReason::SizePropagated,
+                        );
+                        let depth_node =
+                            DepthNode::new(child_node, *child);
+                        pass.translation_stack.push(depth_node);
+                        pass.build_stack.insert(depth_node);
+                    }
+                });
+                positioner.new_sizes.clear();
+
+                let constraint_policy = self.constraint_policy;
+                let mut reposition = None;
+                self.nodes.scope(&id, |nodes, node| {
+                    let (built_size, overflow) = Self::outer_size(
+                        id,
+                        node,
+                        size,
+                        constraint_policy,
+                    );
+                    self.overflows.extend(overflow);
+                    // The size dictated by the parent takes
+                    // precedence.
+                    let size =
+                        node.size_override.unwrap_or(built_size);
+                    if let Some(hooks) = hooks.as_deref_mut() {
+                        hooks.on_build_end(id, size);
+                    }
+
+                    node.state.has_rebuilt();
+                    node.built_stamp =
+                        Some((node.version, node.parent_constraint));
+                    node.relayout_reason =
+                        Some(RelayoutReason::TranslationOnly);
+                    // Children could have been repositioned during the
+                    // build, translations must be propagated through.
+                    node.state.needs_reposition();
+                    // Parent needs to be rebuilt if size changes.
+                    if node.built_size != built_size {
+                        if first_diverged {
+                            self.non_converged.push(NonConvergence {
+                                id,
+                                sizes: [node.built_size, built_size],
+                                reason:
+                                    NonConvergenceReason::MaxRebuilds,
+                            });
+                        }
+                        node.built_size = built_size;
+                        if let Some(parent) =
+                            node.parent.filter(|_| converged)
+                        {
+                            let parent_node =
+                                Self::get_node_mut(nodes, &parent);
+                            // The parent's layout does not depend on
+                            // this node's size.
+                            let sized_by_parent = parent_node
+                                .parent_constraint
+                                .is_tight()
+                                && solvers.sized_by_parent(&parent);
+                            if !sized_by_parent {
+                                parent_node.invalidate();
+                            }
+                            // Insert only if parent node is not already set to
+                            // be rebuilt.
+                            if parent_node.state.built()
+                                && !sized_by_parent
+                            {
+                                parent_node.state.needs_reposition();
+                                parent_node.state.needs_rebuild();
+                                parent_node.mark_relayout(
+                                    RelayoutReason::SizePropagated,
+                                );
+
+                                let depth_node = DepthNode::new(
+                                    parent_node,
+                                    parent,
+                                );
+                                pass.translation_stack
+                                    .push(depth_node);
+                                pass.build_stack.insert(depth_node);
+                            } else if sized_by_parent
+                                && parent_node.state.built()
+                            {
+                                reposition = Some(parent);
+                            }
                         }
                     }
-                    node.size = size;
+                    if node.size != size {
+                        self.transitioning.push(id);
+                        self.layout_changes.resized.push(
+                            SizeChange {
+                                id,
+                                old: node.size,
+                                new: size,
+                            },
+                        );
+                        node.size = size;
+                        self.epoch += 1;
+                        node.size_epoch = self.epoch;
+                    }
+                });
+                if let Some(parent) = reposition {
+                    self.reposition_children(
+                        &parent,
+                        &mut solvers,
+                        &mut positioner,
+                        &mut pass,
+                    );
                 }
-            });
+
+                if pass.total_builds >= self.max_pass_builds() {
+                    let built_size = self.get(&id).built_size;
+                    self.non_converged.push(NonConvergence {
+                        id,
+                        sizes: [prev_built_size, built_size],
+                        reason: NonConvergenceReason::MaxPassBuilds,
+                    });
+                    self.abort_pass(&mut pass);
+                    break;
+                }
+                continue;
+            }
+
+            break;
+        }
+
+        if !pass.is_built() {
+            let remaining = pass.remaining();
+            self.layout_pass = pass;
+            return LayoutProgress::Partial { remaining };
         }
 
-        // Propagate translations from parent to child.
-        for DepthNode { id, .. } in translation_stack.into_iter() {
-            let node = self.get(&id);
+        // Propagate translations from parent to child, shallowest
+        // first.
+        pass.sort_translation_stack();
+        for DepthNode { id, .. } in pass.translation_stack.into_iter()
+        {
+            let Some(node) = self.try_get(&id) else {
+                continue;
+            };
 
             // Translation could have already been resolved by a
             // previous iteration.
@@ -126,172 +801,4808 @@ impl Rectree {
                 continue;
             }
 
-            self.propagate_translation(id);
+            self.propagate_translation(id, hooks.as_deref_mut());
         }
-    }
-
-    /// Propagates world-space translations starting from a node.
-    ///
-    /// This updates the node’s world translation and recursively
-    /// applies it to all descendants, clearing translation mutation
-    /// flags in the process.
-    fn propagate_translation(&mut self, id: NodeId) {
-        let mut node_stack = vec![(id, 0)];
-        let mut translation_stack = vec![Vec2::ZERO];
-
-        while let Some((id, index)) = node_stack.pop() {
-            let node = self.get_mut(&id);
 
-            node.world_translation =
-                node.translation + translation_stack[index];
+        // Both the previous and current geometry need a redraw.
+        for i in 0..self.transitioning.len() {
+            let node = self.get(&self.transitioning[i]);
+            let rect =
+                node.prev_world_rect().union(node.world_rect());
+            self.mark_dirty(rect);
+        }
 
-            // This node is now positioned since the world
-            // translation has been updated.
-            node.state.has_repositioned();
+        LayoutProgress::Complete
+    }
 
-            let new_index = translation_stack.len();
-            translation_stack.push(node.world_translation);
+    /// Repositions the children of a node that is
+    /// [sized by its parent](LayoutSolver::sized_by_parent())
+    /// without rebuilding it.
+    fn reposition_children<S>(
+        &mut self,
+        id: &NodeId,
+        solvers: &mut S,
+        positioner: &mut Positioner,
+        pass: &mut LayoutPass,
+    ) where
+        S: SolverAccess,
+    {
+        solvers.position_children(id, self.get(id), self, positioner);
+        // Sizes stay as dictated by the last build.
+        positioner.new_sizes.clear();
+        positioner.apply(self);
 
-            for child in node.children.iter() {
-                node_stack.push((*child, new_index));
+        self.nodes.scope(id, |nodes, node| {
+            for child in node.children() {
+                let child_node = Self::get_node_mut(nodes, child);
+                child_node.state.needs_reposition();
+                pass.translation_stack
+                    .push(DepthNode::new(child_node, *child));
             }
-        }
+        });
     }
-}
 
-/// Provides access to layout solvers associated with nodes.
-///
-/// Acts as the bridge between [`Rectree`] and layout logic, allowing
-/// each node to be resolved by an external [`LayoutSolver`].
-pub trait LayoutWorld {
-    /// Returns the [`LayoutSolver`] responsible for computing layout
-    /// for the given [`NodeId`].
-    fn get_solver(&self, id: &NodeId) -> &dyn LayoutSolver;
-}
+    /// Gives up on the constraining and building left in the pass,
+    /// scheduling the pending nodes for the next pass instead.
+    fn abort_pass(&mut self, pass: &mut LayoutPass) {
+        self.scheduled_relayout.append(&mut pass.scheduled);
 
-/// Defines how a node participates in layout resolution.
-///
-/// A `LayoutSolver` is responsible for:
-/// - Propagating constraints from parent to children (top-down).
-/// - Computing the node’s final size (bottom-up).
-/// - Positioning child nodes relative to the parent.
-pub trait LayoutSolver {
-    /// Computes the constraint to be applied to this node.
-    ///
-    /// By default, the parent’s constraint is forwarded unchanged.
-    /// Implementations may tighten, relax, or otherwise transform the
-    /// constraint before it is used during layout.
-    fn constraint(
-        &self,
-        parent_constraint: Constraint,
-    ) -> Constraint {
-        parent_constraint
+        let pending = pass
+            .child_stack
+            .drain(..)
+            .chain(pass.build_stack.iter().map(|d| d.id))
+            .collect::<Vec<_>>();
+        pass.build_stack.clear();
+
+        for id in pending {
+            self.schedule_relayout(id);
+        }
     }
 
-    /// Builds the layout for a node and returns its resolved size.
+    /// Measures the size of a subtree given the constraint imposed
+    /// by its parent, without committing the layout.
     ///
-    /// This method is called during the layout pass after constraints
-    /// have been propagated.
+    /// Constraint propagation and building are run the same way as
+    /// in [`Self::layout()`], but the resolved constraints and sizes
+    /// only live for the duration of the call. Node geometry, state
+    /// flags, memos, scheduled nodes, as well as recorded changes
+    /// and overflows are left untouched.
     ///
-    /// Implementations may:
-    /// - Inspect the node’s state and children via [`Rectree`].
-    /// - Assign local translations to child nodes via
-    ///   [`Positioner`].
+    /// This requires `&mut self` because solvers read the sizes of
+    /// children through the tree. The affected constraints and sizes
+    /// are copied beforehand and restored before returning, even if
+    /// a solver panics.
     ///
-    /// All translations written through [`Positioner`] are relative
-    /// to the parent node.
-    fn build(
-        &self,
-        node: &RectNode,
-        tree: &Rectree,
-        positioner: &mut Positioner,
-    ) -> Size;
-}
+    /// Returns the size including the margin (see
+    /// [`RectNode::size()`]), or [`Size::ZERO`] if the node does not
+    /// exist.
+    pub fn measure_subtree<W>(
+        &mut self,
+        id: NodeId,
+        world: &W,
+        constraint: Constraint,
+    ) -> Size
+    where
+        W: LayoutWorld,
+    {
+        let mut solvers = world;
+        // Copies of the fields that will be overwritten, in
+        // pre-order so that parents come before their children.
+        let mut saved = Vec::new();
+        let mut stack = vec![id];
 
-/// Collects child translations produced during layout construction.
-///
-/// See [`LayoutSolver::build()`].
-#[derive(Default)]
-pub struct Positioner {
-    new_translations: Vec<(NodeId, Vec2)>,
-}
+        while let Some(id) = stack.pop() {
+            let Some(node) = Self::get_linked_node(&self.nodes, &id)
+            else {
+                continue;
+            };
 
-impl Positioner {
-    /// Sets the local translation for a node.
+            saved.push((
+                id,
+                node.parent_constraint,
+                node.size,
+                node.built_size,
+            ));
+            stack.extend(node.children.iter().copied());
+        }
+
+        if saved.is_empty() {
+            return Size::ZERO;
+        }
+
+        // Restores the saved fields even if a solver panics.
+        let guard = MeasureGuard { tree: self, saved };
+        let tree = &mut *guard.tree;
+        let saved = &guard.saved;
+
+        let node = tree.get_mut(&id);
+        node.parent_constraint = constraint.deflate(node.margin);
+
+        // Propagate constraint from parent to child.
+        for (id, ..) in saved.iter() {
+            let constraint = Self::children_constraint(
+                tree.get(id),
+                &mut solvers,
+                id,
+            );
+
+            tree.nodes.scope(id, |nodes, node| {
+                for child in node.children() {
+                    let child_node = Self::get_node_mut(nodes, child);
+                    child_node.parent_constraint =
+                        constraint.deflate(child_node.margin);
+                }
+            });
+        }
+
+        // Propagate size from child to parent. Translations are
+        // discarded as they do not affect the size.
+        let mut positioner = Positioner::default();
+        for (id, ..) in saved.iter().rev() {
+            let (size, _) = tree.build_node(
+                id,
+                tree.get(id),
+                &mut solvers,
+                &mut positioner,
+                None,
+            );
+            positioner.new_translations.clear();
+            positioner.new_sizes.clear();
+
+            let (size, _) = Self::outer_size(
+                *id,
+                tree.get(id),
+                size,
+                tree.constraint_policy,
+            );
+            let node = tree.get_mut(id);
+            node.size = size;
+            node.built_size = size;
+        }
+
+        tree.get(&id).size
+    }
+
+    /// Measures the size of a node given the constraint imposed by
+    /// its parent, without committing it.
     ///
-    /// The translation is recorded and applied later as part of the
-    /// layout commit phase. If multiple translations are set for the
-    /// same node, the last one wins.
-    pub fn set(&mut self, id: NodeId, translation: Vec2) {
-        self.new_translations.push((id, translation));
+    /// Only the node itself is built, using the current sizes of its
+    /// children, which makes this cheap enough for containers that
+    /// probe children at multiple constraints during
+    /// [`LayoutSolver::build()`]. Use [`Self::measure_subtree()`] to
+    /// also re-measure the descendants.
+    ///
+    /// Returns the size including the margin (see
+    /// [`RectNode::size()`]), or [`Size::ZERO`] if the node does not
+    /// exist.
+    pub fn measure_child<W>(
+        &self,
+        id: NodeId,
+        world: &W,
+        constraint: Constraint,
+    ) -> Size
+    where
+        W: LayoutWorld,
+    {
+        let Some(node) = self.try_get(&id) else {
+            return Size::ZERO;
+        };
+
+        let mut solvers = world;
+        let mut node = node.clone();
+        node.parent_constraint = constraint.deflate(node.margin);
+
+        let (size, _) = self.build_node(
+            &id,
+            &node,
+            &mut solvers,
+            &mut Positioner::default(),
+            None,
+        );
+        let (size, _) =
+            Self::outer_size(id, &node, size, self.constraint_policy);
+        size
     }
 
-    /// Applies all recorded translations to the [`Rectree`].
+    /// Re-invokes [`LayoutSolver::build()`] on a freshly built node
+    /// and panics if the solver is not idempotent, or if it left a
+    /// child without a translation.
+    #[cfg(feature = "debug-layout")]
+    fn sanitize_build<S>(
+        &self,
+        id: &NodeId,
+        solvers: &mut S,
+        size: Size,
+        positioner: &Positioner,
+        parent_size: Option<Size>,
+    ) where
+        S: SolverAccess,
+    {
+        let node = self.get(id);
+        let name = solvers.debug_name(id);
+
+        let mut rerun = Positioner::default();
+        let rerun_size = self.run_solver(
+            id,
+            node,
+            solvers,
+            &mut rerun,
+            parent_size,
+        );
+        assert!(
+            size == rerun_size,
+            "{id} ({name}) is not idempotent, built {size:?} and then \
+            {rerun_size:?} with the same inputs"
+        );
+
+        for child in node.children() {
+            if self.get(child).is_absolute() {
+                continue;
+            }
+            assert!(
+                positioner
+                    .new_translations
+                    .iter()
+                    .any(|(c, _)| c == child),
+                "{id} ({name}) did not position its child {child}"
+            );
+        }
+    }
+
+    /// Invokes [`LayoutSolver::constraint()`] on a node to compute
+    /// the constraint forwarded to its children.
+    fn children_constraint<S>(
+        node: &RectNode,
+        solvers: &mut S,
+        id: &NodeId,
+    ) -> Constraint
+    where
+        S: SolverAccess,
+    {
+        // Children are constrained within the padding.
+        solvers
+            .constraint(id, node.parent_constraint)
+            .deflate(node.padding)
+    }
+
+    /// Invokes [`LayoutSolver::build()`] on a node, or reuses the
+    /// memoized result if there is one.
     ///
-    /// This is called internally after layout resolution to commit
-    /// the results of [`LayoutSolver::build()`].
-    fn apply(&mut self, tree: &mut Rectree) {
-        for (id, translation) in self.new_translations.drain(..) {
-            tree.get_mut(&id).translation = translation;
+    /// The node does not need to be the one stored in the tree,
+    /// allowing it to be built against a different constraint.
+    ///
+    /// Returns the built size, excluding the padding and margin, and
+    /// whether the result was memoized.
+    ///
+    /// See [`BuildCtx::parent_size`].
+    fn build_node<S>(
+        &self,
+        id: &NodeId,
+        node: &RectNode,
+        solvers: &mut S,
+        positioner: &mut Positioner,
+        parent_size: Option<Size>,
+    ) -> (Size, bool)
+    where
+        S: SolverAccess,
+    {
+        let memoized = node
+            .memo
+            .as_ref()
+            .and_then(|memo| memo.get(&node.parent_constraint));
+
+        match memoized {
+            Some(entry) => {
+                positioner
+                    .new_translations
+                    .extend_from_slice(&entry.translations);
+                positioner.new_sizes.extend_from_slice(&entry.sizes);
+                (entry.size, true)
+            }
+            None => (
+                self.run_solver(
+                    id,
+                    node,
+                    solvers,
+                    positioner,
+                    parent_size,
+                ),
+                false,
+            ),
         }
     }
-}
 
-/// [`NodeId`] cache with depth as the primary value for sorting.
-#[derive(
-    Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord,
-)]
-pub struct DepthNode {
-    depth: u32,
-    id: NodeId,
-}
+    /// Invokes [`LayoutSolver::build()`] on a node, followed by up
+    /// to [`Self::MAX_FINALIZE_PASSES`] calls to
+    /// [`LayoutSolver::finalize()`] if the solver requests them.
+    ///
+    /// See [`BuildCtx::parent_size`].
+    fn run_solver<S>(
+        &self,
+        id: &NodeId,
+        node: &RectNode,
+        solvers: &mut S,
+        positioner: &mut Positioner,
+        parent_size: Option<Size>,
+    ) -> Size
+    where
+        S: SolverAccess,
+    {
+        let constraint =
+            solvers.constraint(id, node.parent_constraint);
+        let ctx = BuildCtx {
+            node,
+            tree: self,
+            constraint,
+            // Children are constrained within the padding.
+            children_constraint: constraint.deflate(node.padding),
+            parent_size,
+            previous_size: node.built_stamp.map(|_| node.size()),
+        };
+        let mut size = solvers.build(id, ctx, positioner);
+        if !solvers.needs_finalize(id) {
+            return size;
+        }
 
-impl DepthNode {
-    pub fn new(depth: u32, id: NodeId) -> Self {
-        Self { depth, id }
+        for _ in 0..Self::MAX_FINALIZE_PASSES {
+            match solvers.finalize(id, node, size, self, positioner) {
+                Some(new_size) if new_size != size => size = new_size,
+                _ => break,
+            }
+        }
+        size
+    }
+
+    /// Inflates a built size by the padding and margin of a node,
+    /// handling overflows according to the [`ConstraintPolicy`].
+    ///
+    /// Returns the size reported to the parent, and the
+    /// [`Overflow`] if there is one.
+    fn outer_size(
+        id: NodeId,
+        node: &RectNode,
+        size: Size,
+        constraint_policy: ConstraintPolicy,
+    ) -> (Size, Option<Overflow>) {
+        let constraint = node.parent_constraint;
+        // The padding is part of the node's size.
+        let mut size = size + node.padding.size();
+        let mut overflow = None;
+
+        if constraint.overflows(size) {
+            overflow = Some(Overflow {
+                id,
+                constraint,
+                size,
+            });
+
+            match constraint_policy {
+                ConstraintPolicy::Trust => {}
+                ConstraintPolicy::Clamp => {
+                    size = constraint.clamp(size)
+                }
+                ConstraintPolicy::DebugPanic => debug_assert!(
+                    false,
+                    "{id} overflows its constraint ({constraint:?}) \
+                    with a size of {size:?}."
+                ),
+            }
+        }
+
+        // Report the margin upwards as part of the size.
+        (size + node.margin.size(), overflow)
+    }
+
+    /// Takes the world space region that needs to be redrawn due to
+    /// all layout passes since the last call to this method.
+    ///
+    /// This is the union of the previous and current world space
+    /// [`Rect`] of every node that was resized or repositioned, as
+    /// well as every node that was removed. Returns `None` if
+    /// nothing has changed.
+    pub fn take_dirty_region(&mut self) -> Option<Rect> {
+        self.dirty_region.take()
+    }
+
+    /// Unions a world space [`Rect`] into the dirty region.
+    ///
+    /// See [`Self::take_dirty_region()`].
+    pub(crate) fn mark_dirty(&mut self, rect: Rect) {
+        self.dirty_region = Some(match self.dirty_region {
+            Some(region) => region.union(rect),
+            None => rect,
+        });
+    }
+
+    /// Syncs the previous geometry of all transitioning nodes with
+    /// their current geometry.
+    fn settle_transitions(&mut self) {
+        for id in self.transitioning.drain(..) {
+            if let Some(node) = self.nodes.get_mut(&id) {
+                node.prev_size = node.size;
+                node.prev_world_translation = node.world_translation;
+            }
+        }
+    }
+
+    /// Computes the world-space translations of the subtree of a
+    /// node as if its parent was at `seed` in world space, e.g. to
+    /// preview a drag without reparenting.
+    ///
+    /// The tree is left untouched, the translations are returned in
+    /// depth-first order instead. Absolute nodes stay relative to
+    /// their root.
+    ///
+    /// Returns an empty list if the node does not exist.
+    pub fn propagate_translation_from(
+        &self,
+        id: NodeId,
+        seed: Vec2,
+    ) -> Vec<(NodeId, Vec2)> {
+        let mut translations = Vec::new();
+        if !self.nodes.contains(&id) {
+            return translations;
+        }
+
+        let mut node_stack = vec![(id, seed)];
+        while let Some((id, parent_translation)) = node_stack.pop() {
+            let Some(node) = Self::get_linked_node(&self.nodes, &id)
+            else {
+                continue;
+            };
+
+            let parent_translation = if node.is_absolute() {
+                Self::root_translation(&self.nodes, &id)
+            } else {
+                parent_translation
+            };
+            // Offset by the margin to get to the content origin.
+            let margin_offset =
+                Vec2::new(node.margin.x0, node.margin.y0);
+            let world_translation =
+                node.translation + margin_offset + parent_translation;
+            translations.push((id, world_translation));
+
+            for child in node.children.iter().rev() {
+                node_stack.push((*child, world_translation));
+            }
+        }
+
+        translations
+    }
+
+    /// Propagates world-space translations starting from a node,
+    /// seeded by the world translation of its parent.
+    ///
+    /// See [`Self::propagate_translation_seeded()`].
+    fn propagate_translation(
+        &mut self,
+        id: NodeId,
+        hooks: Option<&mut (dyn LayoutHooks + '_)>,
+    ) -> usize {
+        let seed = self
+            .try_get(&id)
+            .and_then(|node| node.parent)
+            .and_then(|parent| self.try_get(&parent))
+            .map_or(Vec2::ZERO, |parent| parent.world_translation);
+
+        self.propagate_translation_seeded(id, seed, hooks)
+    }
+
+    /// Propagates world-space translations starting from a node,
+    /// with `seed` as the world translation of its parent.
+    ///
+    /// This updates the node’s world translation and recursively
+    /// applies it to all descendants, clearing translation mutation
+    /// flags in the process.
+    ///
+    /// Branches that are already positioned and whose world
+    /// translation remains unchanged are skipped entirely.
+    ///
+    /// Returns the number of nodes visited.
+    fn propagate_translation_seeded(
+        &mut self,
+        id: NodeId,
+        seed: Vec2,
+        mut hooks: Option<&mut (dyn LayoutHooks + '_)>,
+    ) -> usize {
+        let mut node_stack = vec![(id, 0)];
+        let mut translation_stack = vec![seed];
+        let mut visited = 0;
+
+        while let Some((id, index)) = node_stack.pop() {
+            visited += 1;
+
+            let Some(node) = Self::get_linked_node(&self.nodes, &id)
+            else {
+                continue;
+            };
+
+            let parent_translation = if node.is_absolute() {
+                Self::root_translation(&self.nodes, &id)
+            } else {
+                translation_stack[index]
+            };
+            // Offset by the margin to get to the content origin.
+            let margin_offset =
+                Vec2::new(node.margin.x0, node.margin.y0);
+            let world_translation =
+                node.translation + margin_offset + parent_translation;
+            let node = Self::get_node_mut(&mut self.nodes, &id);
+
+            // Nothing has changed for this branch, its descendants
+            // will remain at the same world translation.
+            if node.state.positioned()
+                && node.world_translation == world_translation
+            {
+                continue;
+            }
+
+            if node.world_translation != world_translation {
+                self.transitioning.push(id);
+                self.layout_changes.repositioned.push(
+                    TranslationChange {
+                        id,
+                        old: node.world_translation,
+                        new: world_translation,
+                    },
+                );
+            }
+            node.world_translation = world_translation;
+            if let Some(hooks) = hooks.as_deref_mut() {
+                hooks.on_position(id, world_translation);
+            }
+
+            // This node is now positioned since the world
+            // translation has been updated.
+            node.state.has_repositioned();
+            node.relayout_reason = None;
+
+            let new_index = translation_stack.len();
+            translation_stack.push(node.world_translation);
+
+            for child in node.children.iter() {
+                node_stack.push((*child, new_index));
+            }
+        }
+
+        visited
     }
 }
 
-/// Size constraints applied to a node during layout.
+/// Provides access to layout solvers associated with nodes.
 ///
-/// A value of `Some(f64)` fixes the corresponding dimension to an
-/// explicit size, while `None` indicates that the dimension is
-/// unconstrained (flexible) and may be determined by layout.
-#[derive(Default, Debug, Clone, Copy, PartialEq)]
-pub struct Constraint {
-    // Fixed width constraint, or `None` if flexible.
-    pub width: Option<f64>,
-    // Fixed height constraint, or `None` if flexible.
-    pub height: Option<f64>,
+/// Acts as the bridge between [`Rectree`] and layout logic, allowing
+/// each node to be resolved by an external [`LayoutSolver`].
+pub trait LayoutWorld {
+    /// Returns the [`LayoutSolver`] responsible for computing layout
+    /// for the given [`NodeId`].
+    fn get_solver(&self, id: &NodeId) -> &dyn LayoutSolver;
+
+    /// Returns the [`LayoutSolver`] responsible for computing layout
+    /// for the given [`NodeId`] with mutable access, used by
+    /// [`Rectree::layout_mut()`].
+    ///
+    /// Returns `None` by default, falling back to
+    /// [`Self::get_solver()`].
+    fn get_solver_mut(
+        &mut self,
+        _id: &NodeId,
+    ) -> Option<&mut dyn LayoutSolver> {
+        None
+    }
 }
 
-impl Constraint {
-    /// Create a constraint with both width and height fixed.
-    pub fn fixed(width: f64, height: f64) -> Self {
-        Self {
-            width: Some(width),
-            height: Some(height),
+/// Invokes the [`LayoutSolver`]s of a [`LayoutWorld`] during
+/// layout, abstracting over immutable and mutable access.
+trait SolverAccess {
+    fn constraint(
+        &mut self,
+        id: &NodeId,
+        parent_constraint: Constraint,
+    ) -> Constraint;
+
+    fn sized_by_parent(&mut self, id: &NodeId) -> bool;
+
+    fn needs_finalize(&mut self, id: &NodeId) -> bool;
+
+    #[cfg(feature = "debug-layout")]
+    fn debug_name(&mut self, id: &NodeId) -> &'static str;
+
+    fn build(
+        &mut self,
+        id: &NodeId,
+        ctx: BuildCtx,
+        positioner: &mut Positioner,
+    ) -> Size;
+
+    fn position_children(
+        &mut self,
+        id: &NodeId,
+        node: &RectNode,
+        tree: &Rectree,
+        positioner: &mut Positioner,
+    );
+
+    fn finalize(
+        &mut self,
+        id: &NodeId,
+        node: &RectNode,
+        size: Size,
+        tree: &Rectree,
+        positioner: &mut Positioner,
+    ) -> Option<Size>;
+}
+
+impl<W: LayoutWorld> SolverAccess for &W {
+    fn constraint(
+        &mut self,
+        id: &NodeId,
+        parent_constraint: Constraint,
+    ) -> Constraint {
+        self.get_solver(id).constraint(parent_constraint)
+    }
+
+    fn sized_by_parent(&mut self, id: &NodeId) -> bool {
+        self.get_solver(id).sized_by_parent()
+    }
+
+    fn needs_finalize(&mut self, id: &NodeId) -> bool {
+        self.get_solver(id).needs_finalize()
+    }
+
+    #[cfg(feature = "debug-layout")]
+    fn debug_name(&mut self, id: &NodeId) -> &'static str {
+        self.get_solver(id).debug_name()
+    }
+
+    fn build(
+        &mut self,
+        id: &NodeId,
+        ctx: BuildCtx,
+        positioner: &mut Positioner,
+    ) -> Size {
+        self.get_solver(id).build_with_ctx(ctx, positioner)
+    }
+
+    fn position_children(
+        &mut self,
+        id: &NodeId,
+        node: &RectNode,
+        tree: &Rectree,
+        positioner: &mut Positioner,
+    ) {
+        self.get_solver(id)
+            .position_children(node, tree, positioner)
+    }
+
+    fn finalize(
+        &mut self,
+        id: &NodeId,
+        node: &RectNode,
+        size: Size,
+        tree: &Rectree,
+        positioner: &mut Positioner,
+    ) -> Option<Size> {
+        self.get_solver(id).finalize(node, size, tree, positioner)
+    }
+}
+
+/// Prefers [`LayoutWorld::get_solver_mut()`] over
+/// [`LayoutWorld::get_solver()`].
+struct MutSolvers<'a, W>(&'a mut W);
+
+impl<W: LayoutWorld> SolverAccess for MutSolvers<'_, W> {
+    fn constraint(
+        &mut self,
+        id: &NodeId,
+        parent_constraint: Constraint,
+    ) -> Constraint {
+        match self.0.get_solver_mut(id) {
+            Some(solver) => solver.constraint(parent_constraint),
+            None => {
+                self.0.get_solver(id).constraint(parent_constraint)
+            }
         }
     }
 
-    /// Create a constraint with a fixed width and flexible height.
-    pub fn fixed_width(width: f64) -> Self {
-        Self {
-            width: Some(width),
-            height: None,
+    fn sized_by_parent(&mut self, id: &NodeId) -> bool {
+        match self.0.get_solver_mut(id) {
+            Some(solver) => solver.sized_by_parent(),
+            None => self.0.get_solver(id).sized_by_parent(),
         }
     }
 
-    /// Create a constraint with a fixed height and flexible width.
-    pub fn fixed_height(height: f64) -> Self {
-        Self {
-            width: None,
-            height: Some(height),
+    fn needs_finalize(&mut self, id: &NodeId) -> bool {
+        match self.0.get_solver_mut(id) {
+            Some(solver) => solver.needs_finalize(),
+            None => self.0.get_solver(id).needs_finalize(),
         }
     }
 
-    /// Create a fully flexible constraint with no fixed dimensions.
-    pub fn flexible() -> Self {
-        Self::default()
+    #[cfg(feature = "debug-layout")]
+    fn debug_name(&mut self, id: &NodeId) -> &'static str {
+        self.0.get_solver(id).debug_name()
+    }
+
+    fn build(
+        &mut self,
+        id: &NodeId,
+        ctx: BuildCtx,
+        positioner: &mut Positioner,
+    ) -> Size {
+        match self.0.get_solver_mut(id) {
+            Some(solver) => {
+                solver.build_mut_with_ctx(ctx, positioner)
+            }
+            None => {
+                self.0.get_solver(id).build_with_ctx(ctx, positioner)
+            }
+        }
+    }
+
+    fn position_children(
+        &mut self,
+        id: &NodeId,
+        node: &RectNode,
+        tree: &Rectree,
+        positioner: &mut Positioner,
+    ) {
+        match self.0.get_solver_mut(id) {
+            Some(solver) => {
+                solver.position_children(node, tree, positioner)
+            }
+            None => self
+                .0
+                .get_solver(id)
+                .position_children(node, tree, positioner),
+        }
+    }
+
+    fn finalize(
+        &mut self,
+        id: &NodeId,
+        node: &RectNode,
+        size: Size,
+        tree: &Rectree,
+        positioner: &mut Positioner,
+    ) -> Option<Size> {
+        match self.0.get_solver_mut(id) {
+            Some(solver) => {
+                solver.finalize(node, size, tree, positioner)
+            }
+            None => self
+                .0
+                .get_solver(id)
+                .finalize(node, size, tree, positioner),
+        }
+    }
+}
+
+/// Callbacks invoked at each step of a layout pass, see
+/// [`Rectree::layout_with_hooks()`].
+///
+/// This provides per-node visibility into the layout pass, e.g. for
+/// tracing or profiling solvers. All methods do nothing by default.
+pub trait LayoutHooks {
+    /// Called before the constraint of a node is propagated to its
+    /// children.
+    fn on_constrain(&mut self, _id: NodeId) {}
+
+    /// Called before a node is built.
+    fn on_build_start(&mut self, _id: NodeId) {}
+
+    /// Called after a node is built with its resolved size, see
+    /// [`RectNode::size()`].
+    fn on_build_end(&mut self, _id: NodeId, _size: Size) {}
+
+    /// Called when the world translation of a node is updated, see
+    /// [`RectNode::world_translation()`].
+    fn on_position(&mut self, _id: NodeId, _world_translation: Vec2) {
+    }
+}
+
+/// Defines how a node participates in layout resolution.
+///
+/// A `LayoutSolver` is responsible for:
+/// - Propagating constraints from parent to children (top-down).
+/// - Computing the node’s final size (bottom-up).
+/// - Positioning child nodes relative to the parent.
+pub trait LayoutSolver {
+    /// Computes the constraint to be applied to this node.
+    ///
+    /// By default, the parent’s constraint is forwarded unchanged.
+    /// Implementations may tighten, relax, or otherwise transform the
+    /// constraint before it is used during layout.
+    fn constraint(
+        &self,
+        parent_constraint: Constraint,
+    ) -> Constraint {
+        parent_constraint
+    }
+
+    /// Hints that the layout of the node depends solely on the
+    /// constraint imposed by its parent, not on its children.
+    ///
+    /// When this returns `true` and the constraint is tight, a
+    /// child resizing does not cause the node to be rebuilt. Its
+    /// children are only repositioned via
+    /// [`Self::position_children()`] instead. Defaults to `false`.
+    fn sized_by_parent(&self) -> bool {
+        false
+    }
+
+    /// Repositions the children of a node without resolving its
+    /// size, see [`Self::sized_by_parent()`].
+    ///
+    /// Sizes written through [`Positioner`] are ignored. Defaults to
+    /// calling [`Self::build()`] and discarding the size.
+    fn position_children(
+        &self,
+        node: &RectNode,
+        tree: &Rectree,
+        positioner: &mut Positioner,
+    ) {
+        self.build(node, tree, positioner);
+    }
+
+    /// Requests [`Self::finalize()`] to be called after
+    /// [`Self::build()`]. Defaults to `false`.
+    fn needs_finalize(&self) -> bool {
+        false
+    }
+
+    /// Second positioning pass, called after [`Self::build()`] if
+    /// [`Self::needs_finalize()`] returns `true`.
+    ///
+    /// `size` is the size returned by [`Self::build()`], allowing
+    /// e.g. a wrapping container to place its children once its
+    /// width is known. Translations written through [`Positioner`]
+    /// override the ones written during the build.
+    ///
+    /// Returning a different size than `size` requests another
+    /// pass with the new size, up to
+    /// [`Rectree::MAX_FINALIZE_PASSES`] passes in total. Returning
+    /// `None` keeps the size as is.
+    fn finalize(
+        &self,
+        _node: &RectNode,
+        _size: Size,
+        _tree: &Rectree,
+        _positioner: &mut Positioner,
+    ) -> Option<Size> {
+        None
+    }
+
+    /// Name of the solver used in diagnostics, defaults to its type
+    /// name.
+    fn debug_name(&self) -> &'static str {
+        core::any::type_name::<Self>()
+    }
+
+    /// Builds the layout for a node and returns its resolved size.
+    ///
+    /// This method is called during the layout pass after constraints
+    /// have been propagated.
+    ///
+    /// Implementations may:
+    /// - Inspect the node’s state and children via [`Rectree`].
+    /// - Assign local translations to child nodes via
+    ///   [`Positioner`].
+    ///
+    /// All translations written through [`Positioner`] are relative
+    /// to the parent node's padding, and the returned size should
+    /// exclude the padding. See [`RectNode::padding()`].
+    fn build(
+        &self,
+        node: &RectNode,
+        tree: &Rectree,
+        positioner: &mut Positioner,
+    ) -> Size;
+
+    /// Same as [`Self::build()`], but allows the solver to update
+    /// its internal state, e.g. caches.
+    ///
+    /// This is called by [`Rectree::layout_mut()`] and defaults to
+    /// [`Self::build()`].
+    fn build_mut(
+        &mut self,
+        node: &RectNode,
+        tree: &Rectree,
+        positioner: &mut Positioner,
+    ) -> Size {
+        self.build(node, tree, positioner)
+    }
+
+    /// Same as [`Self::build()`], with additional context about the
+    /// node, see [`BuildCtx`].
+    ///
+    /// This is what [`Rectree::layout()`] calls, and defaults to
+    /// [`Self::build()`].
+    fn build_with_ctx(
+        &self,
+        ctx: BuildCtx,
+        positioner: &mut Positioner,
+    ) -> Size {
+        self.build(ctx.node, ctx.tree, positioner)
+    }
+
+    /// Same as [`Self::build_mut()`], with additional context about
+    /// the node, see [`BuildCtx`].
+    ///
+    /// This is what [`Rectree::layout_mut()`] calls, and defaults to
+    /// [`Self::build_mut()`]. Solvers overriding
+    /// [`Self::build_with_ctx()`] should override this as well if
+    /// their world provides mutable access to them.
+    fn build_mut_with_ctx(
+        &mut self,
+        ctx: BuildCtx,
+        positioner: &mut Positioner,
+    ) -> Size {
+        self.build_mut(ctx.node, ctx.tree, positioner)
+    }
+}
+
+/// Context of a node being built, see
+/// [`LayoutSolver::build_with_ctx()`].
+#[derive(Debug, Clone, Copy)]
+pub struct BuildCtx<'a> {
+    /// The node being built.
+    pub node: &'a RectNode,
+    pub tree: &'a Rectree,
+    /// Constraint the node forwards to its children before the
+    /// padding is applied, as resolved by
+    /// [`LayoutSolver::constraint()`] from
+    /// [`RectNode::parent_constraint()`].
+    ///
+    /// The node itself is constrained by
+    /// [`RectNode::parent_constraint()`].
+    pub constraint: Constraint,
+    /// Constraint forwarded to the children of the node, i.e.
+    /// [`Self::constraint`] deflated by the padding.
+    pub children_constraint: Constraint,
+    /// Size of the parent if it has already been built during the
+    /// current layout pass, or `None` otherwise.
+    ///
+    /// Parents are built after their children, so this is only
+    /// available when the node is rebuilt afterwards, e.g. because
+    /// the parent dictated its size. Always `None` for roots and
+    /// outside of a layout pass, e.g. in
+    /// [`Rectree::measure_subtree()`].
+    pub parent_size: Option<Size>,
+    /// Size of the node from its previous build, or `None` if it
+    /// has not been built yet. See [`RectNode::size()`].
+    pub previous_size: Option<Size>,
+}
+
+impl<'a> BuildCtx<'a> {
+    /// See [`Rectree::layout_children()`].
+    pub fn children(&self) -> LayoutChildren<'a> {
+        self.tree.layout_children(self.node)
+    }
+}
+
+/// The children of a node taking part in its layout, in document
+/// order, see [`RectNode::children()`].
+///
+/// [Absolute](RectNode::is_absolute()) children are excluded, as
+/// they are not positioned by their parent. See
+/// [`Rectree::layout_children()`].
+#[derive(Debug, Clone)]
+pub struct LayoutChildren<'a> {
+    tree: &'a Rectree,
+    ids: Vec<NodeId>,
+}
+
+impl<'a> LayoutChildren<'a> {
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Ids of the children, in document order.
+    pub fn ids(&self) -> &[NodeId] {
+        &self.ids
+    }
+
+    /// Returns the child at the given position.
+    pub fn get(&self, index: usize) -> Option<LayoutChild<'a>> {
+        self.ids.get(index).map(|id| LayoutChild {
+            id: *id,
+            node: self.tree.get(id),
+        })
+    }
+
+    pub fn iter(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = LayoutChild<'a>> + '_ {
+        let tree = self.tree;
+        self.ids.iter().map(move |id| LayoutChild {
+            id: *id,
+            node: tree.get(id),
+        })
+    }
+}
+
+/// A child within [`LayoutChildren`].
+#[derive(Debug, Clone, Copy)]
+pub struct LayoutChild<'a> {
+    pub id: NodeId,
+    pub node: &'a RectNode,
+}
+
+impl LayoutChild<'_> {
+    /// Size of the child including its margin, see
+    /// [`RectNode::outer_size()`].
+    pub fn size(&self) -> Size {
+        self.node.outer_size()
+    }
+
+    /// See [`RectNode::parent_constraint()`].
+    pub fn constraint(&self) -> Constraint {
+        self.node.parent_constraint()
+    }
+
+    /// See [`RectNode::layout_params()`].
+    pub fn params(&self) -> LayoutParams {
+        self.node.layout_params()
+    }
+}
+
+/// Per-child parameters interpreted by the solver of the parent,
+/// e.g. by a flex container.
+///
+/// See [`Rectree::set_layout_params()`].
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct LayoutParams {
+    /// Share of the remaining space along the main axis, where
+    /// `0.0` means that the child does not grow.
+    pub flex: f64,
+    /// Alignment along the cross axis overriding the one of the
+    /// parent, from `0.0` (start) to `1.0` (end).
+    pub align: Option<f64>,
+}
+
+impl LayoutParams {
+    pub fn with_flex(mut self, flex: f64) -> Self {
+        self.flex = flex;
+        self
+    }
+
+    pub fn with_align(mut self, align: f64) -> Self {
+        self.align = Some(align);
+        self
+    }
+}
+
+/// Collects child translations and sizes produced during layout
+/// construction.
+///
+/// See [`LayoutSolver::build()`].
+#[derive(Default)]
+pub struct Positioner {
+    new_translations: Vec<(NodeId, Vec2)>,
+    new_sizes: Vec<(NodeId, Size)>,
+}
+
+impl Positioner {
+    /// Sets the local translation for a node.
+    ///
+    /// The translation is recorded and applied later as part of the
+    /// layout commit phase. If multiple translations are set for the
+    /// same node, the last one wins.
+    pub fn set(&mut self, id: NodeId, translation: Vec2) {
+        self.new_translations.push((id, translation));
+    }
+
+    /// Dictates the size of a child, including its padding and
+    /// margin (see [`RectNode::outer_size()`]).
+    ///
+    /// The size takes precedence over the size built by the child's
+    /// own solver, which remains available through
+    /// [`RectNode::built_size()`] for measuring the child. The
+    /// child is then rebuilt, with the size available through
+    /// [`RectNode::size_override()`] so that it can lay out its own
+    /// children within the new box. The size is kept until a build
+    /// of the parent no longer sets it. If multiple sizes are set
+    /// for the same node, the last one wins.
+    pub fn set_size(&mut self, id: NodeId, size: Size) {
+        self.new_sizes.push((id, size));
+    }
+
+    /// Sets both the local translation and the size of a child, see
+    /// [`Self::set()`] and [`Self::set_size()`].
+    pub fn set_rect(&mut self, id: NodeId, rect: Rect) {
+        self.set(id, rect.origin().to_vec2());
+        self.set_size(id, rect.size());
+    }
+
+    /// Returns the size dictated for a node, if any.
+    fn size_of(&self, id: &NodeId) -> Option<Size> {
+        self.new_sizes
+            .iter()
+            .rev()
+            .find(|(child, _)| child == id)
+            .map(|(_, size)| *size)
+    }
+
+    /// Records all translations of another positioner, offset by
+    /// `offset`.
+    pub(crate) fn extend_offset(
+        &mut self,
+        other: Self,
+        offset: Vec2,
+    ) {
+        self.new_translations.extend(
+            other
+                .new_translations
+                .into_iter()
+                .map(|(id, translation)| (id, translation + offset)),
+        );
+        self.new_sizes.extend(other.new_sizes);
+    }
+
+    /// Applies all recorded translations to the [`Rectree`].
+    ///
+    /// This is called internally after layout resolution to commit
+    /// the results of [`LayoutSolver::build()`].
+    fn apply(&mut self, tree: &mut Rectree) {
+        for (id, translation) in self.new_translations.drain(..) {
+            let node = tree.get(&id);
+            // Absolute nodes opt out of the parent's layout.
+            if node.is_absolute() {
+                continue;
+            }
+
+            // Offset by the parent's padding.
+            let padding = node
+                .parent
+                .map(|parent| tree.get(&parent).padding)
+                .unwrap_or_default();
+            let padding_offset = Vec2::new(padding.x0, padding.y0);
+
+            tree.get_mut(&id).translation =
+                translation + padding_offset;
+        }
+    }
+}
+
+/// Memoized layout results of a node, keyed by the constraint
+/// imposed by its parent.
+///
+/// Only the most recent entries, up to the capacity, are retained.
+/// See [`RectNode::with_memo()`].
+#[derive(Default, Debug, Clone)]
+pub(crate) struct LayoutMemo {
+    capacity: usize,
+    /// Entries ordered from the oldest to the newest.
+    entries: Vec<MemoEntry>,
+}
+
+impl LayoutMemo {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the memoized entry for the given constraint.
+    fn get(&self, constraint: &Constraint) -> Option<&MemoEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.constraint == *constraint)
+    }
+
+    /// Memoizes the result of a [`LayoutSolver::build()`], evicting
+    /// the oldest entry if the capacity is reached.
+    fn insert(
+        &mut self,
+        constraint: Constraint,
+        size: Size,
+        positioner: &Positioner,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.entries.retain(|entry| entry.constraint != constraint);
+        if self.entries.len() >= self.capacity {
+            self.entries.remove(0);
+        }
+
+        self.entries.push(MemoEntry {
+            constraint,
+            size,
+            translations: positioner.new_translations.clone(),
+            sizes: positioner.new_sizes.clone(),
+        });
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// The result of a [`LayoutSolver::build()`] for a given
+/// constraint.
+#[derive(Debug, Clone)]
+struct MemoEntry {
+    constraint: Constraint,
+    size: Size,
+    /// Child translations set via the [`Positioner`].
+    translations: Vec<(NodeId, Vec2)>,
+    /// Child sizes set via the [`Positioner`].
+    sizes: Vec<(NodeId, Size)>,
+}
+
+/// Why a node has pending layout work.
+///
+/// See [`Rectree::relayout_reason()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RelayoutReason {
+    /// The node was explicitly scheduled, e.g. via
+    /// [`Rectree::schedule_relayout()`] or by being inserted.
+    Scheduled,
+    /// The constraint imposed by the parent has changed.
+    ConstraintChanged,
+    /// The size of a child has changed.
+    SizePropagated,
+    /// The node has been built, only its world translation is
+    /// pending.
+    TranslationOnly,
+}
+
+/// Progress of a [`Rectree::layout_budgeted()`] call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutProgress {
+    /// The layout pass has been fully resolved.
+    Complete,
+    /// The budget ran out before the layout pass could be resolved.
+    Partial {
+        /// Number of nodes that are known to be pending. More nodes
+        /// could be discovered as the layout pass continues.
+        remaining: usize,
+    },
+}
+
+impl LayoutProgress {
+    /// Returns `true` if the layout pass has been fully resolved.
+    pub fn is_complete(&self) -> bool {
+        matches!(self, Self::Complete)
+    }
+}
+
+/// Restores the fields overwritten by
+/// [`Rectree::measure_subtree()`] once dropped.
+struct MeasureGuard<'a> {
+    tree: &'a mut Rectree,
+    /// Original parent constraint, size and built size of each
+    /// measured node.
+    saved: Vec<(NodeId, Constraint, Size, Size)>,
+}
+
+impl Drop for MeasureGuard<'_> {
+    fn drop(&mut self) {
+        for (id, constraint, size, built_size) in self.saved.drain(..)
+        {
+            let node = self.tree.get_mut(&id);
+            node.parent_constraint = constraint;
+            node.size = size;
+            node.built_size = built_size;
+        }
+    }
+}
+
+/// Persistent state of an in-progress layout pass.
+///
+/// See [`Rectree::layout_budgeted()`].
+#[derive(Default, Debug)]
+pub(crate) struct LayoutPass {
+    /// Scheduled nodes that have not been constrained yet.
+    scheduled: BTreeSet<DepthNode>,
+    /// Nodes pending for constraint propagation.
+    child_stack: Vec<NodeId>,
+    /// Nodes pending to be built, deepest first.
+    build_stack: BTreeSet<DepthNode>,
+    /// Nodes to propagate translations from once all nodes are
+    /// built.
+    ///
+    /// This is only iterated once at the end of the pass, so it is
+    /// kept unordered and only sorted and deduplicated via
+    /// [`Self::sort_translation_stack()`], which is cheaper than
+    /// maintaining a [`BTreeSet`].
+    translation_stack: Vec<DepthNode>,
+    /// Number of times each node has been built during the pass.
+    ///
+    /// See [`Rectree::set_max_rebuilds()`].
+    build_counts: HashMap<NodeId, u32>,
+    /// Number of builds performed during the pass.
+    ///
+    /// See [`Rectree::set_max_pass_builds()`].
+    total_builds: u32,
+}
+
+impl LayoutPass {
+    /// Returns `true` if there are no more nodes to constrain or
+    /// build.
+    fn is_built(&self) -> bool {
+        self.scheduled.is_empty()
+            && self.child_stack.is_empty()
+            && self.build_stack.is_empty()
+    }
+
+    /// Sorts and deduplicates [`Self::translation_stack`].
+    fn sort_translation_stack(&mut self) {
+        self.translation_stack.sort_unstable();
+        self.translation_stack.dedup();
+    }
+
+    /// Returns `true` if there is no pass in progress.
+    fn is_empty(&self) -> bool {
+        self.is_built() && self.translation_stack.is_empty()
+    }
+
+    /// Number of nodes that are known to be pending.
+    fn remaining(&self) -> usize {
+        self.scheduled.len()
+            + self.child_stack.len()
+            + self.build_stack.len()
+    }
+}
+
+/// Geometry changes recorded during layout passes.
+///
+/// See [`Rectree::take_layout_changes()`].
+#[derive(Default, Debug, Clone)]
+pub struct LayoutChanges {
+    /// Nodes whose [`RectNode::size()`] has changed.
+    pub resized: Vec<SizeChange>,
+    /// Nodes whose [`RectNode::world_translation()`] has changed.
+    pub repositioned: Vec<TranslationChange>,
+}
+
+impl LayoutChanges {
+    /// Returns `true` if no changes were recorded.
+    pub fn is_empty(&self) -> bool {
+        self.resized.is_empty() && self.repositioned.is_empty()
+    }
+}
+
+/// A node whose built size exceeds the constraint imposed by its
+/// parent.
+///
+/// See [`Rectree::take_overflows()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Overflow {
+    pub id: NodeId,
+    /// Constraint imposed by the parent.
+    pub constraint: Constraint,
+    /// Size returned by [`LayoutSolver::build()`].
+    pub size: Size,
+}
+
+/// A node that prevented a layout pass from converging.
+///
+/// See [`Rectree::take_non_converged()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NonConvergence {
+    pub id: NodeId,
+    /// The last two built sizes, from the oldest to the newest. See
+    /// [`RectNode::built_size()`].
+    pub sizes: [Size; 2],
+    /// The limit that was exceeded.
+    pub reason: NonConvergenceReason,
+}
+
+/// The limit exceeded by a [`NonConvergence`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonConvergenceReason {
+    /// The node kept changing its size after being built
+    /// [`Rectree::max_rebuilds()`] times within a single layout
+    /// pass.
+    MaxRebuilds,
+    /// The pass performed [`Rectree::max_pass_builds()`] builds in
+    /// total, the node being the last one built before the pass was
+    /// aborted.
+    MaxPassBuilds,
+}
+
+/// Determines how sizes returned by solvers that do not satisfy
+/// their constraint are enforced during layout.
+///
+/// Every policy reports the resulting [`Overflow`].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstraintPolicy {
+    /// Trust the solver, the node keeps its built size.
+    #[default]
+    Trust,
+    /// Report the [`Overflow`] and clamp the node's size to the
+    /// constraint.
+    ///
+    /// The size is clamped before it is compared against the
+    /// previous size, so a node that keeps overflowing to the same
+    /// clamped size does not cause its parent to be rebuilt.
+    Clamp,
+    /// Panic in debug builds, naming the overflowing node. Behaves
+    /// like [`Self::Trust`] in release builds.
+    DebugPanic,
+}
+
+/// A change in [`RectNode::size()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SizeChange {
+    pub id: NodeId,
+    pub old: Size,
+    pub new: Size,
+}
+
+/// A change in [`RectNode::world_translation()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TranslationChange {
+    pub id: NodeId,
+    pub old: Vec2,
+    pub new: Vec2,
+}
+
+/// [`NodeId`] cache with depth as the primary value for sorting.
+///
+/// Nodes of equal depth are ordered by their insertion order, which
+/// unlike [`NodeId`]s does not depend on the allocation history of
+/// the tree.
+#[derive(
+    Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub struct DepthNode {
+    depth: u32,
+    insertion_order: u64,
+    id: NodeId,
+}
+
+impl DepthNode {
+    pub fn new(node: &RectNode, id: NodeId) -> Self {
+        Self {
+            depth: node.depth,
+            insertion_order: node.insertion_order,
+            id,
+        }
+    }
+}
+
+/// Size constraints applied to a node during layout.
+///
+/// A value of `Some(f64)` bounds the corresponding dimension, while
+/// `None` indicates that the dimension is unconstrained (flexible)
+/// and may be determined by layout.
+///
+/// A bound is either tight, fixing the dimension to an exact size,
+/// or loose, allowing any size up to the bound. Bounds are tight by
+/// default.
+///
+/// A bound of [`f64::INFINITY`] explicitly marks the dimension as
+/// unbounded, see [`Self::unbounded()`]. Infinite and NaN bounds
+/// are treated like flexible ones when resolving sizes.
+///
+/// With the `serde` feature, constraints can be serialized. They can
+/// also be formatted and parsed in a compact form like `20×flex`,
+/// see [`Self::parse()`].
+#[derive(Default, Debug, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct Constraint {
+    // Width constraint, or `None` if flexible.
+    pub width: Option<f64>,
+    // Height constraint, or `None` if flexible.
+    pub height: Option<f64>,
+    /// Whether [`Self::width`] is a maximum rather than an exact
+    /// size.
+    pub loose_width: bool,
+    /// Whether [`Self::height`] is a maximum rather than an exact
+    /// size.
+    pub loose_height: bool,
+}
+
+/// Builders.
+impl Constraint {
+    /// Create a constraint with both width and height fixed.
+    pub fn fixed(width: f64, height: f64) -> Self {
+        Self {
+            width: Some(width),
+            height: Some(height),
+            ..Default::default()
+        }
+    }
+
+    /// Create a constraint with a fixed width and flexible height.
+    pub fn fixed_width(width: f64) -> Self {
+        Self {
+            width: Some(width),
+            ..Default::default()
+        }
+    }
+
+    /// Create a constraint with a fixed height and flexible width.
+    pub fn fixed_height(height: f64) -> Self {
+        Self {
+            height: Some(height),
+            ..Default::default()
+        }
+    }
+
+    /// Create a fully flexible constraint with no fixed dimensions.
+    pub fn flexible() -> Self {
+        Self::default()
+    }
+
+    /// Create a constraint with both width and height explicitly
+    /// unbounded, i.e. loosely bounded by [`f64::INFINITY`].
+    ///
+    /// This behaves like [`Self::flexible()`] during layout, while
+    /// distinguishing a resolved lack of bounds from a missing one.
+    pub fn unbounded() -> Self {
+        Self::loose(f64::INFINITY, f64::INFINITY)
+    }
+
+    /// Create a constraint with both width and height bounded by a
+    /// maximum.
+    pub fn loose(max_width: f64, max_height: f64) -> Self {
+        Self::fixed(max_width, max_height).loosen()
+    }
+
+    /// Create a constraint with a maximum width and flexible height.
+    pub fn loose_width(max_width: f64) -> Self {
+        Self::fixed_width(max_width).loosen()
+    }
+
+    /// Create a constraint with a maximum height and flexible width.
+    pub fn loose_height(max_height: f64) -> Self {
+        Self::fixed_height(max_height).loosen()
+    }
+
+    /// Turn all bounds into maximums, keeping their values.
+    pub fn loosen(mut self) -> Self {
+        self.loose_width = true;
+        self.loose_height = true;
+        self
+    }
+
+    /// Turn all bounds into exact sizes, keeping their values.
+    pub fn tighten(mut self) -> Self {
+        self.loose_width = false;
+        self.loose_height = false;
+        self
+    }
+
+    /// Fix the width to an exact size, keeping the height.
+    pub fn with_fixed_width(mut self, width: f64) -> Self {
+        self.width = Some(width);
+        self.loose_width = false;
+        self
+    }
+
+    /// Fix the height to an exact size, keeping the width.
+    pub fn with_fixed_height(mut self, height: f64) -> Self {
+        self.height = Some(height);
+        self.loose_height = false;
+        self
+    }
+}
+
+/// Resolution.
+impl Constraint {
+    /// Returns `true` if the width is bounded by a finite value.
+    pub fn has_bounded_width(&self) -> bool {
+        self.width.is_some_and(f64::is_finite)
+    }
+
+    /// Returns `true` if the height is bounded by a finite value.
+    pub fn has_bounded_height(&self) -> bool {
+        self.height.is_some_and(f64::is_finite)
+    }
+
+    /// Returns `true` if both the width and height are bounded by
+    /// finite values.
+    pub fn is_bounded(&self) -> bool {
+        self.has_bounded_width() && self.has_bounded_height()
+    }
+
+    /// Returns `true` if [`Self::width`] is a maximum rather than
+    /// an exact size.
+    pub fn is_loose_width(&self) -> bool {
+        self.loose_width
+    }
+
+    /// Returns `true` if [`Self::height`] is a maximum rather than
+    /// an exact size.
+    pub fn is_loose_height(&self) -> bool {
+        self.loose_height
+    }
+
+    /// Returns `true` if the width is fixed to an exact size.
+    pub fn is_tight_width(&self) -> bool {
+        self.has_bounded_width() && !self.loose_width
+    }
+
+    /// Returns `true` if the height is fixed to an exact size.
+    pub fn is_tight_height(&self) -> bool {
+        self.has_bounded_height() && !self.loose_height
+    }
+
+    /// Returns `true` if both the width and height are fixed to an
+    /// exact size.
+    pub fn is_tight(&self) -> bool {
+        self.is_tight_width() && self.is_tight_height()
+    }
+
+    /// Resolves a preferred width against the width constraint.
+    ///
+    /// A tight bound overrides the preferred width, a loose bound
+    /// caps it, and no bound leaves it untouched.
+    pub fn resolve_width(&self, preferred: f64) -> f64 {
+        resolve_axis(self.width, self.loose_width, preferred)
+    }
+
+    /// Resolves a preferred height against the height constraint.
+    ///
+    /// See [`Self::resolve_width()`].
+    pub fn resolve_height(&self, preferred: f64) -> f64 {
+        resolve_axis(self.height, self.loose_height, preferred)
+    }
+
+    /// Resolves a preferred [`Size`] against the constraint.
+    ///
+    /// See [`Self::resolve_width()`].
+    pub fn resolve(&self, preferred: Size) -> Size {
+        Size::new(
+            self.resolve_width(preferred.width),
+            self.resolve_height(preferred.height),
+        )
+    }
+
+    /// Returns the largest size allowed by the constraint, with
+    /// unbounded dimensions clamped to `max`.
+    pub fn biggest(&self, max: Size) -> Size {
+        Size::new(
+            bounded(self.width).unwrap_or(max.width),
+            bounded(self.height).unwrap_or(max.height),
+        )
+    }
+
+    /// Returns the smallest size allowed by the constraint, which
+    /// is zero except along tight dimensions.
+    pub fn smallest(&self) -> Size {
+        let axis = |bound: Option<f64>, tight: bool| match tight {
+            true => bounded(bound).unwrap_or(0.0),
+            false => 0.0,
+        };
+        Size::new(
+            axis(self.width, self.is_tight_width()),
+            axis(self.height, self.is_tight_height()),
+        )
+    }
+}
+
+/// Bounds are compared by value, except that NaN bounds are equal
+/// to each other, so that a NaN constraint is not perpetually
+/// considered changed during layout.
+impl PartialEq for Constraint {
+    fn eq(&self, other: &Self) -> bool {
+        let axis_eq = |a: Option<f64>, b: Option<f64>| match (a, b) {
+            (Some(a), Some(b)) => {
+                a == b || (a.is_nan() && b.is_nan())
+            }
+            (a, b) => a.is_none() && b.is_none(),
+        };
+
+        axis_eq(self.width, other.width)
+            && axis_eq(self.height, other.height)
+            && self.loose_width == other.loose_width
+            && self.loose_height == other.loose_height
+    }
+}
+
+/// Transformations.
+impl Constraint {
+    /// Multiplies the bounds by the given factor. The tightness of
+    /// each bound is preserved.
+    pub fn scale(mut self, factor: f64) -> Self {
+        self.width = self.width.map(|w| w * factor);
+        self.height = self.height.map(|h| h * factor);
+        self
+    }
+
+    /// Combines the demands of two constraints, e.g. a tooltip
+    /// bounded by both its anchor and the screen, taking the tighter
+    /// bound along each axis.
+    ///
+    /// A bound wins over a flexible axis, and the result is only
+    /// flexible if both axes are, in which case it is not loose. Of
+    /// two bounds, the smaller one wins, while an exact size wins
+    /// over a maximum of the same value.
+    pub fn intersect(self, other: Self) -> Self {
+        let axis = |a: (Option<f64>, bool),
+                    b: (Option<f64>, bool)| {
+            match (a, b) {
+                ((None, _), (None, _)) => (None, false),
+                ((None, _), bound) | (bound, (None, _)) => bound,
+                ((Some(x), x_loose), (Some(y), y_loose)) => {
+                    if x < y {
+                        a
+                    } else if y < x {
+                        b
+                    } else {
+                        (Some(x.min(y)), x_loose && y_loose)
+                    }
+                }
+            }
+        };
+
+        let (width, loose_width) = axis(
+            (self.width, self.loose_width),
+            (other.width, other.loose_width),
+        );
+        let (height, loose_height) = axis(
+            (self.height, self.loose_height),
+            (other.height, other.loose_height),
+        );
+        Self {
+            width,
+            height,
+            loose_width,
+            loose_height,
+        }
+    }
+
+    /// Shrinks the bounds by the given [`Insets`], clamping them to
+    /// zero. The tightness of each bound is preserved.
+    pub fn deflate(mut self, insets: Insets) -> Self {
+        self.width =
+            self.width.map(|w| (w - insets.x_value()).max(0.0));
+        self.height =
+            self.height.map(|h| (h - insets.y_value()).max(0.0));
+        self
+    }
+}
+
+/// Overflow detection.
+impl Constraint {
+    /// Tolerance used for detecting overflows.
+    pub const OVERFLOW_EPSILON: f64 = 1e-6;
+
+    /// Returns `true` if the size exceeds any bound of the
+    /// constraint by more than [`Self::OVERFLOW_EPSILON`].
+    pub fn overflows(&self, size: Size) -> bool {
+        let exceeds = |bound: Option<f64>, value: f64| {
+            bound.is_some_and(|bound| {
+                value - bound > Self::OVERFLOW_EPSILON
+            })
+        };
+
+        exceeds(self.width, size.width)
+            || exceeds(self.height, size.height)
+    }
+
+    /// Clamps the size to stay within the bounds of the
+    /// constraint.
+    pub fn clamp(&self, size: Size) -> Size {
+        Size::new(
+            self.width.map_or(size.width, |w| size.width.min(w)),
+            self.height.map_or(size.height, |h| size.height.min(h)),
+        )
+    }
+}
+
+/// Parsing.
+impl Constraint {
+    /// Parses a constraint formatted as `{width}x{height}`, where
+    /// each axis is either `flex`, a tight bound like `20`, or a
+    /// loose bound like `<=20`. The `×` and `≤` symbols written by
+    /// the [`Display`] implementation are accepted as well.
+    ///
+    /// Bounds must be finite, except for loose bounds of `inf` or
+    /// `∞`, as written for [`Self::unbounded()`].
+    pub fn parse(s: &str) -> Result<Self, ParseConstraintError> {
+        let s = s.trim();
+        // Skip a leading `flex`, which contains the separator.
+        let start = if s.starts_with(FLEX) { FLEX.len() } else { 0 };
+        let (width, height) = s[start..]
+            .find(['x', '×'])
+            .map(|i| {
+                let (width, rest) = s.split_at(start + i);
+                let separator = rest.chars().next().unwrap_or('x');
+                (width, &rest[separator.len_utf8()..])
+            })
+            .ok_or(ParseConstraintError::MissingSeparator)?;
+
+        let (width, loose_width) = parse_axis(width)?;
+        let (height, loose_height) = parse_axis(height)?;
+        Ok(Self {
+            width,
+            height,
+            loose_width,
+            loose_height,
+        })
+    }
+}
+
+/// Formats the constraint as `{width}×{height}`, e.g. `20×flex` or
+/// `≤20×10`. See [`Constraint::parse()`].
+impl Display for Constraint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let axis = |f: &mut Formatter<'_>, bound, loose| match bound {
+            Some(bound) if loose => write!(f, "≤{bound}"),
+            Some(bound) => write!(f, "{bound}"),
+            None => f.write_str(FLEX),
+        };
+
+        axis(f, self.width, self.loose_width)?;
+        f.write_str("×")?;
+        axis(f, self.height, self.loose_height)
+    }
+}
+
+impl FromStr for Constraint {
+    type Err = ParseConstraintError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+const FLEX: &str = "flex";
+
+/// Parses a single axis of a [`Constraint`], returning the bound
+/// and whether it is loose.
+fn parse_axis(
+    s: &str,
+) -> Result<(Option<f64>, bool), ParseConstraintError> {
+    let s = s.trim();
+    if s == FLEX {
+        return Ok((None, false));
+    }
+
+    let (s, loose) =
+        match s.strip_prefix('≤').or_else(|| s.strip_prefix("<=")) {
+            Some(s) => (s.trim_start(), true),
+            None => (s, false),
+        };
+    let bound = match s {
+        "∞" => f64::INFINITY,
+        _ => s.parse::<f64>().map_err(|_| {
+            ParseConstraintError::InvalidAxis(s.to_string())
+        })?,
+    };
+    // Only a loose bound can be explicitly unbounded.
+    let unbounded = loose && bound == f64::INFINITY;
+    if !bound.is_finite() && !unbounded {
+        return Err(ParseConstraintError::NonFinite(bound));
+    }
+    Ok((Some(bound), loose))
+}
+
+/// Error returned by [`Constraint::parse()`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseConstraintError {
+    /// The width and height are not separated by `x` or `×`.
+    MissingSeparator,
+    /// An axis is neither `flex` nor a number.
+    InvalidAxis(String),
+    /// An axis is NaN, negative infinity, or a tight infinite
+    /// bound.
+    NonFinite(f64),
+}
+
+impl Display for ParseConstraintError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::MissingSeparator => f.write_str(
+                "Missing `x` separator between width and height.",
+            ),
+            Self::InvalidAxis(axis) => f.write_fmt(format_args!(
+                "Invalid axis `{axis}`, expected `flex` or a number."
+            )),
+            Self::NonFinite(bound) => f.write_fmt(format_args!(
+                "Bounds must be finite, got {bound}."
+            )),
+        }
+    }
+}
+
+impl core::error::Error for ParseConstraintError {}
+
+/// A length along a single axis, relative to the parent's bound if
+/// needed.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum ConstraintValue {
+    /// An absolute length.
+    Fixed(f64),
+    /// A percentage of the parent's bound, e.g. `50.0` for half of
+    /// it.
+    Percent(f64),
+    /// No particular length.
+    #[default]
+    Flexible,
+}
+
+impl ConstraintValue {
+    /// Resolves the value against the parent's bound along the same
+    /// axis, see [`Constraint::width`] and [`Constraint::height`].
+    ///
+    /// Returns `None` for [`Self::Flexible`], or for
+    /// [`Self::Percent`] if the parent is unbounded.
+    pub fn resolve(&self, parent: Option<f64>) -> Option<f64> {
+        match *self {
+            ConstraintValue::Fixed(value) => Some(value),
+            ConstraintValue::Percent(percent) => {
+                bounded(parent).map(|parent| parent * percent / 100.0)
+            }
+            ConstraintValue::Flexible => None,
+        }
+    }
+}
+
+/// Filters out infinite and NaN bounds.
+fn bounded(bound: Option<f64>) -> Option<f64> {
+    bound.filter(|bound| bound.is_finite())
+}
+
+fn resolve_axis(
+    bound: Option<f64>,
+    loose: bool,
+    preferred: f64,
+) -> f64 {
+    match bounded(bound) {
+        Some(bound) if loose => preferred.min(bound),
+        Some(bound) => bound,
+        None => preferred,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+    use alloc::vec::Vec;
+    use core::cell::{Cell, RefCell};
+    use hashbrown::HashMap;
+
+    use super::*;
+    use crate::node::PositionMode;
+
+    /// Number of solver runs per build, the sanitizer reruns every
+    /// build.
+    const RUNS: usize =
+        if cfg!(feature = "debug-layout") { 2 } else { 1 };
+
+    enum TestSolver {
+        /// Fixed size regardless of children.
+        Fixed(Size),
+        /// Same as [`TestSolver::Fixed`], but also constrain its
+        /// children to the size.
+        Constrained(Size),
+        /// Stacks children vertically, ordered by [`NodeId`].
+        Stack,
+        /// Fills the constraint and centers all children.
+        Center,
+        /// Fills the constraint and stacks children, regardless of
+        /// their sizes. See [`LayoutSolver::sized_by_parent()`].
+        Fill,
+    }
+
+    impl LayoutSolver for TestSolver {
+        fn build(
+            &self,
+            node: &RectNode,
+            tree: &Rectree,
+            positioner: &mut Positioner,
+        ) -> Size {
+            if let TestSolver::Center = self {
+                let size =
+                    node.parent_constraint().biggest(Size::ZERO);
+
+                for id in node.children() {
+                    let offset =
+                        (size - tree.get(id).outer_size()) * 0.5;
+                    positioner.set(*id, offset.to_vec2());
+                }
+                return size;
+            }
+
+            let mut size = Size::ZERO;
+            for child in tree.layout_children(node).iter() {
+                positioner.set(child.id, Vec2::new(0.0, size.height));
+                let child_size = child.size();
+                size.width = size.width.max(child_size.width);
+                size.height += child_size.height;
+            }
+
+            match self {
+                TestSolver::Fixed(size)
+                | TestSolver::Constrained(size) => *size,
+                TestSolver::Fill => {
+                    node.parent_constraint().biggest(Size::ZERO)
+                }
+                TestSolver::Stack | TestSolver::Center => size,
+            }
+        }
+
+        fn sized_by_parent(&self) -> bool {
+            matches!(self, TestSolver::Fill)
+        }
+
+        fn constraint(
+            &self,
+            parent_constraint: Constraint,
+        ) -> Constraint {
+            match self {
+                TestSolver::Constrained(size) => {
+                    Constraint::fixed(size.width, size.height)
+                }
+                _ => parent_constraint,
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct TestWorld {
+        solvers: HashMap<NodeId, TestSolver>,
+    }
+
+    impl TestWorld {
+        fn insert(
+            &mut self,
+            tree: &mut Rectree,
+            parent: Option<NodeId>,
+            solver: TestSolver,
+        ) -> NodeId {
+            let mut node = RectNode::new();
+            if let Some(parent) = parent {
+                node = node.with_parent(parent);
+            }
+            self.insert_node(tree, node, solver)
+        }
+
+        fn insert_node(
+            &mut self,
+            tree: &mut Rectree,
+            node: RectNode,
+            solver: TestSolver,
+        ) -> NodeId {
+            let id = tree.insert(node);
+            self.solvers.insert(id, solver);
+            id
+        }
+    }
+
+    impl LayoutWorld for TestWorld {
+        fn get_solver(&self, id: &NodeId) -> &dyn LayoutSolver {
+            &self.solvers[id]
+        }
+    }
+
+    #[test]
+    fn test_layout_changes() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let root = world.insert(
+            &mut tree,
+            None,
+            Fixed(Size::new(100.0, 100.0)),
+        );
+        let column_a = world.insert(&mut tree, Some(root), Stack);
+        let column_b = world.insert(&mut tree, Some(root), Stack);
+
+        let mut insert_leaf = |parent| {
+            world.insert(
+                &mut tree,
+                Some(parent),
+                Fixed(Size::new(10.0, 10.0)),
+            )
+        };
+        let a0 = insert_leaf(column_a);
+        let a1 = insert_leaf(column_a);
+        let a2 = insert_leaf(column_a);
+        let b0 = insert_leaf(column_b);
+        let b1 = insert_leaf(column_b);
+
+        tree.layout(&world);
+        assert!(!tree.take_layout_changes().is_empty());
+
+        // Grow the middle leaf.
+        world.solvers.insert(a1, Fixed(Size::new(10.0, 30.0)));
+        tree.schedule_relayout(a1);
+        tree.layout(&world);
+
+        let changes = tree.take_layout_changes();
+
+        // The root has a fixed size, resizing stops there.
+        let mut resized =
+            changes.resized.iter().map(|c| c.id).collect::<Vec<_>>();
+        resized.sort();
+        let mut expected = vec![a1, column_a];
+        expected.sort();
+        assert_eq!(resized, expected);
+
+        // The sibling below the grown leaf and the column below are
+        // pushed down.
+        let mut repositioned = changes
+            .repositioned
+            .iter()
+            .map(|c| c.id)
+            .collect::<Vec<_>>();
+        repositioned.sort();
+        let mut expected = vec![a2, column_b, b0, b1];
+        expected.sort();
+        assert_eq!(repositioned, expected);
+
+        let a2_change =
+            changes.repositioned.iter().find(|c| c.id == a2).unwrap();
+        assert_eq!(a2_change.old, Vec2::new(0.0, 20.0));
+        assert_eq!(a2_change.new, Vec2::new(0.0, 40.0));
+        assert_eq!(tree.get(&a0).world_translation(), Vec2::ZERO);
+
+        // Changes have been taken.
+        assert!(tree.take_layout_changes().is_empty());
+
+        // Untaken changes are discarded by the next pass.
+        world.solvers.insert(a1, Fixed(Size::new(10.0, 10.0)));
+        tree.schedule_relayout(a1);
+        tree.layout(&world);
+        tree.layout(&world);
+        assert!(tree.take_layout_changes().is_empty());
+    }
+
+    /// Stacks children vertically, while leaf nodes fill up the
+    /// constrained width.
+    struct StackWorld {
+        root_width: f64,
+        /// Number of [`LayoutSolver::build()`] calls.
+        builds: Cell<usize>,
+    }
+
+    impl StackWorld {
+        fn new(root_width: f64) -> Self {
+            Self {
+                root_width,
+                builds: Cell::new(0),
+            }
+        }
+    }
+
+    impl LayoutWorld for StackWorld {
+        fn get_solver(&self, _id: &NodeId) -> &dyn LayoutSolver {
+            self
+        }
+    }
+
+    impl LayoutSolver for StackWorld {
+        fn constraint(
+            &self,
+            parent_constraint: Constraint,
+        ) -> Constraint {
+            match parent_constraint.width {
+                Some(_) => parent_constraint,
+                None => Constraint::fixed_width(self.root_width),
+            }
+        }
+
+        fn build(
+            &self,
+            node: &RectNode,
+            tree: &Rectree,
+            positioner: &mut Positioner,
+        ) -> Size {
+            self.builds.set(self.builds.get() + 1);
+
+            let width = self
+                .constraint(node.parent_constraint())
+                .width
+                .unwrap_or(0.0);
+            if node.children().is_empty() {
+                return Size::new(width, 10.0);
+            }
+
+            let mut height = 0.0;
+            for child in tree.layout_children(node).iter() {
+                positioner.set(child.id, Vec2::new(0.0, height));
+                height += child.size().height;
+            }
+
+            Size::new(width, height)
+        }
+    }
+
+    /// Creates a root with 10 children, each with 10 children.
+    fn create_stack_tree() -> (Rectree, NodeId, Vec<NodeId>) {
+        let mut tree = Rectree::new();
+        let root = tree.insert(RectNode::new());
+        let mut ids = vec![root];
+
+        for _ in 0..10 {
+            let id = tree.insert(RectNode::new().with_parent(root));
+            ids.push(id);
+            for _ in 0..10 {
+                ids.push(
+                    tree.insert(RectNode::new().with_parent(id)),
+                );
+            }
+        }
+
+        (tree, root, ids)
+    }
+
+    #[test]
+    fn test_unchanged_constraint_skips_build() {
+        let world = StackWorld::new(100.0);
+        let (mut tree, root, ids) = create_stack_tree();
+        tree.layout(&world);
+        world.builds.set(0);
+
+        // The children are constrained to the same width.
+        tree.set_root_constraint(
+            root,
+            Constraint::fixed_height(500.0),
+        );
+        tree.layout(&world);
+        assert_eq!(world.builds.get(), RUNS);
+
+        // Reverting the constraint mid-pass leaves nothing to
+        // rebuild below the root.
+        let wide = StackWorld::new(200.0);
+        tree.schedule_relayout(root);
+        assert!(!tree.layout_budgeted(&wide, 1).is_complete());
+        world.builds.set(0);
+        tree.schedule_relayout(root);
+        tree.layout(&world);
+        assert_eq!(world.builds.get(), RUNS);
+        assert_eq!(wide.builds.get(), 0);
+
+        for id in ids.iter() {
+            assert_eq!(tree.get(id).size().width, 100.0);
+            assert_eq!(tree.relayout_reason(id), None);
+        }
+    }
+
+    #[test]
+    fn test_measure_subtree() {
+        let world = StackWorld::new(100.0);
+        let (mut tree, root, ids) = create_stack_tree();
+        tree.layout(&world);
+
+        // Leave some pending work around as well.
+        tree.schedule_relayout(ids[2]);
+        let column = ids[1];
+        let before = format!("{tree:?}");
+
+        let size = tree.measure_subtree(
+            column,
+            &world,
+            Constraint::fixed_width(50.0),
+        );
+        assert_eq!(size, Size::new(50.0, 100.0));
+
+        let size = tree.measure_subtree(
+            root,
+            &world,
+            Constraint::flexible(),
+        );
+        assert_eq!(size, Size::new(100.0, 1000.0));
+
+        // The live tree is left untouched.
+        assert_eq!(format!("{tree:?}"), before);
+        assert_eq!(tree.get(&column).size(), Size::new(100.0, 100.0));
+    }
+
+    #[test]
+    fn test_measure_subtree_panic() {
+        extern crate std;
+        use std::panic::{AssertUnwindSafe, catch_unwind};
+
+        struct Panicking;
+
+        impl LayoutSolver for Panicking {
+            fn build(
+                &self,
+                _node: &RectNode,
+                _tree: &Rectree,
+                _positioner: &mut Positioner,
+            ) -> Size {
+                panic!("build failed");
+            }
+        }
+
+        /// Panics when building the root, after all of its
+        /// descendants have been measured.
+        struct PanicWorld(StackWorld, NodeId);
+
+        impl LayoutWorld for PanicWorld {
+            fn get_solver(&self, id: &NodeId) -> &dyn LayoutSolver {
+                match *id == self.1 {
+                    true => &Panicking,
+                    false => &self.0,
+                }
+            }
+        }
+
+        let (mut tree, root, _) = create_stack_tree();
+        let world = PanicWorld(StackWorld::new(100.0), root);
+        tree.layout(&world.0);
+        let before = format!("{tree:?}");
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            tree.measure_subtree(
+                root,
+                &world,
+                Constraint::fixed_width(50.0),
+            )
+        }));
+        assert!(result.is_err());
+        assert_eq!(format!("{tree:?}"), before);
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum HookEvent {
+        Constrain(NodeId),
+        BuildStart(NodeId),
+        BuildEnd(NodeId, Size),
+        Position(NodeId, Vec2),
+    }
+
+    /// Records the sequence of [`LayoutHooks`] calls.
+    #[derive(Default)]
+    struct RecordHooks {
+        events: Vec<HookEvent>,
+    }
+
+    impl LayoutHooks for RecordHooks {
+        fn on_constrain(&mut self, id: NodeId) {
+            self.events.push(HookEvent::Constrain(id));
+        }
+
+        fn on_build_start(&mut self, id: NodeId) {
+            self.events.push(HookEvent::BuildStart(id));
+        }
+
+        fn on_build_end(&mut self, id: NodeId, size: Size) {
+            self.events.push(HookEvent::BuildEnd(id, size));
+        }
+
+        fn on_position(
+            &mut self,
+            id: NodeId,
+            world_translation: Vec2,
+        ) {
+            self.events
+                .push(HookEvent::Position(id, world_translation));
+        }
+    }
+
+    #[test]
+    fn test_layout_hooks() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let root = world.insert(&mut tree, None, Stack);
+        let leaf_a = world.insert(
+            &mut tree,
+            Some(root),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+        let leaf_b = world.insert(
+            &mut tree,
+            Some(root),
+            Fixed(Size::new(20.0, 10.0)),
+        );
+
+        let mut hooks = RecordHooks::default();
+        tree.layout_with_hooks(&world, &mut hooks);
+        let events = hooks.events;
+
+        let position_of = |event: HookEvent| {
+            events.iter().position(|e| *e == event).unwrap()
+        };
+
+        for id in [root, leaf_a, leaf_b] {
+            let node = tree.get(&id);
+            let constrain = position_of(HookEvent::Constrain(id));
+            let build_start = position_of(HookEvent::BuildStart(id));
+            let build_end =
+                position_of(HookEvent::BuildEnd(id, node.size()));
+            let position = position_of(HookEvent::Position(
+                id,
+                node.world_translation(),
+            ));
+
+            // Constrain before build, build before position.
+            assert!(constrain < build_start);
+            assert!(build_start < build_end);
+            assert!(build_end < position);
+        }
+
+        // Children are built before their parent.
+        let root_start = position_of(HookEvent::BuildStart(root));
+        for id in [leaf_a, leaf_b] {
+            let size = tree.get(&id).size();
+            assert!(
+                position_of(HookEvent::BuildEnd(id, size))
+                    < root_start
+            );
+        }
+        assert_eq!(events.len(), 12);
+    }
+
+    #[test]
+    fn test_drain_scheduled_relayout() {
+        let mut tree = Rectree::new();
+        let root = tree.insert(RectNode::new());
+        let a = tree.insert(RectNode::new().with_parent(root));
+        let b = tree.insert(RectNode::new().with_parent(root));
+        let leaf = tree.insert(RectNode::new().with_parent(a));
+
+        let bottom_up =
+            tree.drain_scheduled_relayout().collect::<Vec<_>>();
+        assert_eq!(bottom_up, [leaf, b, a, root]);
+        assert!(!tree.needs_relayout());
+
+        tree.schedule_relayout(leaf);
+        tree.schedule_relayout(b);
+        tree.schedule_relayout(root);
+        tree.schedule_relayout(a);
+        let top_down = tree
+            .drain_scheduled_relayout_topdown()
+            .collect::<Vec<_>>();
+        assert_eq!(top_down, [root, a, b, leaf]);
+        assert_eq!(tree.drain_scheduled_relayout().count(), 0);
+    }
+
+    #[test]
+    fn test_size_changed_since() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let root = world.insert(&mut tree, None, Stack);
+        let a = world.insert(
+            &mut tree,
+            Some(root),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+        let b = world.insert(
+            &mut tree,
+            Some(root),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+        tree.layout(&world);
+
+        // The renderer syncs everything.
+        let renderer = tree.current_epoch();
+        assert_eq!(
+            tree.size_changed_since(&a, renderer),
+            Some(false)
+        );
+
+        world.solvers.insert(a, Fixed(Size::new(20.0, 10.0)));
+        tree.schedule_relayout(a);
+        tree.layout(&world);
+
+        // The physics system syncs after the first resize.
+        let physics = tree.current_epoch();
+        assert!(physics > renderer);
+
+        world.solvers.insert(b, Fixed(Size::new(10.0, 20.0)));
+        tree.schedule_relayout(b);
+        tree.layout(&world);
+
+        // Observers see the changes after their own epoch only.
+        let changed = |id, epoch| tree.size_changed_since(&id, epoch);
+        assert_eq!(changed(a, renderer), Some(true));
+        assert_eq!(changed(b, renderer), Some(true));
+        assert_eq!(changed(root, renderer), Some(true));
+        assert_eq!(changed(a, physics), Some(false));
+        assert_eq!(changed(b, physics), Some(true));
+        assert_eq!(changed(root, physics), Some(true));
+
+        // Rebuilding to the same size is not a change.
+        let epoch = tree.current_epoch();
+        tree.schedule_relayout(a);
+        tree.layout(&world);
+        assert_eq!(tree.current_epoch(), epoch);
+
+        // New nodes count as changed, removed ones are unknown.
+        let c = world.insert(&mut tree, Some(root), Stack);
+        assert_eq!(tree.size_changed_since(&c, epoch), Some(true));
+        tree.remove(&c);
+        assert_eq!(tree.size_changed_since(&c, epoch), None);
+    }
+
+    #[test]
+    fn test_constraint_bounds() {
+        let inf = f64::INFINITY;
+        let cases = [
+            // (constraint, bounded width, bounded height)
+            (Constraint::flexible(), false, false),
+            (Constraint::unbounded(), false, false),
+            (Constraint::fixed(10.0, 20.0), true, true),
+            (Constraint::loose(10.0, 20.0), true, true),
+            (Constraint::fixed_width(10.0), true, false),
+            (Constraint::loose_height(20.0), false, true),
+            (Constraint::fixed(inf, 20.0), false, true),
+            (Constraint::fixed(10.0, f64::NAN), true, false),
+            (Constraint::fixed(-inf, 0.0), false, true),
+        ];
+        for (constraint, width, height) in cases {
+            assert_eq!(constraint.has_bounded_width(), width);
+            assert_eq!(constraint.has_bounded_height(), height);
+            assert_eq!(constraint.is_bounded(), width && height);
+        }
+
+        // Unbounded dimensions are never tight.
+        assert!(!Constraint::fixed(inf, inf).is_tight());
+        assert!(Constraint::fixed(inf, 20.0).is_tight_height());
+    }
+
+    #[test]
+    fn test_constraint_biggest_smallest() {
+        let inf = f64::INFINITY;
+        let max = Size::new(500.0, 400.0);
+
+        let constraint = Constraint::fixed(10.0, 20.0);
+        assert_eq!(constraint.biggest(max), Size::new(10.0, 20.0));
+        assert_eq!(constraint.smallest(), Size::new(10.0, 20.0));
+
+        let constraint = Constraint::loose(10.0, 20.0);
+        assert_eq!(constraint.biggest(max), Size::new(10.0, 20.0));
+        assert_eq!(constraint.smallest(), Size::ZERO);
+
+        for constraint in
+            [Constraint::flexible(), Constraint::unbounded()]
+        {
+            assert_eq!(constraint.biggest(max), max);
+            assert_eq!(constraint.smallest(), Size::ZERO);
+        }
+
+        let constraint = Constraint {
+            height: Some(inf),
+            ..Constraint::fixed_width(10.0)
+        };
+        assert_eq!(constraint.biggest(max), Size::new(10.0, 400.0));
+        assert_eq!(constraint.smallest(), Size::new(10.0, 0.0));
+
+        let constraint = Constraint::fixed(f64::NAN, 20.0);
+        assert_eq!(constraint.biggest(max), Size::new(500.0, 20.0));
+        assert_eq!(constraint.smallest(), Size::new(0.0, 20.0));
+    }
+
+    #[test]
+    fn test_constraint_unbounded_resolve() {
+        let preferred = Size::new(30.0, 40.0);
+        for constraint in [
+            Constraint::unbounded(),
+            Constraint::unbounded().tighten(),
+            Constraint::fixed(f64::NAN, f64::NAN),
+        ] {
+            assert_eq!(constraint.resolve(preferred), preferred);
+            assert!(!constraint.overflows(preferred));
+            assert_eq!(constraint.clamp(preferred), preferred);
+        }
+
+        let constraint =
+            Constraint::unbounded().deflate(Insets::uniform(5.0));
+        assert_eq!(constraint, Constraint::unbounded());
+        assert_eq!(
+            ConstraintValue::Percent(50.0)
+                .resolve(Some(f64::INFINITY)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_constraint_eq() {
+        let nan = Constraint::fixed(f64::NAN, 10.0);
+        assert_eq!(nan, nan);
+        assert_ne!(nan, nan.loosen());
+        assert_ne!(nan, Constraint::fixed(0.0, 10.0));
+        assert_ne!(
+            Constraint::fixed_width(f64::NAN),
+            Constraint::flexible()
+        );
+        assert_eq!(Constraint::unbounded(), Constraint::unbounded());
+        assert_ne!(
+            Constraint::unbounded(),
+            Constraint::flexible().loosen()
+        );
+    }
+
+    #[test]
+    fn test_layout_non_finite_root_constraint() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+        let root = world.insert(&mut tree, None, Stack);
+        let child = world.insert(&mut tree, Some(root), Fill);
+        tree.layout(&world);
+
+        for constraint in [
+            Constraint::unbounded(),
+            Constraint::fixed(f64::NAN, 10.0),
+        ] {
+            tree.set_root_constraint(root, constraint);
+            tree.layout(&world);
+            assert!(!tree.needs_relayout());
+
+            // Re-applying the same constraint is not a change.
+            tree.set_root_constraint(root, constraint);
+            assert!(!tree.needs_relayout());
+        }
+
+        // Unbounded dimensions are not filled.
+        assert_eq!(tree.get(&child).size(), Size::new(0.0, 10.0));
+    }
+
+    #[test]
+    fn test_constraint_display() {
+        let constraint = Constraint::fixed_width(20.0);
+        assert_eq!(format!("{constraint}"), "20×flex");
+        let constraint = Constraint::loose(20.5, 10.0);
+        assert_eq!(format!("{constraint}"), "≤20.5×≤10");
+        let constraint = Constraint::flexible();
+        assert_eq!(format!("{constraint}"), "flex×flex");
+        let constraint = Constraint::unbounded();
+        assert_eq!(format!("{constraint}"), "≤inf×≤inf");
+    }
+
+    #[test]
+    fn test_constraint_parse() {
+        let constraints = [
+            Constraint::fixed(20.0, 10.0),
+            Constraint::fixed_width(20.0),
+            Constraint::loose(20.5, 0.0),
+            Constraint::flexible(),
+            Constraint::unbounded(),
+            Constraint {
+                height: Some(-3.0),
+                loose_height: true,
+                ..Constraint::fixed_width(1e9)
+            },
+        ];
+        for constraint in constraints {
+            let formatted = format!("{constraint}");
+            assert_eq!(Constraint::parse(&formatted), Ok(constraint));
+        }
+
+        // ASCII variants and whitespace.
+        assert_eq!(
+            " 20 x flex ".parse(),
+            Ok(Constraint::fixed_width(20.0))
+        );
+        assert_eq!(
+            Constraint::parse("flexx<=10"),
+            Ok(Constraint {
+                height: Some(10.0),
+                loose_height: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            Constraint::parse("<=∞ x ≤inf"),
+            Ok(Constraint::unbounded())
+        );
+    }
+
+    #[test]
+    fn test_constraint_parse_errors() {
+        use ParseConstraintError::*;
+
+        assert_eq!(Constraint::parse("20"), Err(MissingSeparator));
+        assert_eq!(Constraint::parse("flex"), Err(MissingSeparator));
+        assert_eq!(
+            Constraint::parse("20xwide"),
+            Err(InvalidAxis("wide".into()))
+        );
+        assert_eq!(
+            Constraint::parse("infx10"),
+            Err(NonFinite(f64::INFINITY))
+        );
+        assert_eq!(
+            Constraint::parse("10x<=-inf"),
+            Err(NonFinite(f64::NEG_INFINITY))
+        );
+        assert_eq!(
+            Constraint::parse("10x∞"),
+            Err(NonFinite(f64::INFINITY))
+        );
+        // NaN never equals itself.
+        assert!(matches!(
+            Constraint::parse("NaNxflex"),
+            Err(NonFinite(bound)) if bound.is_nan()
+        ));
+
+        let error = Constraint::parse("nanx1").unwrap_err();
+        assert_eq!(
+            format!("{error}"),
+            "Bounds must be finite, got NaN."
+        );
+    }
+
+    #[test]
+    fn test_constraint_value_resolve() {
+        use ConstraintValue::*;
+
+        assert_eq!(Fixed(30.0).resolve(Some(200.0)), Some(30.0));
+        assert_eq!(Fixed(30.0).resolve(None), Some(30.0));
+        assert_eq!(Percent(50.0).resolve(Some(200.0)), Some(100.0));
+        assert_eq!(Percent(100.0).resolve(Some(200.0)), Some(200.0));
+        assert_eq!(Percent(50.0).resolve(None), None);
+        assert_eq!(Flexible.resolve(Some(200.0)), None);
+        assert_eq!(Flexible.resolve(None), None);
+    }
+
+    #[test]
+    fn test_schedule_relayout_subtree() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let root = world.insert(&mut tree, None, Stack);
+        let mut leaves = Vec::new();
+        let mut parent = root;
+        // A deep chain of stacks, each holding a single leaf.
+        for _ in 0..5 {
+            let column = world.insert(&mut tree, Some(parent), Stack);
+            leaves.push(world.insert(
+                &mut tree,
+                Some(column),
+                Fixed(Size::new(10.0, 10.0)),
+            ));
+            parent = column;
+        }
+        tree.layout(&world);
+        assert_eq!(tree.get(&root).size(), Size::new(10.0, 50.0));
+
+        // Change the metrics without scheduling anything.
+        for leaf in leaves.iter() {
+            world.solvers.insert(*leaf, Fixed(Size::new(20.0, 20.0)));
+        }
+        assert!(!tree.needs_relayout());
+
+        assert_eq!(tree.schedule_relayout_subtree(root), 11);
+        tree.layout(&world);
+
+        for leaf in leaves.iter() {
+            assert_eq!(tree.get(leaf).size(), Size::new(20.0, 20.0));
+        }
+        assert_eq!(tree.get(&root).size(), Size::new(20.0, 100.0));
+    }
+
+    #[test]
+    fn test_relayout_reason() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let root = world.insert(&mut tree, None, Stack);
+        let leaf = world.insert(
+            &mut tree,
+            Some(root),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+        assert_eq!(
+            tree.relayout_reason(&leaf),
+            Some(RelayoutReason::Scheduled)
+        );
+
+        tree.layout(&world);
+        assert!(!tree.needs_relayout_node(&root));
+        assert!(!tree.needs_relayout_node(&leaf));
+
+        world.solvers.insert(leaf, Fixed(Size::new(20.0, 20.0)));
+        tree.schedule_relayout(leaf);
+        assert!(!tree.needs_relayout_node(&root));
+
+        // Constraining and building the leaf propagates its new
+        // size to the root.
+        let progress = tree.layout_budgeted(&world, 2);
+        assert!(!progress.is_complete());
+        assert_eq!(
+            tree.relayout_reason(&leaf),
+            Some(RelayoutReason::TranslationOnly)
+        );
+        assert_eq!(
+            tree.relayout_reason(&root),
+            Some(RelayoutReason::SizePropagated)
+        );
+
+        tree.layout(&world);
+        assert_eq!(tree.relayout_reason(&root), None);
+        assert_eq!(tree.relayout_reason(&leaf), None);
+
+        // A new root constraint reaches the children.
+        let world = StackWorld::new(100.0);
+        let (mut tree, root, _) = create_stack_tree();
+        tree.layout(&world);
+
+        let world = StackWorld::new(200.0);
+        tree.schedule_relayout(root);
+        tree.layout_budgeted(&world, 1);
+        for child in tree.get(&root).children() {
+            assert_eq!(
+                tree.relayout_reason(child),
+                Some(RelayoutReason::ConstraintChanged)
+            );
+        }
+    }
+
+    fn built_nodes(hooks: &RecordHooks) -> Vec<NodeId> {
+        hooks
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                HookEvent::BuildStart(id) => Some(*id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_skip_unchanged_builds() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let root = world.insert(
+            &mut tree,
+            None,
+            Constrained(Size::new(100.0, 100.0)),
+        );
+        let mut leaves = Vec::new();
+        for _ in 0..100 {
+            let panel = world.insert(&mut tree, Some(root), Fill);
+            leaves.push(world.insert(
+                &mut tree,
+                Some(panel),
+                Fixed(Size::new(10.0, 10.0)),
+            ));
+        }
+        tree.layout(&world);
+
+        // Children are tightly constrained regardless of the root.
+        tree.set_root_constraint(root, Constraint::fixed(50.0, 50.0));
+        let mut hooks = RecordHooks::default();
+        tree.layout_with_hooks(&world, &mut hooks);
+        assert_eq!(built_nodes(&hooks), [root]);
+
+        // Change the constraint of the panels, then revert it before
+        // the pass resolves.
+        world
+            .solvers
+            .insert(root, Constrained(Size::new(80.0, 80.0)));
+        tree.schedule_relayout(root);
+        assert!(!tree.layout_budgeted(&world, 1).is_complete());
+
+        world
+            .solvers
+            .insert(root, Constrained(Size::new(100.0, 100.0)));
+        tree.schedule_relayout(root);
+        let mut hooks = RecordHooks::default();
+        tree.layout_with_hooks(&world, &mut hooks);
+        assert_eq!(built_nodes(&hooks), [root]);
+
+        for leaf in leaves.iter() {
+            let panel = tree.get(leaf).parent().unwrap();
+            assert_eq!(
+                tree.get(&panel).size(),
+                Size::new(100.0, 100.0)
+            );
+            assert_eq!(tree.relayout_reason(&panel), None);
+            assert_eq!(tree.relayout_reason(leaf), None);
+        }
+    }
+
+    /// Uses the same solver for every node.
+    #[cfg(feature = "debug-layout")]
+    struct UniformWorld<S>(S);
+
+    #[cfg(feature = "debug-layout")]
+    impl<S: LayoutSolver> LayoutWorld for UniformWorld<S> {
+        fn get_solver(&self, _id: &NodeId) -> &dyn LayoutSolver {
+            &self.0
+        }
+    }
+
+    /// Grows on every build.
+    #[cfg(feature = "debug-layout")]
+    #[derive(Default)]
+    struct GrowingSolver(Cell<f64>);
+
+    #[cfg(feature = "debug-layout")]
+    impl LayoutSolver for GrowingSolver {
+        fn build(
+            &self,
+            _node: &RectNode,
+            _tree: &Rectree,
+            _positioner: &mut Positioner,
+        ) -> Size {
+            self.0.set(self.0.get() + 1.0);
+            Size::new(self.0.get(), 0.0)
+        }
+    }
+
+    /// Never positions its children.
+    #[cfg(feature = "debug-layout")]
+    struct ForgetfulSolver;
+
+    #[cfg(feature = "debug-layout")]
+    impl LayoutSolver for ForgetfulSolver {
+        fn build(
+            &self,
+            _node: &RectNode,
+            _tree: &Rectree,
+            _positioner: &mut Positioner,
+        ) -> Size {
+            Size::ZERO
+        }
+    }
+
+    #[cfg(feature = "debug-layout")]
+    #[test]
+    #[should_panic(expected = "GrowingSolver) is not idempotent")]
+    fn test_sanitize_non_idempotent() {
+        let mut tree = Rectree::new();
+        tree.insert(RectNode::new());
+        tree.layout(&UniformWorld(GrowingSolver::default()));
+    }
+
+    #[cfg(feature = "debug-layout")]
+    #[test]
+    #[should_panic(expected = "did not position its child")]
+    fn test_sanitize_unpositioned_child() {
+        let mut tree = Rectree::new();
+        let root = tree.insert(RectNode::new());
+        tree.insert(RectNode::new().with_parent(root));
+        tree.layout(&UniformWorld(ForgetfulSolver));
+    }
+
+    #[cfg(feature = "debug-layout")]
+    #[test]
+    fn test_sanitize_absolute_child() {
+        let mut tree = Rectree::new();
+        let root = tree.insert(RectNode::new());
+        tree.insert(
+            RectNode::new()
+                .with_parent(root)
+                .with_position_mode(PositionMode::Absolute),
+        );
+        tree.layout(&UniformWorld(ForgetfulSolver));
+    }
+
+    /// Wraps children into rows, which requires its own width.
+    #[derive(Default)]
+    struct WrapSolver {
+        finalizes: Cell<usize>,
+    }
+
+    impl LayoutSolver for WrapSolver {
+        fn needs_finalize(&self) -> bool {
+            true
+        }
+
+        fn build(
+            &self,
+            node: &RectNode,
+            tree: &Rectree,
+            _positioner: &mut Positioner,
+        ) -> Size {
+            // Take as much width as the content would in one row.
+            let width = node
+                .children()
+                .iter()
+                .map(|id| tree.get(id).size().width)
+                .sum();
+            Size::new(
+                node.parent_constraint().resolve_width(width),
+                0.0,
+            )
+        }
+
+        fn finalize(
+            &self,
+            node: &RectNode,
+            size: Size,
+            tree: &Rectree,
+            positioner: &mut Positioner,
+        ) -> Option<Size> {
+            self.finalizes.set(self.finalizes.get() + 1);
+
+            let mut cursor = Vec2::ZERO;
+            let mut row_height = 0.0f64;
+            for child in tree.layout_children(node).iter() {
+                let child_size = child.size();
+                if cursor.x > 0.0
+                    && cursor.x + child_size.width > size.width
+                {
+                    cursor = Vec2::new(0.0, cursor.y + row_height);
+                    row_height = 0.0;
+                }
+                positioner.set(child.id, cursor);
+                cursor.x += child_size.width;
+                row_height = row_height.max(child_size.height);
+            }
+
+            Some(Size::new(size.width, cursor.y + row_height))
+        }
+    }
+
+    struct WrapWorld {
+        root: NodeId,
+        wrap: WrapSolver,
+        leaf: TestSolver,
+    }
+
+    impl LayoutWorld for WrapWorld {
+        fn get_solver(&self, id: &NodeId) -> &dyn LayoutSolver {
+            if *id == self.root {
+                &self.wrap
+            } else {
+                &self.leaf
+            }
+        }
+    }
+
+    enum StretchSolver {
+        /// Places children in a row, stretching them to the height
+        /// of the tallest.
+        Row,
+        /// Wraps a single child, centering it within the size
+        /// dictated by the parent.
+        Cell,
+        Leaf(Size),
+        /// Grows taller on every build, never converging.
+        #[cfg_attr(feature = "debug-layout", allow(dead_code))]
+        Growing(Cell<f64>),
+    }
+
+    impl LayoutSolver for StretchSolver {
+        fn build(
+            &self,
+            node: &RectNode,
+            tree: &Rectree,
+            positioner: &mut Positioner,
+        ) -> Size {
+            let children = tree.layout_children(node);
+
+            match self {
+                StretchSolver::Row => {
+                    let mut size = Size::ZERO;
+                    for child in children.iter() {
+                        let child_size = child.node.built_size();
+                        size.height =
+                            size.height.max(child_size.height);
+                    }
+                    for child in children.iter() {
+                        let width = child.node.built_size().width;
+                        positioner.set_rect(
+                            child.id,
+                            Rect::new(
+                                size.width,
+                                0.0,
+                                size.width + width,
+                                size.height,
+                            ),
+                        );
+                        size.width += width;
+                    }
+                    size
+                }
+                StretchSolver::Cell => {
+                    let child = children.get(0).unwrap();
+                    let size = child.size();
+                    let outer = node.size_override().unwrap_or(size);
+                    positioner.set(
+                        child.id,
+                        ((outer - size) * 0.5).to_vec2(),
+                    );
+                    size
+                }
+                StretchSolver::Leaf(size) => *size,
+                StretchSolver::Growing(height) => {
+                    height.set(height.get() + 1.0);
+                    Size::new(10.0, height.get())
+                }
+            }
+        }
+    }
+
+    struct StretchWorld(HashMap<NodeId, StretchSolver>);
+
+    impl LayoutWorld for StretchWorld {
+        fn get_solver(&self, id: &NodeId) -> &dyn LayoutSolver {
+            &self.0[id]
+        }
+    }
+
+    // The growing cell is intentionally not idempotent.
+    #[cfg(not(feature = "debug-layout"))]
+    #[test]
+    fn test_non_converged() {
+        let mut tree = Rectree::new();
+        let row = tree.insert(RectNode::new());
+        let cell = tree.insert(RectNode::new().with_parent(row));
+
+        let mut solvers = HashMap::new();
+        solvers.insert(row, StretchSolver::Row);
+        solvers.insert(cell, StretchSolver::Growing(Cell::new(0.0)));
+        let world = StretchWorld(solvers);
+
+        // Every size dictated by the row causes the cell to grow,
+        // which in turn causes the row to dictate a new size.
+        tree.set_max_rebuilds(4);
+        tree.layout(&world);
+        assert!(!tree.needs_relayout());
+
+        let non_converged = tree.take_non_converged();
+        assert_eq!(
+            non_converged,
+            [NonConvergence {
+                id: cell,
+                sizes: [Size::new(10.0, 4.0), Size::new(10.0, 5.0)],
+                reason: NonConvergenceReason::MaxRebuilds,
+            }]
+        );
+        // The row keeps the size dictated last.
+        assert_eq!(tree.get(&cell).size(), Size::new(10.0, 4.0));
+        assert_eq!(tree.get(&row).size(), Size::new(10.0, 4.0));
+    }
+
+    // The growing cell is intentionally not idempotent.
+    #[cfg(not(feature = "debug-layout"))]
+    #[test]
+    fn test_max_pass_builds() {
+        let mut tree = Rectree::new();
+        let row = tree.insert(RectNode::new());
+        let cell = tree.insert(RectNode::new().with_parent(row));
+
+        let mut solvers = HashMap::new();
+        solvers.insert(row, StretchSolver::Row);
+        solvers.insert(cell, StretchSolver::Growing(Cell::new(0.0)));
+        let world = StretchWorld(solvers);
+
+        // The per node limit is never reached.
+        tree.set_max_rebuilds(1000);
+        tree.set_max_pass_builds(5);
+        tree.layout(&world);
+
+        let non_converged = tree.take_non_converged();
+        assert_eq!(non_converged.len(), 1);
+        assert_eq!(
+            non_converged[0].reason,
+            NonConvergenceReason::MaxPassBuilds
+        );
+        // The pending work is left for the next pass.
+        assert!(tree.needs_relayout());
+
+        // Untaken entries are discarded by the next pass.
+        tree.layout(&world);
+        tree.layout(&world);
+        assert_eq!(tree.take_non_converged().len(), 1);
+    }
+
+    #[test]
+    fn test_positioner_set_size() {
+        use StretchSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut solvers = HashMap::<NodeId, StretchSolver>::new();
+        let mut insert = |tree: &mut Rectree, parent, solver| {
+            let mut node = RectNode::new();
+            if let Some(parent) = parent {
+                node = node.with_parent(parent);
+            }
+            let id = tree.insert(node);
+            solvers.insert(id, solver);
+            id
+        };
+
+        let row = insert(&mut tree, None, Row);
+        let cell_a = insert(&mut tree, Some(row), Cell);
+        let leaf_a = insert(
+            &mut tree,
+            Some(cell_a),
+            Leaf(Size::new(10.0, 10.0)),
+        );
+        let cell_b = insert(&mut tree, Some(row), Cell);
+        let leaf_b = insert(
+            &mut tree,
+            Some(cell_b),
+            Leaf(Size::new(20.0, 30.0)),
+        );
+
+        let mut world = StretchWorld(solvers);
+        tree.layout(&world);
+
+        // The parent's size takes precedence over the cell's own.
+        assert_eq!(tree.get(&row).size(), Size::new(30.0, 30.0));
+        assert_eq!(tree.get(&cell_a).size(), Size::new(10.0, 30.0));
+        assert_eq!(tree.get(&cell_b).size(), Size::new(20.0, 30.0));
+        assert_eq!(
+            tree.get(&leaf_a).world_rect(),
+            Rect::new(0.0, 10.0, 10.0, 20.0)
+        );
+        assert_eq!(
+            tree.get(&leaf_b).world_rect(),
+            Rect::new(10.0, 0.0, 30.0, 30.0)
+        );
+
+        // Shrinking the tallest leaf shrinks every cell.
+        world.0.insert(leaf_b, Leaf(Size::new(20.0, 20.0)));
+        tree.schedule_relayout(leaf_b);
+        tree.layout(&world);
+
+        assert_eq!(tree.get(&row).size(), Size::new(30.0, 20.0));
+        assert_eq!(tree.get(&cell_a).size(), Size::new(10.0, 20.0));
+        assert_eq!(
+            tree.get(&leaf_a).world_rect(),
+            Rect::new(0.0, 5.0, 10.0, 15.0)
+        );
+        assert!(!tree.needs_relayout());
+    }
+
+    /// Records the [`BuildCtx`] of non-root nodes, and fills up the
+    /// constraint. Roots dictate the sizes of their children.
+    #[derive(Default)]
+    struct ContextSolver {
+        /// Parent and previous sizes, see [`BuildCtx`].
+        sizes: RefCell<Vec<[Option<Size>; 2]>>,
+    }
+
+    impl LayoutWorld for ContextSolver {
+        fn get_solver(&self, _id: &NodeId) -> &dyn LayoutSolver {
+            self
+        }
+    }
+
+    impl LayoutSolver for ContextSolver {
+        fn build(
+            &self,
+            _node: &RectNode,
+            _tree: &Rectree,
+            _positioner: &mut Positioner,
+        ) -> Size {
+            unreachable!("build_with_ctx is overridden")
+        }
+
+        fn build_with_ctx(
+            &self,
+            ctx: BuildCtx,
+            positioner: &mut Positioner,
+        ) -> Size {
+            for child in ctx.node.children() {
+                match ctx.node.parent() {
+                    Some(_) => positioner.set(*child, Vec2::ZERO),
+                    None => positioner.set_rect(
+                        *child,
+                        Rect::new(0.0, 0.0, 50.0, 40.0),
+                    ),
+                }
+            }
+            if ctx.node.parent().is_some() {
+                self.sizes
+                    .borrow_mut()
+                    .push([ctx.parent_size, ctx.previous_size]);
+            }
+            assert_eq!(
+                ctx.children_constraint,
+                ctx.constraint.deflate(ctx.node.padding())
+            );
+            ctx.node
+                .parent_constraint()
+                .resolve(Size::new(10.0, 10.0))
+        }
+    }
+
+    #[test]
+    fn test_build_with_ctx() {
+        let mut tree = Rectree::new();
+        let root = tree.insert(RectNode::new());
+        let child = tree.insert(RectNode::new().with_parent(root));
+        tree.set_root_constraint(
+            root,
+            Constraint::fixed(100.0, 80.0),
+        );
+
+        let world = ContextSolver::default();
+        tree.layout(&world);
+
+        // The child fills up the resolved constraint and is built
+        // before its parent, which then dictates a smaller size.
+        let size = Size::new(100.0, 80.0);
+        let dictated = Size::new(50.0, 40.0);
+        assert_eq!(tree.get(&child).size(), dictated);
+        // The sanitizer records every build twice.
+        world.sizes.borrow_mut().dedup();
+        assert_eq!(
+            *world.sizes.borrow(),
+            [[None, None], [Some(size), Some(size)]]
+        );
+
+        // The parent has not been built yet during this pass.
+        tree.schedule_relayout(child);
+        tree.layout(&world);
+
+        assert_eq!(
+            world.sizes.borrow().last(),
+            Some(&[None, Some(dictated)])
+        );
+    }
+
+    /// Lays out children in a row, distributing the remaining width
+    /// of the constraint by their [`LayoutParams::flex`].
+    struct FlexRow;
+
+    impl LayoutSolver for FlexRow {
+        fn build(
+            &self,
+            _node: &RectNode,
+            _tree: &Rectree,
+            _positioner: &mut Positioner,
+        ) -> Size {
+            unreachable!("build_with_ctx is overridden")
+        }
+
+        fn build_with_ctx(
+            &self,
+            ctx: BuildCtx,
+            positioner: &mut Positioner,
+        ) -> Size {
+            let children = ctx.children();
+            let mut fixed_width = 0.0;
+            let mut total_flex = 0.0;
+            for child in children.iter() {
+                match child.params().flex {
+                    0.0 => fixed_width += child.size().width,
+                    flex => total_flex += flex,
+                }
+            }
+            let remaining =
+                ctx.children_constraint.biggest(Size::ZERO).width
+                    - fixed_width;
+
+            let mut size = Size::ZERO;
+            for child in children.iter() {
+                let child_size = match child.params().flex {
+                    0.0 => child.size(),
+                    flex => Size::new(
+                        remaining * flex / total_flex,
+                        child.size().height,
+                    ),
+                };
+                positioner.set_rect(
+                    child.id,
+                    Rect::from_origin_size(
+                        (size.width, 0.0),
+                        child_size,
+                    ),
+                );
+                size.width += child_size.width;
+                size.height = size.height.max(child_size.height);
+            }
+            size
+        }
+    }
+
+    struct FlexWorld {
+        root: NodeId,
+    }
+
+    impl LayoutWorld for FlexWorld {
+        fn get_solver(&self, id: &NodeId) -> &dyn LayoutSolver {
+            if *id == self.root {
+                &FlexRow
+            } else {
+                &crate::solvers::PassthroughSolver
+            }
+        }
+    }
+
+    #[test]
+    fn test_layout_children_order() {
+        let mut tree = Rectree::new();
+        let root = tree.insert(RectNode::new());
+        let insert = |tree: &mut Rectree| {
+            tree.insert(RectNode::new().with_parent(root))
+        };
+        let a = insert(&mut tree);
+        let b = insert(&mut tree);
+        let c = insert(&mut tree);
+        tree.insert(
+            RectNode::new()
+                .with_parent(root)
+                .with_position_mode(PositionMode::Absolute),
+        );
+        tree.remove(&a);
+        // Likely reuses the slot of `a`, but still comes last.
+        let d = insert(&mut tree);
+
+        let children = tree.layout_children(tree.get(&root));
+        assert_eq!(children.ids(), [b, c, d]);
+        assert_eq!(children.len(), 3);
+        assert_eq!(children.get(2).map(|child| child.id), Some(d));
+        assert!(children.get(3).is_none());
+
+        // Reordering is reflected in the next build of the parent.
+        tree.layout(&crate::solvers::PassthroughWorld);
+        assert!(tree.set_child_index(&d, 0));
+        assert!(tree.relayout_reason(&root).is_some());
+        let children = tree.layout_children(tree.get(&root));
+        assert_eq!(children.ids(), [d, b, c]);
+
+        // Out of bounds, counting the absolute child.
+        assert!(tree.set_child_index(&b, 3));
+        assert!(!tree.set_child_index(&b, 4));
+        assert!(!tree.set_child_index(&root, 0));
+        let children = tree.layout_children(tree.get(&root));
+        assert_eq!(children.ids(), [d, c, b]);
+    }
+
+    #[test]
+    fn test_children_sizes() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let root = world.insert(&mut tree, None, Stack);
+        let mut insert_leaf = |width| {
+            world.insert(
+                &mut tree,
+                Some(root),
+                Fixed(Size::new(width, 10.0)),
+            )
+        };
+        let a = insert_leaf(30.0);
+        let b = insert_leaf(10.0);
+        let c = insert_leaf(20.0);
+        world.insert_node(
+            &mut tree,
+            RectNode::new()
+                .with_parent(root)
+                .with_position_mode(PositionMode::Absolute),
+            Fixed(Size::new(5.0, 5.0)),
+        );
+        tree.layout(&world);
+
+        assert_eq!(
+            tree.children_sizes(&root).collect::<Vec<_>>(),
+            [
+                (a, Size::new(30.0, 10.0)),
+                (b, Size::new(10.0, 10.0)),
+                (c, Size::new(20.0, 10.0)),
+            ]
+        );
+        assert_eq!(tree.children_sizes(&a).count(), 0);
+
+        tree.remove(&root);
+        assert_eq!(tree.children_sizes(&root).count(), 0);
+    }
+
+    #[test]
+    fn test_layout_params() {
+        let mut tree = Rectree::new();
+        let root = tree.insert(RectNode::new());
+        let leaf = |width| {
+            RectNode::from_size(Size::new(width, 10.0))
+                .with_parent(root)
+        };
+        let a = tree.insert(leaf(20.0));
+        let b = tree.insert(leaf(0.0).with_layout_params(
+            LayoutParams::default().with_flex(1.0),
+        ));
+        let c = tree.insert(leaf(30.0));
+        tree.set_root_constraint(
+            root,
+            Constraint::fixed_width(100.0),
+        );
+
+        let world = FlexWorld { root };
+        tree.layout(&world);
+
+        let x_range = |id| {
+            let rect = tree.get(&id).world_rect();
+            (rect.x0, rect.x1)
+        };
+        assert_eq!(x_range(a), (0.0, 20.0));
+        assert_eq!(x_range(b), (20.0, 70.0));
+        assert_eq!(x_range(c), (70.0, 100.0));
+
+        // Changing the params relayouts the parent.
+        let params = LayoutParams::default().with_flex(1.0);
+        assert!(tree.set_layout_params(c, params));
+        assert!(tree.needs_relayout_node(&root));
+        tree.layout(&world);
+
+        let x_range = |id| {
+            let rect = tree.get(&id).world_rect();
+            (rect.x0, rect.x1)
+        };
+        assert_eq!(x_range(b), (20.0, 60.0));
+        assert_eq!(x_range(c), (60.0, 100.0));
+        assert_eq!(tree.get(&c).layout_params(), params);
+
+        // Unchanged params do not schedule anything.
+        tree.set_layout_params(c, params);
+        assert!(!tree.needs_relayout());
+        tree.remove(&c);
+        assert!(!tree.set_layout_params(c, params));
+    }
+
+    #[test]
+    fn test_finalize() {
+        let mut tree = Rectree::new();
+        let root = tree.insert(RectNode::new());
+        let leaves = (0..5)
+            .map(|_| tree.insert(RectNode::new().with_parent(root)))
+            .collect::<Vec<_>>();
+        let world = WrapWorld {
+            root,
+            wrap: WrapSolver::default(),
+            leaf: TestSolver::Fixed(Size::new(30.0, 10.0)),
+        };
+        tree.set_root_constraint(
+            root,
+            Constraint::loose_width(100.0),
+        );
+        tree.layout(&world);
+
+        // 3 leaves per row.
+        assert_eq!(tree.get(&root).size(), Size::new(100.0, 20.0));
+        assert_eq!(
+            tree.get(&leaves[2]).translation(),
+            Vec2::new(60.0, 0.0)
+        );
+        assert_eq!(
+            tree.get(&leaves[3]).translation(),
+            Vec2::new(0.0, 10.0)
+        );
+        // The second pass confirms the size, the sanitizer reruns
+        // both passes.
+        assert_eq!(world.wrap.finalizes.get(), 2 * RUNS);
+    }
+
+    #[test]
+    fn test_schedule_relayout_all() {
+        let world = StackWorld::new(100.0);
+        let (mut tree, _, _) = create_stack_tree();
+        let second_root = tree.insert(RectNode::new());
+        tree.layout(&world);
+
+        world.builds.set(0);
+        assert_eq!(tree.schedule_relayout_all(), 112);
+        tree.layout(&world);
+
+        // Every node is rebuilt, even with unchanged constraints.
+        assert_eq!(world.builds.get(), 112 * RUNS);
+        assert!(tree.get(&second_root).state.built());
+    }
+
+    #[test]
+    fn test_sized_by_parent() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let root = world.insert(
+            &mut tree,
+            None,
+            Constrained(Size::new(100.0, 100.0)),
+        );
+        let panel = world.insert(&mut tree, Some(root), Fill);
+        let leaf = world.insert(
+            &mut tree,
+            Some(panel),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+        let sibling = world.insert(
+            &mut tree,
+            Some(panel),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+        tree.layout(&world);
+        assert_eq!(tree.get(&panel).size(), Size::new(100.0, 100.0));
+        assert_eq!(
+            tree.get(&sibling).world_translation(),
+            Vec2::new(0.0, 10.0)
+        );
+
+        world.solvers.insert(leaf, Fixed(Size::new(20.0, 20.0)));
+        tree.schedule_relayout(leaf);
+
+        let mut hooks = RecordHooks::default();
+        tree.layout_with_hooks(&world, &mut hooks);
+
+        // Only the leaf is rebuilt.
+        let builds = hooks
+            .events
+            .iter()
+            .filter(|e| matches!(e, HookEvent::BuildStart(_)))
+            .collect::<Vec<_>>();
+        assert_eq!(builds, [&HookEvent::BuildStart(leaf)]);
+        assert_eq!(tree.get(&leaf).size(), Size::new(20.0, 20.0));
+        assert_eq!(tree.get(&panel).size(), Size::new(100.0, 100.0));
+        // Siblings are still moved around the resized leaf.
+        assert_eq!(
+            tree.get(&sibling).world_translation(),
+            Vec2::new(0.0, 20.0)
+        );
+
+        // Without a tight constraint, the panel is rebuilt.
+        world.solvers.insert(root, Stack);
+        tree.schedule_relayout(root);
+        tree.layout(&world);
+
+        world.solvers.insert(leaf, Fixed(Size::new(30.0, 30.0)));
+        tree.schedule_relayout(leaf);
+
+        let mut hooks = RecordHooks::default();
+        tree.layout_with_hooks(&world, &mut hooks);
+        assert!(hooks.events.contains(&HookEvent::BuildStart(panel)));
+    }
+
+    /// Memoizes its last `(Constraint, Size)` pair across layout
+    /// passes.
+    #[derive(Default)]
+    struct CachingSolver {
+        last: Option<(Constraint, Size)>,
+        /// Number of times the size was computed.
+        computes: usize,
+    }
+
+    impl CachingSolver {
+        fn compute(constraint: Constraint) -> Size {
+            constraint.resolve(Size::new(50.0, 20.0))
+        }
+    }
+
+    impl LayoutSolver for CachingSolver {
+        fn build(
+            &self,
+            node: &RectNode,
+            _tree: &Rectree,
+            _positioner: &mut Positioner,
+        ) -> Size {
+            Self::compute(node.parent_constraint())
+        }
+
+        fn build_mut(
+            &mut self,
+            node: &RectNode,
+            _tree: &Rectree,
+            _positioner: &mut Positioner,
+        ) -> Size {
+            let constraint = node.parent_constraint();
+            if let Some((last, size)) = self.last
+                && last == constraint
+            {
+                return size;
+            }
+
+            self.computes += 1;
+            let size = Self::compute(constraint);
+            self.last = Some((constraint, size));
+            size
+        }
+    }
+
+    #[derive(Default)]
+    struct CachingWorld {
+        solvers: HashMap<NodeId, CachingSolver>,
+    }
+
+    impl LayoutWorld for CachingWorld {
+        fn get_solver(&self, id: &NodeId) -> &dyn LayoutSolver {
+            &self.solvers[id]
+        }
+
+        fn get_solver_mut(
+            &mut self,
+            id: &NodeId,
+        ) -> Option<&mut dyn LayoutSolver> {
+            self.solvers
+                .get_mut(id)
+                .map(|solver| solver as &mut dyn LayoutSolver)
+        }
+    }
+
+    #[test]
+    fn test_layout_mut() {
+        let mut tree = Rectree::new();
+        let mut world = CachingWorld::default();
+
+        let root = tree.insert(RectNode::new());
+        world.solvers.insert(root, CachingSolver::default());
+
+        tree.layout_mut(&mut world);
+        assert_eq!(tree.get(&root).size(), Size::new(50.0, 20.0));
+        assert_eq!(world.solvers[&root].computes, 1);
+
+        // Same constraint, the cached size is reused.
+        tree.schedule_relayout(root);
+        tree.layout_mut(&mut world);
+        assert_eq!(tree.get(&root).size(), Size::new(50.0, 20.0));
+        assert_eq!(world.solvers[&root].computes, 1);
+
+        // The immutable path does not touch the cache.
+        tree.schedule_relayout(root);
+        tree.layout(&world);
+        assert_eq!(world.solvers[&root].computes, 1);
+    }
+
+    #[test]
+    fn test_root_constraint() {
+        let world = StackWorld::new(100.0);
+        let (mut tree, window_a, _) = create_stack_tree();
+        let window_b = tree.insert(RectNode::new());
+        let leaf = tree.insert(RectNode::new().with_parent(window_b));
+        tree.layout(&world);
+
+        assert!(tree.set_root_constraint(
+            window_a,
+            Constraint::fixed_width(300.0)
+        ));
+        assert!(tree.set_root_constraint(
+            window_b,
+            Constraint::fixed_width(200.0)
+        ));
+        assert!(!tree.set_root_constraint(
+            leaf,
+            Constraint::fixed_width(200.0)
+        ));
+        tree.layout(&world);
+
+        assert_eq!(tree.get(&window_a).size().width, 300.0);
+        assert_eq!(tree.get(&window_b).size().width, 200.0);
+        assert_eq!(tree.get(&leaf).size().width, 200.0);
+
+        // Setting the same constraint again is a no-op.
+        tree.set_root_constraint(
+            window_a,
+            Constraint::fixed_width(300.0),
+        );
+        assert!(!tree.needs_relayout());
+    }
+
+    /// Leaves take their width from a shared cursor, making the
+    /// result depend on the build order.
+    #[cfg_attr(feature = "debug-layout", allow(dead_code))]
+    #[derive(Default)]
+    struct CursorWorld {
+        cursor: Cell<f64>,
+    }
+
+    impl LayoutWorld for CursorWorld {
+        fn get_solver(&self, _id: &NodeId) -> &dyn LayoutSolver {
+            self
+        }
+    }
+
+    impl LayoutSolver for CursorWorld {
+        fn build(
+            &self,
+            node: &RectNode,
+            _tree: &Rectree,
+            positioner: &mut Positioner,
+        ) -> Size {
+            if !node.children().is_empty() {
+                for child in node.children() {
+                    positioner.set(*child, Vec2::ZERO);
+                }
+                return Size::ZERO;
+            }
+
+            let width = self.cursor.get();
+            self.cursor.set(width + 1.0);
+            Size::new(width, 10.0)
+        }
+    }
+
+    // The cursor is intentionally not idempotent.
+    #[cfg(not(feature = "debug-layout"))]
+    #[test]
+    fn test_deterministic_build_order() {
+        /// Builds the same structure after inserting and removing
+        /// `churn` nodes, returning the leaf sizes in insertion
+        /// order.
+        fn leaf_sizes(churn: usize) -> Vec<Size> {
+            let mut tree = Rectree::new();
+            let ids = (0..churn)
+                .map(|_| tree.insert(RectNode::new()))
+                .collect::<Vec<_>>();
+            for id in ids.iter() {
+                tree.remove(id);
+            }
+
+            let root = tree.insert(RectNode::new());
+            let leaves = (0..5)
+                .map(|_| {
+                    tree.insert(RectNode::new().with_parent(root))
+                })
+                .collect::<Vec<_>>();
+            tree.layout(&CursorWorld::default());
+
+            leaves.iter().map(|id| tree.get(id).size()).collect()
+        }
+
+        let expected = leaf_sizes(0);
+        assert_eq!(leaf_sizes(6), expected);
+        assert_eq!(leaf_sizes(11), expected);
+    }
+
+    #[test]
+    fn test_measure_child() {
+        let world = StackWorld::new(100.0);
+        let (mut tree, _, ids) = create_stack_tree();
+        tree.layout(&world);
+
+        let column = ids[1];
+        let leaf = ids[2];
+        let before = format!("{tree:?}");
+
+        let size = tree.measure_child(
+            leaf,
+            &world,
+            Constraint::fixed_width(30.0),
+        );
+        assert_eq!(size, Size::new(30.0, 10.0));
+
+        // Children keep their current sizes.
+        let size = tree.measure_child(
+            column,
+            &world,
+            Constraint::fixed_width(40.0),
+        );
+        assert_eq!(size, Size::new(40.0, 100.0));
+
+        assert_eq!(format!("{tree:?}"), before);
+    }
+
+    #[test]
+    fn test_scale_all() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let root = world.insert_node(
+            &mut tree,
+            RectNode::from_translation((10.0, 20.0))
+                .with_padding(Insets::uniform(4.0)),
+            Stack,
+        );
+        let column = world.insert_node(
+            &mut tree,
+            RectNode::new()
+                .with_parent(root)
+                .with_margin(Insets::uniform(2.0)),
+            Stack,
+        );
+        let mut ids = vec![root, column];
+        for _ in 0..3 {
+            ids.push(world.insert(
+                &mut tree,
+                Some(column),
+                Fixed(Size::new(10.0, 10.0)),
+            ));
+        }
+        tree.set_root_constraint(
+            root,
+            Constraint::loose_width(300.0),
+        );
+        tree.layout(&world);
+
+        let geometry = |tree: &Rectree| {
+            ids.iter()
+                .map(|id| {
+                    let node = tree.get(id);
+                    (node.world_rect(), node.parent_constraint())
+                })
+                .collect::<Vec<_>>()
+        };
+        let original = geometry(&tree);
+
+        tree.scale_all(2.0);
+        assert!(tree.needs_relayout());
+        tree.layout(&world);
+        assert_eq!(
+            tree.get(&ids[2]).world_rect(),
+            original[2].0.scale_from_origin(2.0)
+        );
+
+        tree.scale_all(0.5);
+        tree.layout(&world);
+
+        for (
+            (rect, constraint),
+            (expected_rect, expected_constraint),
+        ) in geometry(&tree).into_iter().zip(original)
+        {
+            let diff = [
+                rect.x0 - expected_rect.x0,
+                rect.y0 - expected_rect.y0,
+                rect.x1 - expected_rect.x1,
+                rect.y1 - expected_rect.y1,
+            ];
+            assert!(diff.iter().all(|d| d.abs() < 1e-9));
+            assert_eq!(constraint, expected_constraint);
+        }
+
+        // Nothing changes, nothing is flagged.
+        tree.take_dirty_region();
+        let epoch = tree.current_epoch();
+        tree.scale_all(1.0);
+        assert!(!tree.needs_relayout());
+        assert_eq!(tree.current_epoch(), epoch);
+        assert_eq!(tree.take_dirty_region(), None);
+    }
+
+    #[test]
+    fn test_scale_all_unchanged_nodes() {
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+        let root = world.insert(
+            &mut tree,
+            None,
+            TestSolver::Fixed(Size::ZERO),
+        );
+        tree.layout(&world);
+        tree.take_dirty_region();
+
+        // A zero-sized node at the origin keeps its geometry.
+        let epoch = tree.current_epoch();
+        tree.scale_all(2.0);
+        assert_eq!(tree.current_epoch(), epoch);
+        assert_eq!(
+            tree.size_changed_since(&root, epoch),
+            Some(false)
+        );
+        assert_eq!(tree.take_dirty_region(), None);
+    }
+
+    /// Compares [`LayoutPass::translation_stack`] against a
+    /// [`BTreeSet`] for 1k dirty nodes.
+    ///
+    /// Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_translation_stack() {
+        extern crate std;
+        use std::println;
+        use std::time::Instant;
+
+        const ITERATIONS: u32 = 1000;
+
+        // A 4-ary tree with 1k nodes.
+        let mut tree = Rectree::new();
+        let mut ids = vec![tree.insert(RectNode::new())];
+        for i in 0..999 {
+            let parent = ids[i / 4];
+            ids.push(
+                tree.insert(RectNode::new().with_parent(parent)),
+            );
+        }
+        // Scatter the depths and mark every node twice, like a real
+        // pass would.
+        ids.extend_from_within(..);
+        let len = ids.len();
+        for i in 0..len {
+            ids.swap(i, (i * 7919) % len);
+        }
+        let depth_nodes = ids
+            .iter()
+            .map(|id| DepthNode::new(tree.get(id), *id))
+            .collect::<Vec<_>>();
+
+        let start = Instant::now();
+        let mut btree_order = Vec::new();
+        for _ in 0..ITERATIONS {
+            let mut set = BTreeSet::new();
+            for depth_node in depth_nodes.iter() {
+                set.insert(*depth_node);
+            }
+            btree_order = set.into_iter().collect();
+        }
+        let btree = start.elapsed() / ITERATIONS;
+
+        let start = Instant::now();
+        let mut vec_order = Vec::new();
+        for _ in 0..ITERATIONS {
+            let mut pass = LayoutPass::default();
+            for depth_node in depth_nodes.iter() {
+                pass.translation_stack.push(*depth_node);
+            }
+            pass.sort_translation_stack();
+            vec_order = pass.translation_stack;
+        }
+        let vec = start.elapsed() / ITERATIONS;
+
+        println!("BTreeSet: {btree:?}, sorted Vec: {vec:?}");
+        assert_eq!(vec_order, btree_order);
+    }
+
+    #[test]
+    fn test_layout_budgeted() {
+        let mut world = StackWorld::new(100.0);
+
+        let (mut budgeted, budgeted_root, ids) = create_stack_tree();
+        let (mut full, full_root, _) = create_stack_tree();
+        budgeted.layout(&world);
+        full.layout(&world);
+
+        // Reflow the entire tree.
+        world.root_width = 200.0;
+        budgeted.schedule_relayout(budgeted_root);
+        full.schedule_relayout(full_root);
+
+        full.layout(&world);
+
+        // 111 nodes to constrain and build, 3 chunks in total.
+        assert!(!budgeted.layout_budgeted(&world, 80).is_complete());
+        assert!(budgeted.needs_relayout());
+        assert!(!budgeted.layout_budgeted(&world, 80).is_complete());
+        assert_eq!(
+            budgeted.layout_budgeted(&world, 80),
+            LayoutProgress::Complete
+        );
+        assert!(!budgeted.needs_relayout());
+
+        for id in ids {
+            let a = budgeted.get(&id);
+            let b = full.get(&id);
+            assert_eq!(a.size(), b.size());
+            assert_eq!(a.world_translation(), b.world_translation());
+        }
+        assert_eq!(budgeted.get(&budgeted_root).size().width, 200.0);
+    }
+
+    #[test]
+    fn test_layout_memo() {
+        let mut world = StackWorld::new(100.0);
+        let mut tree = Rectree::new();
+
+        let root = tree.insert(RectNode::new().with_memo(2));
+        let leaf = tree
+            .insert(RectNode::new().with_memo(2).with_parent(root));
+
+        tree.layout(&world);
+        assert_eq!(world.builds.get(), 2 * RUNS);
+
+        let mut toggle_width = |tree: &mut Rectree, width| {
+            world.root_width = width;
+            tree.schedule_relayout(root);
+            tree.layout(&world);
+            assert_eq!(tree.get(&leaf).size().width, width);
+            world.builds.get() / RUNS
+        };
+
+        // The root is always rebuilt since it is scheduled.
+        assert_eq!(toggle_width(&mut tree, 200.0), 4);
+        // Both widths are now memoized for the leaf.
+        assert_eq!(toggle_width(&mut tree, 100.0), 5);
+        assert_eq!(toggle_width(&mut tree, 200.0), 6);
+
+        // Scheduling the leaf invalidates its memo.
+        tree.schedule_relayout(leaf);
+        assert_eq!(toggle_width(&mut tree, 100.0), 8);
+    }
+
+    #[test]
+    fn test_constraint_tight() {
+        let constraint = Constraint::fixed(100.0, 50.0);
+        assert!(constraint.is_tight_width());
+        assert!(constraint.is_tight_height());
+
+        // Tight bounds override the preferred size.
+        assert_eq!(
+            constraint.resolve(Size::new(20.0, 80.0)),
+            Size::new(100.0, 50.0)
+        );
+
+        let constraint = Constraint::fixed_width(100.0);
+        assert!(constraint.is_tight_width());
+        assert!(!constraint.is_tight_height());
+        assert_eq!(
+            constraint.resolve(Size::new(20.0, 80.0)),
+            Size::new(100.0, 80.0)
+        );
+    }
+
+    #[test]
+    fn test_constraint_loose() {
+        let constraint = Constraint::loose(100.0, 50.0);
+        assert!(!constraint.is_tight_width());
+        assert!(!constraint.is_tight_height());
+
+        // Loose bounds only cap the preferred size.
+        assert_eq!(
+            constraint.resolve(Size::new(20.0, 80.0)),
+            Size::new(20.0, 50.0)
+        );
+
+        let constraint = Constraint::loose_height(50.0);
+        assert_eq!(constraint.width, None);
+        assert_eq!(constraint.resolve_width(200.0), 200.0);
+        assert_eq!(constraint.resolve_height(20.0), 20.0);
+        assert_eq!(constraint.resolve_height(80.0), 50.0);
+        assert!(constraint.is_loose_height());
+
+        // Fixing one axis keeps the other loose.
+        let constraint = Constraint::loose(100.0, 50.0)
+            .with_fixed_width(30.0)
+            .with_fixed_height(20.0)
+            .loosen()
+            .with_fixed_width(10.0);
+        assert_eq!(constraint.width, Some(10.0));
+        assert!(!constraint.is_loose_width());
+        assert!(constraint.is_loose_height());
+    }
+
+    #[test]
+    fn test_constraint_intersect() {
+        let flexible = Constraint::flexible();
+        let fixed = |w| Constraint::fixed_width(w);
+        let loose = |w| Constraint {
+            width: Some(w),
+            loose_width: true,
+            ..Default::default()
+        };
+
+        let cases = [
+            (flexible, flexible, flexible),
+            (flexible, fixed(10.0), fixed(10.0)),
+            (flexible, loose(10.0), loose(10.0)),
+            (fixed(10.0), fixed(20.0), fixed(10.0)),
+            (fixed(20.0), loose(10.0), loose(10.0)),
+            (fixed(10.0), loose(20.0), fixed(10.0)),
+            (loose(10.0), loose(20.0), loose(10.0)),
+            (fixed(10.0), loose(10.0), fixed(10.0)),
+        ];
+        for (a, b, expected) in cases {
+            // Symmetric and independent per axis.
+            assert_eq!(a.intersect(b), expected);
+            assert_eq!(b.intersect(a), expected);
+
+            let transpose = |c: Constraint| Constraint {
+                width: c.height,
+                height: c.width,
+                loose_width: c.loose_height,
+                loose_height: c.loose_width,
+            };
+            assert_eq!(
+                transpose(a).intersect(transpose(b)),
+                transpose(expected)
+            );
+        }
+
+        // Both axes at once.
+        assert_eq!(
+            Constraint::fixed(100.0, 50.0)
+                .intersect(Constraint::loose(80.0, 60.0)),
+            Constraint {
+                width: Some(80.0),
+                height: Some(50.0),
+                loose_width: true,
+                loose_height: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_constraint_loosen_tighten() {
+        let tight = Constraint::fixed(100.0, 50.0);
+        let loose = tight.loosen();
+
+        assert_eq!(loose, Constraint::loose(100.0, 50.0));
+        assert_ne!(loose, tight);
+        assert_eq!(loose.tighten(), tight);
+
+        // Flexible constraints are never tight.
+        let flexible = Constraint::flexible();
+        assert!(!flexible.is_tight_width());
+        assert!(!flexible.tighten().is_tight_height());
+        assert_eq!(
+            flexible.resolve(Size::new(20.0, 80.0)),
+            Size::new(20.0, 80.0)
+        );
+    }
+
+    /// Creates a constrained root with an oversized child,
+    /// returning the tree, the world and the child id.
+    fn create_overflow_tree() -> (Rectree, TestWorld, NodeId) {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let root = world.insert(
+            &mut tree,
+            None,
+            Constrained(Size::new(100.0, 100.0)),
+        );
+        let child = world.insert(
+            &mut tree,
+            Some(root),
+            Fixed(Size::new(150.0, 50.0)),
+        );
+        world.insert(
+            &mut tree,
+            Some(root),
+            Fixed(Size::new(50.0, 50.0)),
+        );
+
+        (tree, world, child)
+    }
+
+    #[test]
+    fn test_overflow_trust() {
+        let (mut tree, world, child) = create_overflow_tree();
+
+        assert_eq!(tree.constraint_policy(), ConstraintPolicy::Trust);
+        tree.layout(&world);
+
+        let overflows = tree.take_overflows();
+        assert_eq!(
+            overflows,
+            vec![Overflow {
+                id: child,
+                constraint: Constraint::fixed(100.0, 100.0),
+                size: Size::new(150.0, 50.0),
+            }]
+        );
+        // Size is retained.
+        assert_eq!(tree.get(&child).size(), Size::new(150.0, 50.0));
+        assert!(tree.take_overflows().is_empty());
+
+        // Untaken overflows are discarded by the next pass.
+        tree.schedule_relayout(child);
+        tree.layout(&world);
+        tree.layout(&world);
+        assert!(tree.take_overflows().is_empty());
+    }
+
+    #[test]
+    fn test_overflow_clamp() {
+        let (mut tree, world, child) = create_overflow_tree();
+
+        tree.set_constraint_policy(ConstraintPolicy::Clamp);
+        tree.layout(&world);
+
+        assert_eq!(tree.take_overflows().len(), 1);
+        assert_eq!(tree.get(&child).size(), Size::new(100.0, 50.0));
+
+        // Within the loose bound, nothing is reported.
+        let overflows = Constraint::loose(100.0, 100.0)
+            .overflows(Size::new(100.0 + 1e-9, 20.0));
+        assert!(!overflows);
+    }
+
+    #[test]
+    fn test_overflow_clamp_no_cascade() {
+        let (mut tree, mut world, child) = create_overflow_tree();
+
+        tree.set_constraint_policy(ConstraintPolicy::Clamp);
+        tree.layout(&world);
+
+        // Still clamped to the same size.
+        world
+            .solvers
+            .insert(child, TestSolver::Fixed(Size::new(200.0, 50.0)));
+        tree.schedule_relayout(child);
+
+        let mut hooks = RecordHooks::default();
+        tree.layout_with_hooks(&world, &mut hooks);
+
+        assert_eq!(tree.take_overflows().len(), 1);
+        assert_eq!(tree.get(&child).size(), Size::new(100.0, 50.0));
+        let builds = hooks
+            .events
+            .iter()
+            .filter(|e| matches!(e, HookEvent::BuildStart(_)))
+            .count();
+        assert_eq!(builds, 1);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "overflows its constraint")]
+    fn test_overflow_debug_panic() {
+        let (mut tree, world, _) = create_overflow_tree();
+
+        tree.set_constraint_policy(ConstraintPolicy::DebugPanic);
+        tree.layout(&world);
+    }
+
+    #[test]
+    fn test_absolute_position() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let root = world.insert_node(
+            &mut tree,
+            RectNode::from_translation((100.0, 100.0)),
+            Stack,
+        );
+        let column = world.insert(&mut tree, Some(root), Stack);
+        let leaf_a = world.insert(
+            &mut tree,
+            Some(column),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+        let absolute = world.insert_node(
+            &mut tree,
+            RectNode::from_translation((5.0, 5.0))
+                .with_position_mode(PositionMode::Absolute)
+                .with_parent(column),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+        let leaf_b = world.insert(
+            &mut tree,
+            Some(column),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+
+        tree.layout(&world);
+
+        // The absolute child does not contribute to the height.
+        assert_eq!(tree.get(&column).size(), Size::new(10.0, 20.0));
+        // Relative to the root instead of the column.
+        assert_eq!(
+            tree.get(&absolute).world_translation(),
+            Vec2::new(105.0, 105.0)
+        );
+
+        tree.take_layout_changes();
+
+        // Resize a sibling.
+        world.solvers.insert(leaf_a, Fixed(Size::new(10.0, 30.0)));
+        tree.schedule_relayout(leaf_a);
+        tree.layout(&world);
+
+        assert_eq!(tree.get(&column).size(), Size::new(10.0, 40.0));
+        assert_eq!(
+            tree.get(&leaf_b).world_translation(),
+            Vec2::new(100.0, 130.0)
+        );
+        assert_eq!(
+            tree.get(&absolute).world_translation(),
+            Vec2::new(105.0, 105.0)
+        );
+        assert!(
+            tree.take_layout_changes()
+                .repositioned
+                .iter()
+                .all(|c| c.id != absolute)
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "is linked but does not exist in tree")]
+    fn test_propagate_dangling_child() {
+        let mut tree = Rectree::new();
+        let root = tree.insert(RectNode::new());
+        let dangling = tree.insert(RectNode::new());
+        tree.remove(&dangling);
+
+        tree.link_child_unchecked(&root, dangling);
+        tree.propagate_translation(root, None);
+    }
+
+    #[test]
+    fn test_margin_in_stack() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let column = world.insert(&mut tree, None, Stack);
+        let margined = world.insert_node(
+            &mut tree,
+            RectNode::new()
+                .with_margin(Insets::new(5.0, 1.0, 5.0, 3.0))
+                .with_parent(column),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+        let below = world.insert(
+            &mut tree,
+            Some(column),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+
+        tree.layout(&world);
+
+        // The margin is reported upwards as part of the outer size.
+        let node = tree.get(&margined);
+        assert_eq!(node.size(), Size::new(10.0, 10.0));
+        assert_eq!(node.outer_size(), Size::new(20.0, 14.0));
+        assert_eq!(tree.get(&column).size(), Size::new(20.0, 24.0));
+
+        // Only the content is offset by the margin.
+        assert_eq!(node.translation(), Vec2::ZERO);
+        assert_eq!(node.world_translation(), Vec2::new(5.0, 1.0));
+        assert_eq!(
+            node.world_rect(),
+            Rect::new(5.0, 1.0, 15.0, 11.0)
+        );
+        assert_eq!(
+            tree.get(&below).world_translation(),
+            Vec2::new(0.0, 14.0)
+        );
+    }
+
+    #[test]
+    fn test_margin_in_center() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let root = world.insert(
+            &mut tree,
+            None,
+            Constrained(Size::new(100.0, 100.0)),
+        );
+        let center = world.insert(&mut tree, Some(root), Center);
+        let margined = world.insert_node(
+            &mut tree,
+            RectNode::new()
+                .with_margin(Insets::new(10.0, 0.0, 0.0, 0.0))
+                .with_parent(center),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+
+        tree.layout(&world);
+
+        // The constraint is deflated by the margin.
+        let node = tree.get(&margined);
+        assert_eq!(
+            node.parent_constraint(),
+            Constraint::fixed(90.0, 100.0)
+        );
+
+        // Centered as a 20x10 rect, with the content on the right.
+        assert_eq!(node.translation(), Vec2::new(40.0, 45.0));
+        assert_eq!(
+            node.world_rect(),
+            Rect::new(50.0, 45.0, 60.0, 55.0)
+        );
+    }
+
+    #[test]
+    fn test_padding() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let root = world.insert(
+            &mut tree,
+            None,
+            Constrained(Size::new(100.0, 100.0)),
+        );
+        let column = world.insert_node(
+            &mut tree,
+            RectNode::new()
+                .with_padding(Insets::new(1.0, 2.0, 3.0, 4.0))
+                .with_margin(10.0)
+                .with_parent(root),
+            Stack,
+        );
+        let child = world.insert(
+            &mut tree,
+            Some(column),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+
+        tree.layout(&world);
+
+        // Children are constrained within both margin and padding.
+        assert_eq!(
+            tree.get(&child).parent_constraint(),
+            Constraint::fixed(76.0, 74.0)
+        );
+
+        // Size includes the padding, the outer size also the margin.
+        let node = tree.get(&column);
+        assert_eq!(node.size(), Size::new(14.0, 16.0));
+        assert_eq!(node.outer_size(), Size::new(34.0, 36.0));
+        assert_eq!(
+            node.world_rect(),
+            Rect::new(10.0, 10.0, 24.0, 26.0)
+        );
+
+        // Children are offset by the padding.
+        assert_eq!(
+            tree.get(&child).translation(),
+            Vec2::new(1.0, 2.0)
+        );
+        assert_eq!(
+            tree.get(&child).world_translation(),
+            Vec2::new(11.0, 12.0)
+        );
+        assert!(tree.take_overflows().is_empty());
+    }
+
+    #[test]
+    fn test_padding_larger_than_constraint() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let root = world.insert(
+            &mut tree,
+            None,
+            Constrained(Size::new(10.0, 10.0)),
+        );
+        let column = world.insert_node(
+            &mut tree,
+            RectNode::new().with_padding(8.0).with_parent(root),
+            Stack,
+        );
+        let child = world.insert(&mut tree, Some(column), Stack);
+
+        tree.layout(&world);
+
+        // Children are constrained to zero.
+        assert_eq!(
+            tree.get(&child).parent_constraint(),
+            Constraint::fixed(0.0, 0.0)
+        );
+
+        // The padding is preserved, overflowing the constraint.
+        assert_eq!(tree.get(&column).size(), Size::new(16.0, 16.0));
+        let overflows = tree.take_overflows();
+        assert_eq!(overflows.len(), 1);
+        assert_eq!(overflows[0].id, column);
+
+        // Unless clamped.
+        tree.set_constraint_policy(ConstraintPolicy::Clamp);
+        tree.schedule_relayout(column);
+        tree.layout(&world);
+        assert_eq!(tree.get(&column).size(), Size::new(10.0, 10.0));
+    }
+
+    #[test]
+    fn test_prev_geometry() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let column = world.insert(&mut tree, None, Stack);
+        let leaf_a = world.insert(
+            &mut tree,
+            Some(column),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+        let leaf_b = world.insert(
+            &mut tree,
+            Some(column),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+        tree.layout(&world);
+        tree.layout(&world);
+
+        let node = tree.get(&leaf_b);
+        assert_eq!(node.prev_world_rect(), node.world_rect());
+
+        // Resize the first leaf, pushing the second one down.
+        world.solvers.insert(leaf_a, Fixed(Size::new(20.0, 20.0)));
+        tree.schedule_relayout(leaf_a);
+        tree.layout(&world);
+
+        let node = tree.get(&leaf_a);
+        assert_eq!(node.prev_size(), Size::new(10.0, 10.0));
+        assert_eq!(node.size(), Size::new(20.0, 20.0));
+
+        let node = tree.get(&leaf_b);
+        assert_eq!(
+            node.prev_world_rect(),
+            Rect::new(0.0, 10.0, 10.0, 20.0)
+        );
+        assert_eq!(
+            node.world_rect(),
+            Rect::new(0.0, 20.0, 10.0, 30.0)
+        );
+        assert_eq!(
+            tree.interpolated_world_rect(&leaf_b, 0.5),
+            Rect::new(0.0, 15.0, 10.0, 25.0)
+        );
+
+        // Nothing changed, both geometry are now equal.
+        tree.layout(&world);
+        for id in [leaf_a, leaf_b] {
+            let node = tree.get(&id);
+            assert_eq!(node.prev_world_rect(), node.world_rect());
+            assert_eq!(
+                tree.interpolated_world_rect(&id, 0.5),
+                node.world_rect()
+            );
+        }
+    }
+
+    #[test]
+    fn test_dirty_region() {
+        use TestSolver::*;
+
+        let mut tree = Rectree::new();
+        let mut world = TestWorld::default();
+
+        let column = world.insert(&mut tree, None, Stack);
+        let leaf_a = world.insert(
+            &mut tree,
+            Some(column),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+        let leaf_b = world.insert(
+            &mut tree,
+            Some(column),
+            Fixed(Size::new(10.0, 10.0)),
+        );
+        tree.layout(&world);
+        assert!(tree.take_dirty_region().is_some());
+
+        // Nothing changed.
+        tree.layout(&world);
+        assert!(tree.take_dirty_region().is_none());
+
+        // Shrinking the first leaf moves the second one up.
+        world.solvers.insert(leaf_a, Fixed(Size::new(10.0, 5.0)));
+        tree.schedule_relayout(leaf_a);
+        tree.layout(&world);
+
+        // Covers the old and new position of the second leaf.
+        let region = tree.take_dirty_region().unwrap();
+        assert!(
+            region.contains_rect(Rect::new(0.0, 10.0, 10.0, 20.0))
+        );
+        assert!(region.contains_rect(tree.get(&leaf_b).world_rect()));
+        assert_eq!(region, Rect::new(0.0, 0.0, 10.0, 20.0));
+        assert!(tree.take_dirty_region().is_none());
+
+        // Removed nodes need to be cleared.
+        tree.remove(&leaf_b);
+        assert_eq!(
+            tree.take_dirty_region(),
+            Some(Rect::new(0.0, 5.0, 10.0, 15.0))
+        );
+    }
+
+    #[test]
+    fn test_propagate_translation_from() {
+        let world = StackWorld::new(100.0);
+        let mut tree = Rectree::new();
+
+        let root =
+            tree.insert(RectNode::from_translation((50.0, 50.0)));
+        let fragment = tree.insert(
+            RectNode::from_translation((0.0, 5.0)).with_parent(root),
+        );
+        let leaf = tree.insert(
+            RectNode::from_translation((1.0, 2.0))
+                .with_parent(fragment),
+        );
+        tree.layout(&world);
+        let placed = [fragment, leaf]
+            .map(|id| tree.get(&id).world_translation());
+
+        // Preview the fragment under a parent at another position.
+        tree.take_layout_changes();
+        let before = format!("{tree:?}");
+        let seed = Vec2::new(-20.0, 300.0);
+        let offset = seed - Vec2::new(50.0, 50.0);
+        assert_eq!(
+            tree.propagate_translation_from(fragment, seed),
+            [
+                (fragment, placed[0] + offset),
+                (leaf, placed[1] + offset)
+            ]
+        );
+
+        // The tree itself is left untouched.
+        assert_eq!(format!("{tree:?}"), before);
+        assert!(tree.take_layout_changes().repositioned.is_empty());
+        for (id, placed) in [fragment, leaf].iter().zip(placed) {
+            assert_eq!(tree.get(id).world_translation(), placed);
+        }
+
+        // Seeding with the actual parent matches the layout.
+        let parent = tree.get(&root).world_translation();
+        assert_eq!(
+            tree.propagate_translation_from(fragment, parent),
+            [(fragment, placed[0]), (leaf, placed[1])]
+        );
+
+        let removed = tree.insert(RectNode::new());
+        tree.remove(&removed);
+        assert!(
+            tree.propagate_translation_from(removed, seed).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_relayout_nested_keeps_translation() {
+        let world = StackWorld::new(100.0);
+        let mut tree = Rectree::new();
+
+        let root =
+            tree.insert(RectNode::from_translation((50.0, 50.0)));
+        let child = tree.insert(RectNode::new().with_parent(root));
+        let leaf = tree.insert(RectNode::new().with_parent(child));
+        tree.layout(&world);
+        let placed = tree.get(&leaf).world_translation();
+
+        // The size of the leaf is unchanged, leaving its ancestors
+        // untouched.
+        tree.schedule_relayout(leaf);
+        tree.layout(&world);
+        assert_eq!(tree.get(&leaf).world_translation(), placed);
+    }
+
+    #[test]
+    fn test_propagate_translation_skips_unchanged() {
+        let mut tree = Rectree::new();
+
+        let root = tree.insert(RectNode::new());
+        let a = tree.insert(
+            RectNode::from_translation((10.0, 0.0)).with_parent(root),
+        );
+        let b = tree.insert(
+            RectNode::from_translation((0.0, 10.0)).with_parent(root),
+        );
+        let a_child = tree.insert(
+            RectNode::from_translation((1.0, 1.0)).with_parent(a),
+        );
+        let b_child = tree.insert(
+            RectNode::from_translation((2.0, 2.0)).with_parent(b),
+        );
+
+        // First propagation visits every node.
+        assert_eq!(tree.propagate_translation(root, None), 5);
+        assert_eq!(
+            tree.get(&b_child).world_translation(),
+            Vec2::new(2.0, 12.0)
+        );
+
+        // Move only `a`, the `b` branch should be skipped.
+        let node = tree.get_mut(&a);
+        node.translation = Vec2::new(20.0, 0.0);
+        node.state.needs_reposition();
+        tree.get_mut(&root).state.needs_reposition();
+
+        // Visits `root`, `a`, `a_child` and `b` (skipped).
+        assert_eq!(tree.propagate_translation(root, None), 4);
+        assert_eq!(
+            tree.get(&a_child).world_translation(),
+            Vec2::new(21.0, 1.0)
+        );
+        assert_eq!(
+            tree.get(&b_child).world_translation(),
+            Vec2::new(2.0, 12.0)
+        );
     }
 }
@@ -7,17 +7,27 @@ use core::fmt::{Display, Formatter};
 use core::ops::Deref;
 
 use alloc::collections::btree_set::BTreeSet;
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
 use alloc::vec;
+use alloc::vec::Vec;
 use hashbrown::HashSet;
+use kurbo::{Rect, Vec2};
 use sparse_map::{Key, SparseMap};
 
-use crate::layout::DepthNode;
-use crate::node::RectNode;
+use crate::layout::{
+    Constraint, ConstraintPolicy, DepthNode, LayoutChanges,
+    LayoutPass, NonConvergence, Overflow, RelayoutReason,
+};
+use crate::node::{NodeRef, RectNode};
 
 pub use kurbo;
 
+pub mod builder;
 pub mod layout;
 pub mod node;
+pub mod snapshot;
+pub mod solvers;
 
 /// A hierarchical tree of rectangular layout nodes.
 ///
@@ -41,6 +51,44 @@ pub struct Rectree {
     /// Deeper nodes are processed first to ensure children are laid
     /// out before their parents.
     scheduled_relayout: BTreeSet<DepthNode>,
+    /// Geometry changes recorded during the last layout pass.
+    ///
+    /// See [`Rectree::take_layout_changes()`].
+    layout_changes: LayoutChanges,
+    /// State of an in-progress layout pass.
+    ///
+    /// See [`Rectree::layout_budgeted()`].
+    layout_pass: LayoutPass,
+    /// Overflows detected during the last layout pass.
+    ///
+    /// See [`Rectree::take_overflows()`].
+    overflows: Vec<Overflow>,
+    /// See [`Rectree::set_constraint_policy()`].
+    constraint_policy: ConstraintPolicy,
+    /// Nodes that did not converge during the last layout pass.
+    ///
+    /// See [`Rectree::take_non_converged()`].
+    non_converged: Vec<NonConvergence>,
+    /// See [`Rectree::set_max_rebuilds()`].
+    max_rebuilds: Option<u32>,
+    /// See [`Rectree::set_max_pass_builds()`].
+    max_pass_builds: Option<u32>,
+    /// Nodes whose previous geometry differs from the current one.
+    ///
+    /// See [`RectNode::prev_world_rect()`].
+    transitioning: Vec<NodeId>,
+    /// See [`Rectree::take_dirty_region()`].
+    dirty_region: Option<Rect>,
+    /// Insertion order assigned to the next inserted node.
+    ///
+    /// See [`DepthNode`].
+    next_insertion_order: u64,
+    /// See [`Rectree::current_epoch()`].
+    epoch: u64,
+    /// See [`Rectree::set_skip_zero_area()`].
+    skip_zero_area: bool,
+    /// See [`Rectree::set_max_nodes()`].
+    max_nodes: Option<usize>,
 }
 
 /// Builders.
@@ -52,35 +100,98 @@ impl Rectree {
         Self::default()
     }
 
+    /// Maximum depth of a node in the tree, see
+    /// [`RectNode::depth()`].
+    pub const MAX_DEPTH: u32 = 4096;
+
     /// Inserts a node into the tree while keeping track of the
     /// parent-child relationship.
     ///
     /// # Panics
     ///
-    /// Panics if an invalid parent [`NodeId`] is used.
-    pub fn insert(&mut self, mut node: RectNode) -> NodeId {
+    /// Panics if an invalid parent [`NodeId`] is used, if the node
+    /// would exceed [`Self::MAX_DEPTH`], or if the tree is full, see
+    /// [`Self::set_max_nodes()`]. See [`Self::try_insert()`] for a
+    /// fallible variant.
+    pub fn insert(&mut self, node: RectNode) -> NodeId {
+        self.try_insert(node).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Inserts a node into the tree while keeping track of the
+    /// parent-child relationship.
+    ///
+    /// The tree is left unchanged if an [`InsertError`] is returned.
+    pub fn try_insert(
+        &mut self,
+        mut node: RectNode,
+    ) -> Result<NodeId, InsertError> {
+        if let Some(max_nodes) = self.max_nodes
+            && self.nodes.len() >= max_nodes
+        {
+            return Err(InsertError::CapacityReached(max_nodes));
+        }
+
+        node.depth = match node.parent {
+            Some(parent) => {
+                let parent_node = self
+                    .try_get(&parent)
+                    .ok_or(InsertError::InvalidParent(parent))?;
+
+                if parent_node.depth >= Self::MAX_DEPTH {
+                    return Err(InsertError::TooDeep);
+                }
+                parent_node.depth + 1
+            }
+            None => 0,
+        };
+        node.prev_size = node.size;
+        node.built_size = node.size;
+        node.prev_world_translation = node.world_translation;
+        node.insertion_order = self.next_insertion_order;
+        self.next_insertion_order += 1;
+        node.relayout_reason = Some(RelayoutReason::Scheduled);
+        self.epoch += 1;
+        node.size_epoch = self.epoch;
+
+        // The parent's memoized results no longer account for all
+        // of its children.
+        if let Some(parent) = node.parent {
+            self.clear_memos(parent);
+        }
+
         let key = self.nodes.insert_with_key(|nodes, key| {
             let id = NodeId(key);
             if let Some(parent) = node.parent {
-                let parent_node =
-                    nodes.get_mut(&parent).unwrap_or_else(|| {
-                        panic!("Invalid parent Id ({parent}).")
-                    });
-
-                parent_node.children.insert(id);
-                node.depth = parent_node.depth + 1;
+                Self::get_node_mut(nodes, &parent).children.push(id);
             } else {
                 // No parent, meaning that it's a root id.
                 self.root_ids.insert(id);
             }
 
-            self.scheduled_relayout
-                .insert(DepthNode::new(node.depth, id));
+            self.scheduled_relayout.insert(DepthNode::new(&node, id));
 
             node
         });
 
-        NodeId(key)
+        Ok(NodeId(key))
+    }
+
+    /// Bounds the number of nodes the tree can hold, or removes the
+    /// bound if `None`, which is the default.
+    ///
+    /// Once the bound is reached, [`Self::try_insert()`] returns
+    /// [`InsertError::CapacityReached`] instead of growing the node
+    /// storage, until nodes are removed. This keeps the memory used
+    /// by the tree bounded, e.g. on embedded targets. Lowering the
+    /// bound below the current number of nodes does not remove any
+    /// node.
+    pub fn set_max_nodes(&mut self, max_nodes: Option<usize>) {
+        self.max_nodes = max_nodes;
+    }
+
+    /// See [`Self::set_max_nodes()`].
+    pub fn max_nodes(&self) -> Option<usize> {
+        self.max_nodes
     }
 
     /// Removes a node and all of its descendants from the tree.
@@ -88,12 +199,17 @@ impl Rectree {
     /// Returns `true` if the node existed and was removed, or `false`
     /// if the given [`NodeId`] does not exist.
     pub fn remove(&mut self, id: &NodeId) -> bool {
+        if let Some(parent) = self.try_get(id).and_then(|n| n.parent)
+        {
+            self.clear_memos(parent);
+        }
+
         if let Some(node) = self.nodes.get(id) {
             if let Some(parent) =
                 node.parent.and_then(|id| self.nodes.get_mut(&id))
             {
                 // Bookeeping.
-                parent.children.remove(id);
+                parent.remove_child(id);
             } else {
                 // No parent, meaning that it's a root id.
                 self.root_ids.remove(id);
@@ -106,6 +222,266 @@ impl Rectree {
         false
     }
 
+    /// Removes a node while preserving its children by moving them
+    /// up to the removed node's parent, or turning them into root
+    /// nodes if the removed node was a root.
+    ///
+    /// The children take the removed node's position among its
+    /// siblings, keeping their order, see [`RectNode::children()`].
+    /// The depths of all moved subtrees are updated accordingly and
+    /// the affected nodes are scheduled for relayout.
+    ///
+    /// Returns `true` if the node existed and was removed, or `false`
+    /// if the given [`NodeId`] does not exist.
+    pub fn remove_node_reparenting(&mut self, id: &NodeId) -> bool {
+        let Some(node) = self.try_get(id) else {
+            return false;
+        };
+        let (parent, depth) = (node.parent, node.depth);
+        let children = node.children.clone();
+        let index = self.child_index(id);
+
+        // Inserted right after the node, which then leaves the gap.
+        for (offset, child) in children.iter().enumerate() {
+            self.move_subtree(
+                child,
+                parent,
+                depth,
+                index + 1 + offset,
+            );
+        }
+
+        let node = self.nodes.remove(id).unwrap();
+        self.scheduled_relayout.remove(&DepthNode::new(&node, *id));
+        self.mark_dirty(node.world_rect());
+        match parent {
+            Some(parent) => {
+                self.get_mut(&parent).remove_child(id);
+            }
+            None => {
+                // No parent, meaning that it's a root id.
+                self.root_ids.remove(id);
+            }
+        }
+
+        true
+    }
+
+    /// Swaps the positions of two nodes in the hierarchy, moving
+    /// each node along with its descendants under the other node's
+    /// parent, at the other node's index among its children.
+    ///
+    /// Swapping two siblings only swaps their indices, see
+    /// [`RectNode::children()`]. The depths of both subtrees are
+    /// updated accordingly and the affected parents are scheduled
+    /// for relayout.
+    ///
+    /// Returns `false` if either [`NodeId`] does not exist, if one
+    /// node is an ancestor of the other, if both nodes are roots, as
+    /// roots are unordered, or if the swap would exceed
+    /// [`Self::MAX_DEPTH`].
+    pub fn swap(&mut self, a: &NodeId, b: &NodeId) -> bool {
+        let (Some(node_a), Some(node_b)) =
+            (self.try_get(a), self.try_get(b))
+        else {
+            return false;
+        };
+        let (parent_a, depth_a) = (node_a.parent, node_a.depth);
+        let (parent_b, depth_b) = (node_b.parent, node_b.depth);
+
+        // Swapping would create a cycle.
+        if self.is_ancestor(a, b) || self.is_ancestor(b, a) {
+            return false;
+        }
+        if let (Some(parent_a), Some(parent_b)) = (parent_a, parent_b)
+            && parent_a == parent_b
+        {
+            let children = &mut self.get_mut(&parent_a).children;
+            let index_a = children.iter().position(|c| c == a);
+            let index_b = children.iter().position(|c| c == b);
+            if let (Some(index_a), Some(index_b)) = (index_a, index_b)
+            {
+                children.swap(index_a, index_b);
+                self.schedule_relayout(parent_a);
+            }
+            return true;
+        }
+        if parent_a.is_none() && parent_b.is_none() {
+            return false;
+        }
+        if self.subtree_height(a) + depth_b > Self::MAX_DEPTH
+            || self.subtree_height(b) + depth_a > Self::MAX_DEPTH
+        {
+            return false;
+        }
+
+        let index_a = self.child_index(a);
+        let index_b = self.child_index(b);
+        // `a` lands right before `b`, which `b` then leaves.
+        self.move_subtree(a, parent_b, depth_b, index_b);
+        self.move_subtree(b, parent_a, depth_a, index_a);
+        true
+    }
+
+    /// Index of a node among the children of its parent, or `0` for
+    /// roots.
+    fn child_index(&self, id: &NodeId) -> usize {
+        self.get(id)
+            .parent
+            .and_then(|parent| {
+                self.get(&parent)
+                    .children
+                    .iter()
+                    .position(|c| c == id)
+            })
+            .unwrap_or(0)
+    }
+
+    /// Moves a node to `index` among the children of its parent,
+    /// shifting the siblings in between, see
+    /// [`RectNode::children()`].
+    ///
+    /// The parent is scheduled for relayout, so that solvers
+    /// depending on the document order, e.g. rows, place the
+    /// children accordingly.
+    ///
+    /// Returns `false` if the [`NodeId`] does not exist, if it is a
+    /// root, as roots are unordered, or if `index` is out of bounds.
+    pub fn set_child_index(
+        &mut self,
+        id: &NodeId,
+        index: usize,
+    ) -> bool {
+        let Some(parent) = self.try_get(id).and_then(|n| n.parent)
+        else {
+            return false;
+        };
+        let parent_node = self.get_mut(&parent);
+        if index >= parent_node.children.len() {
+            return false;
+        }
+
+        if let Some(old_index) = parent_node.remove_child(id) {
+            parent_node.children.insert(index, *id);
+            if old_index != index {
+                self.schedule_relayout(parent);
+            }
+        }
+        true
+    }
+
+    /// Moves a node along with its descendants under a new parent
+    /// at the given depth, without any validation.
+    ///
+    /// The node is inserted at `index` among the children of the new
+    /// parent, clamped to their number, which is ignored for roots.
+    fn move_subtree(
+        &mut self,
+        id: &NodeId,
+        parent: Option<NodeId>,
+        depth: u32,
+        index: usize,
+    ) {
+        let node = self.get_mut(id);
+        let old_parent = core::mem::replace(&mut node.parent, parent);
+        let old_depth = node.depth;
+
+        // Depths must be updated before scheduling, as scheduled
+        // nodes are keyed by their depth.
+        let mut child_stack = vec![*id];
+        while let Some(id) = child_stack.pop() {
+            let node = self.nodes.get(&id).unwrap();
+            let old_key = DepthNode::new(node, id);
+
+            let node = self.get_mut(&id);
+            node.depth = node.depth - old_depth + depth;
+            let new_key = DepthNode::new(node, id);
+            child_stack.extend(node.children());
+
+            // Re-key descendants that were already scheduled.
+            if self.scheduled_relayout.remove(&old_key) {
+                self.scheduled_relayout.insert(new_key);
+            }
+        }
+
+        match old_parent {
+            Some(old_parent) => {
+                self.get_mut(&old_parent).remove_child(id);
+                self.schedule_relayout(old_parent);
+            }
+            None => {
+                self.root_ids.remove(id);
+            }
+        }
+
+        match parent {
+            Some(parent) => {
+                let children = &mut self.get_mut(&parent).children;
+                children.insert(index.min(children.len()), *id);
+                self.schedule_relayout(parent);
+            }
+            None => {
+                self.root_ids.insert(*id);
+                // Root nodes are not constrained by any parent.
+                self.get_mut(id).parent_constraint =
+                    Constraint::default();
+                self.schedule_relayout(*id);
+            }
+        }
+    }
+
+    /// Sets whether [zero-area](RectNode::is_zero_area()) nodes are
+    /// treated as non-participating, which is off by default.
+    ///
+    /// When enabled, such nodes are left out of
+    /// [`Self::collect_world_rects()`] and
+    /// [`Self::query_region()`], since they can never be seen or
+    /// hit. Their children are still visited, unless the node
+    /// [clips its children](RectNode::clip_children()), in which
+    /// case the whole subtree is skipped.
+    pub fn set_skip_zero_area(&mut self, skip: bool) {
+        self.skip_zero_area = skip;
+    }
+
+    /// See [`Self::set_skip_zero_area()`].
+    pub fn skip_zero_area(&self) -> bool {
+        self.skip_zero_area
+    }
+
+    /// Returns `true` if `ancestor` is a strict ancestor of `id`.
+    fn is_ancestor(&self, ancestor: &NodeId, id: &NodeId) -> bool {
+        let mut current = self.try_get(id).and_then(|n| n.parent);
+
+        while let Some(id) = current {
+            if id == *ancestor {
+                return true;
+            }
+            current = self.try_get(&id).and_then(|n| n.parent);
+        }
+
+        false
+    }
+
+    /// Returns the depth of the deepest descendant of a node,
+    /// relative to the node itself.
+    fn subtree_height(&self, id: &NodeId) -> u32 {
+        let depth = self.get(id).depth;
+        let mut height = 0;
+        let mut child_stack = vec![*id];
+
+        while let Some(id) = child_stack.pop() {
+            let Some(node) = Self::get_linked_node(&self.nodes, &id)
+            else {
+                continue;
+            };
+
+            height = height.max(node.depth - depth);
+            child_stack.extend(node.children());
+        }
+
+        height
+    }
+
     /// Recursively removes a node and all of its descendants.
     ///
     /// This is an internal helper used by [`Self::remove()`].
@@ -115,10 +491,18 @@ impl Rectree {
         let mut child_stack = vec![*id];
 
         while let Some(id) = child_stack.pop() {
-            let node = self.get(&id);
+            let Some(node) = Self::get_linked_node(&self.nodes, &id)
+            else {
+                continue;
+            };
 
             child_stack.extend(node.children());
+            let rect = node.world_rect();
+            self.mark_dirty(rect);
             self.nodes.remove(&id);
+            // Only the top node could be a root, but a stale root id
+            // would leave traversals hitting a removed node.
+            self.root_ids.remove(&id);
         }
     }
 }
@@ -157,6 +541,25 @@ impl Rectree {
         })
     }
 
+    /// Returns a [`NodeRef`] to a node if it exists.
+    pub fn try_node_ref(&self, id: &NodeId) -> Option<NodeRef<'_>> {
+        self.nodes.contains(id).then_some(NodeRef {
+            tree: self,
+            id: *id,
+        })
+    }
+
+    /// Returns a [`NodeRef`] to a node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given [`NodeId`] does not exist in the tree.
+    pub fn node_ref(&self, id: &NodeId) -> NodeRef<'_> {
+        self.try_node_ref(id).unwrap_or_else(|| {
+            panic!("{id} does not exists in tree.")
+        })
+    }
+
     /// Returns the set of root node identifiers.
     ///
     /// Root nodes are nodes that do not have a parent.
@@ -164,6 +567,249 @@ impl Rectree {
         &self.root_ids
     }
 
+    /// Returns the number of children of a node.
+    ///
+    /// Non-existent nodes are treated as having no children.
+    pub fn child_count(&self, id: &NodeId) -> usize {
+        self.try_get(id).map_or(0, |node| node.children.len())
+    }
+
+    /// Returns `true` if a node has at least one child.
+    ///
+    /// Non-existent nodes are treated as having no children.
+    pub fn has_children(&self, id: &NodeId) -> bool {
+        self.child_count(id) > 0
+    }
+
+    /// Returns `true` if a node has no children.
+    ///
+    /// Non-existent nodes are treated as having no children, making
+    /// them leaves.
+    pub fn is_leaf(&self, id: &NodeId) -> bool {
+        !self.has_children(id)
+    }
+
+    /// Iterates over every leaf node, i.e. nodes without children,
+    /// across all roots.
+    ///
+    /// A node whose children have all been removed is a leaf. The
+    /// order is unspecified.
+    pub fn leaves(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.leaves_from(self.root_ids.iter().copied().collect())
+    }
+
+    /// Same as [`Self::leaves()`], but scoped to the subtree rooted
+    /// at `id`, including `id` itself if it has no children.
+    ///
+    /// Yields nothing if the node does not exist.
+    pub fn leaves_of(
+        &self,
+        id: &NodeId,
+    ) -> impl Iterator<Item = NodeId> + '_ {
+        let stack = if self.nodes.contains(id) {
+            vec![*id]
+        } else {
+            Vec::new()
+        };
+        self.leaves_from(stack)
+    }
+
+    /// Lazily traverses the subtrees of the nodes in `stack`,
+    /// yielding their leaves.
+    fn leaves_from(
+        &self,
+        mut stack: Vec<NodeId>,
+    ) -> impl Iterator<Item = NodeId> + '_ {
+        core::iter::from_fn(move || {
+            while let Some(id) = stack.pop() {
+                let Some(node) =
+                    Self::get_linked_node(&self.nodes, &id)
+                else {
+                    continue;
+                };
+
+                if node.children.is_empty() {
+                    return Some(id);
+                }
+                stack.extend(node.children());
+            }
+
+            None
+        })
+    }
+
+    /// Linearly interpolates between the previous and current world
+    /// space [`Rect`] of a node, where `t` of `0.0` yields
+    /// [`RectNode::prev_world_rect()`] and `1.0` yields
+    /// [`RectNode::world_rect()`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given [`NodeId`] does not exist in the tree.
+    pub fn interpolated_world_rect(
+        &self,
+        id: &NodeId,
+        t: f64,
+    ) -> Rect {
+        let node = self.get(id);
+        let prev = node.prev_world_rect();
+        let curr = node.world_rect();
+        let lerp = |a: f64, b: f64| a + (b - a) * t;
+
+        Rect::new(
+            lerp(prev.x0, curr.x0),
+            lerp(prev.y0, curr.y0),
+            lerp(prev.x1, curr.x1),
+            lerp(prev.y1, curr.y1),
+        )
+    }
+
+    /// Collects the world space [`Rect`] of every node into `out`,
+    /// in painter's order.
+    ///
+    /// Nodes are traversed depth first from each root, so parents
+    /// always come before their children, and siblings come in
+    /// document order, see [`RectNode::children()`]. The order
+    /// between roots is unspecified.
+    ///
+    /// `out` is cleared first, allowing the same buffer to be reused
+    /// across frames without reallocating.
+    ///
+    /// See [`Self::set_skip_zero_area()`] to leave out zero-area
+    /// nodes.
+    pub fn collect_world_rects(&self, out: &mut Vec<(NodeId, Rect)>) {
+        out.clear();
+        let mut node_stack = Vec::new();
+
+        for root_id in self.root_ids.iter() {
+            node_stack.push(*root_id);
+
+            while let Some(id) = node_stack.pop() {
+                let Some(node) =
+                    Self::get_linked_node(&self.nodes, &id)
+                else {
+                    continue;
+                };
+
+                if self.skip_zero_area && node.is_zero_area() {
+                    if !node.clip_children {
+                        node_stack
+                            .extend(node.children().iter().rev());
+                    }
+                    continue;
+                }
+
+                out.push((id, node.world_rect()));
+                node_stack.extend(node.children().iter().rev());
+            }
+        }
+    }
+
+    /// Collects every node whose world space [`Rect`] overlaps
+    /// `region`, see [`Rect::overlaps()`].
+    ///
+    /// Nodes are traversed in the same order as
+    /// [`Self::collect_world_rects()`]. Descendants of nodes that
+    /// [clip their children](RectNode::clip_children()) only match
+    /// within the clipped area, and are skipped entirely if that
+    /// area does not overlap `region`.
+    ///
+    /// See [`Self::set_skip_zero_area()`] to leave out zero-area
+    /// nodes, along with the subtrees they clip.
+    ///
+    /// This visits every unclipped node, for large trees consider
+    /// using a spatial index instead.
+    pub fn query_region(&self, region: Rect) -> Vec<NodeId> {
+        let mut out = Vec::new();
+        // Nodes along with the area their ancestors clip them to.
+        let mut node_stack = Vec::new();
+
+        for root_id in self.root_ids.iter() {
+            node_stack.push((*root_id, None::<Rect>));
+
+            while let Some((id, clip)) = node_stack.pop() {
+                let Some(node) =
+                    Self::get_linked_node(&self.nodes, &id)
+                else {
+                    continue;
+                };
+
+                if self.skip_zero_area && node.is_zero_area() {
+                    // Nothing can be seen through a collapsed clip.
+                    if !node.clip_children {
+                        node_stack.extend(
+                            node.children()
+                                .iter()
+                                .rev()
+                                .map(|child| (*child, clip)),
+                        );
+                    }
+                    continue;
+                }
+
+                let rect = node.world_rect();
+                let visible = match clip {
+                    Some(clip) if !clip.overlaps(rect) => None,
+                    Some(clip) => Some(clip.intersect(rect)),
+                    None => Some(rect),
+                };
+                if visible.is_some_and(|v| v.overlaps(region)) {
+                    out.push(id);
+                }
+
+                let clip = match node.clip_children {
+                    true => match visible {
+                        Some(visible) => Some(visible),
+                        // Fully clipped by an ancestor.
+                        None => continue,
+                    },
+                    false => clip,
+                };
+                if clip.is_some_and(|clip| !clip.overlaps(region)) {
+                    continue;
+                }
+
+                node_stack.extend(
+                    node.children()
+                        .iter()
+                        .rev()
+                        .map(|child| (*child, clip)),
+                );
+            }
+        }
+
+        out
+    }
+
+    /// Takes an immutable snapshot of the world space [`Rect`] and
+    /// depth of every node, in painter's order.
+    ///
+    /// The snapshot is detached from the tree, so it can be shared
+    /// with e.g. a render thread while the tree keeps being mutated.
+    /// See [`Self::collect_world_rects()`] for the order.
+    #[cfg(target_has_atomic = "ptr")]
+    pub fn snapshot_rects(&self) -> Arc<[(NodeId, Rect, u32)]> {
+        let mut node_stack = Vec::new();
+        let mut snapshot = Vec::new();
+
+        for root_id in self.root_ids.iter() {
+            node_stack.push(*root_id);
+
+            while let Some(id) = node_stack.pop() {
+                let Some(node) =
+                    Self::get_linked_node(&self.nodes, &id)
+                else {
+                    continue;
+                };
+
+                snapshot.push((id, node.world_rect(), node.depth));
+                node_stack.extend(node.children().iter().rev());
+            }
+        }
+
+        snapshot.into()
+    }
+
     /// Returns an immutable reference to a node.
     ///
     /// This is a workaround for [`Self::get()`] due to lifetime
@@ -172,7 +818,6 @@ impl Rectree {
     /// # Panics
     ///
     /// Panics if the given [`NodeId`] does not exist in the tree.
-    #[expect(dead_code)]
     fn get_node<'a>(
         nodes: &'a SparseMap<RectNode>,
         id: &NodeId,
@@ -182,6 +827,56 @@ impl Rectree {
         })
     }
 
+    /// Returns an immutable reference to a node that is linked by
+    /// the tree structure (e.g. as a child) and must always exist.
+    ///
+    /// A missing node means that the tree invariants are broken,
+    /// which is caught by a debug assertion. In release builds,
+    /// `None` is returned so that the node can be skipped.
+    fn get_linked_node<'a>(
+        nodes: &'a SparseMap<RectNode>,
+        id: &NodeId,
+    ) -> Option<&'a RectNode> {
+        let node = nodes.get(id);
+        debug_assert!(
+            node.is_some(),
+            "{id} is linked but does not exist in tree."
+        );
+        node
+    }
+
+    /// Links a child to a parent without any bookkeeping, allowing
+    /// tests to deliberately break the tree invariants.
+    #[cfg(all(test, debug_assertions))]
+    pub(crate) fn link_child_unchecked(
+        &mut self,
+        parent: &NodeId,
+        child: NodeId,
+    ) {
+        self.get_mut(parent).children.push(child);
+    }
+
+    /// Returns the translation of the root node of the hierarchy
+    /// the given node belongs to.
+    ///
+    /// Since root nodes have no parent, their translation is also
+    /// their world translation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given [`NodeId`] does not exist in the tree.
+    fn root_translation(
+        nodes: &SparseMap<RectNode>,
+        id: &NodeId,
+    ) -> Vec2 {
+        let mut node = Self::get_node(nodes, id);
+        while let Some(parent) = node.parent {
+            node = Self::get_node(nodes, &parent);
+        }
+
+        node.translation
+    }
+
     /// Returns a mutable reference to a node.
     ///
     /// This is a workaround for [`Self::get_mut()`] due to lifetime
@@ -200,6 +895,80 @@ impl Rectree {
     }
 }
 
+/// Validation.
+impl Rectree {
+    /// Checks the structural consistency of every node reachable
+    /// from the roots.
+    ///
+    /// Every root must have no parent and a depth of `0`, and every
+    /// child must exist, point back to its parent and be exactly one
+    /// level deeper. Every node must also be reachable, i.e. be
+    /// listed as a root or by its parent. Useful to catch corrupted
+    /// trees, e.g. after loading a save, see
+    /// [`Self::from_snapshot()`].
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut stack = Vec::new();
+        let mut reachable = 0;
+        for root_id in self.root_ids.iter() {
+            let node = self
+                .try_get(root_id)
+                .ok_or(ValidationError::MissingNode(*root_id))?;
+            if let Some(parent) = node.parent {
+                return Err(ValidationError::ParentMismatch {
+                    id: *root_id,
+                    expected: None,
+                    found: Some(parent),
+                });
+            }
+            if node.depth != 0 {
+                return Err(ValidationError::DepthMismatch {
+                    id: *root_id,
+                    expected: 0,
+                    found: node.depth,
+                });
+            }
+            stack.push((*root_id, node));
+        }
+
+        while let Some((id, node)) = stack.pop() {
+            reachable += 1;
+            for child_id in node.children() {
+                let child = self
+                    .try_get(child_id)
+                    .ok_or(ValidationError::MissingNode(*child_id))?;
+                if child.parent != Some(id) {
+                    return Err(ValidationError::ParentMismatch {
+                        id: *child_id,
+                        expected: Some(id),
+                        found: child.parent,
+                    });
+                }
+                // Also bounds the traversal, should the children
+                // form a cycle.
+                if child.depth != node.depth + 1 {
+                    return Err(ValidationError::DepthMismatch {
+                        id: *child_id,
+                        expected: node.depth + 1,
+                        found: child.depth,
+                    });
+                }
+                stack.push((*child_id, child));
+            }
+        }
+
+        // Orphans missing from the roots, or children missing from
+        // their parent, are never visited.
+        if reachable != self.nodes.len() {
+            return Err(ValidationError::UnreachableNodes {
+                reachable,
+                total: self.nodes.len(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(
     Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord,
 )]
@@ -218,3 +987,675 @@ impl Display for NodeId {
         f.write_fmt(format_args!("NodeId({})", self.0))
     }
 }
+
+/// Error returned by [`Rectree::try_insert()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertError {
+    /// The parent does not exist in the tree.
+    InvalidParent(NodeId),
+    /// The node would exceed [`Rectree::MAX_DEPTH`].
+    TooDeep,
+    /// The tree already holds the maximum number of nodes, see
+    /// [`Rectree::set_max_nodes()`].
+    CapacityReached(usize),
+}
+
+impl Display for InsertError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidParent(parent) => f.write_fmt(format_args!(
+                "Invalid parent Id ({parent})."
+            )),
+            Self::TooDeep => f.write_fmt(format_args!(
+                "Node exceeds the maximum depth of {}.",
+                Rectree::MAX_DEPTH
+            )),
+            Self::CapacityReached(max_nodes) => f.write_fmt(
+                format_args!("Tree is full with {max_nodes} nodes."),
+            ),
+        }
+    }
+}
+
+impl core::error::Error for InsertError {}
+
+/// Error returned by [`Rectree::validate()`] and
+/// [`Rectree::from_snapshot()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationError {
+    /// A root or child does not exist in the tree.
+    MissingNode(NodeId),
+    /// A node does not point back to the parent it is linked from,
+    /// or a root has a parent.
+    ParentMismatch {
+        id: NodeId,
+        expected: Option<NodeId>,
+        found: Option<NodeId>,
+    },
+    /// A node's depth is inconsistent with its parent chain.
+    DepthMismatch {
+        id: NodeId,
+        expected: u32,
+        found: u32,
+    },
+    /// Some nodes are neither a root nor listed by their parent.
+    UnreachableNodes { reachable: usize, total: usize },
+    /// A snapshot refers to a node index it does not contain, see
+    /// [`snapshot::TreeSnapshot`].
+    InvalidIndex(usize),
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::MissingNode(id) => f.write_fmt(format_args!(
+                "{id} is linked but does not exist in tree."
+            )),
+            Self::ParentMismatch {
+                id,
+                expected,
+                found,
+            } => f.write_fmt(format_args!(
+                "{id} has parent {found:?}, expected {expected:?}."
+            )),
+            Self::DepthMismatch {
+                id,
+                expected,
+                found,
+            } => f.write_fmt(format_args!(
+                "{id} has depth {found}, expected {expected}."
+            )),
+            Self::UnreachableNodes { reachable, total } => f
+                .write_fmt(format_args!(
+                    "Only {reachable} of {total} nodes are reachable \
+                    from the roots."
+                )),
+            Self::InvalidIndex(index) => f.write_fmt(format_args!(
+                "Node index {index} is out of the snapshot."
+            )),
+        }
+    }
+}
+
+impl core::error::Error for ValidationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "is linked but does not exist in tree")]
+    fn test_remove_dangling_child() {
+        let mut tree = Rectree::new();
+        let root = tree.insert(RectNode::new());
+        let dangling = tree.insert(RectNode::new());
+        tree.remove(&dangling);
+
+        tree.link_child_unchecked(&root, dangling);
+        tree.remove(&root);
+    }
+
+    #[test]
+    fn test_collect_world_rects() {
+        let mut tree = Rectree::new();
+
+        let root_a = tree.insert(RectNode::new());
+        let root_b = tree.insert(RectNode::new());
+        let child = tree.insert(RectNode::new().with_parent(root_a));
+        let removed =
+            tree.insert(RectNode::new().with_parent(root_a));
+        tree.insert(RectNode::new().with_parent(child));
+        tree.insert(RectNode::new().with_parent(child));
+        tree.insert(RectNode::new().with_parent(root_b));
+        tree.insert(RectNode::new().with_parent(removed));
+        tree.remove(&removed);
+
+        // Stale content must be cleared.
+        let mut out = vec![(root_a, Rect::ZERO)];
+        tree.collect_world_rects(&mut out);
+
+        assert_eq!(out.len(), 6);
+        // Parents always come before their children.
+        for (index, (id, _)) in out.iter().enumerate() {
+            if let Some(parent) = tree.get(id).parent() {
+                let parent_index =
+                    out.iter().position(|(id, _)| *id == parent);
+                assert!(parent_index.unwrap() < index);
+            }
+        }
+    }
+
+    /// Creates a root with a grid of 10x10 children, each 10x10
+    /// with a child offset by 5.
+    fn create_grid_tree(clip_children: bool) -> (Rectree, NodeId) {
+        let mut tree = Rectree::new();
+        let root = tree.insert(
+            RectNode::from_rect(Rect::new(0.0, 0.0, 100.0, 100.0))
+                .with_clip_children(clip_children),
+        );
+        for y in 0..10 {
+            for x in 0..10 {
+                let origin = Vec2::new(x as f64, y as f64) * 10.0;
+                let cell = tree.insert(
+                    RectNode::from_translation_size(
+                        origin,
+                        (10.0, 10.0),
+                    )
+                    .with_parent(root)
+                    .with_clip_children(clip_children),
+                );
+                tree.insert(
+                    RectNode::from_translation_size(
+                        (5.0, 5.0),
+                        (10.0, 10.0),
+                    )
+                    .with_parent(cell),
+                );
+            }
+        }
+        tree.layout(&crate::solvers::PassthroughWorld);
+        (tree, root)
+    }
+
+    #[test]
+    fn test_query_region() {
+        let (tree, _) = create_grid_tree(false);
+        let mut rects = Vec::new();
+        tree.collect_world_rects(&mut rects);
+
+        let regions = [
+            Rect::new(0.0, 0.0, 100.0, 100.0),
+            Rect::new(12.0, 33.0, 47.0, 38.0),
+            Rect::new(-20.0, -20.0, -1.0, -1.0),
+            Rect::new(99.0, 99.0, 200.0, 200.0),
+            Rect::new(25.0, 25.0, 25.0, 25.0),
+        ];
+        for region in regions {
+            let expected = rects
+                .iter()
+                .filter(|(_, rect)| rect.overlaps(region))
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>();
+            assert_eq!(tree.query_region(region), expected);
+        }
+    }
+
+    #[test]
+    fn test_skip_zero_area() {
+        let mut tree = Rectree::new();
+        // Collapsed clipping parent, e.g. a hidden panel.
+        let panel = tree.insert(
+            RectNode::from_size((100.0, 0.0))
+                .with_clip_children(true),
+        );
+        let child = tree.insert(
+            RectNode::from_size((10.0, 10.0)).with_parent(panel),
+        );
+        // Collapsed wrapper that does not clip.
+        let wrapper = tree.insert(RectNode::from_translation_size(
+            (50.0, 50.0),
+            (0.0, 10.0),
+        ));
+        let overflow = tree.insert(
+            RectNode::from_size((10.0, 10.0)).with_parent(wrapper),
+        );
+        tree.layout(&crate::solvers::PassthroughWorld);
+
+        assert!(tree.get(&panel).is_zero_area());
+        assert!(!tree.get(&child).is_zero_area());
+        assert!(!tree.skip_zero_area());
+
+        // Edges still touch the region by default.
+        let region = Rect::new(0.0, 0.0, 5.0, 5.0);
+        let mut found = tree.query_region(region);
+        found.sort();
+        let mut expected = vec![panel, child];
+        expected.sort();
+        assert_eq!(found, expected);
+
+        tree.set_skip_zero_area(true);
+        assert!(tree.query_region(region).is_empty());
+        let region = Rect::new(50.0, 50.0, 55.0, 55.0);
+        assert_eq!(tree.query_region(region), [overflow]);
+
+        let mut rects = vec![];
+        tree.collect_world_rects(&mut rects);
+        assert_eq!(
+            rects,
+            [(overflow, Rect::new(50.0, 50.0, 60.0, 60.0))]
+        );
+    }
+
+    #[test]
+    fn test_query_region_clipped() {
+        let (mut tree, root) = create_grid_tree(true);
+
+        // Only the clipped part of the grandchildren matches.
+        let region = Rect::new(12.0, 12.0, 14.0, 14.0);
+        let mut found = tree.query_region(region);
+        found.sort();
+        let cell = *tree
+            .get(&root)
+            .children()
+            .iter()
+            .find(|id| {
+                tree.get(id).translation() == (10.0, 10.0).into()
+            })
+            .unwrap();
+        let grandchild =
+            *tree.get(&cell).children().iter().next().unwrap();
+        let mut expected = vec![root, cell];
+        expected.sort();
+        assert_eq!(found, expected);
+
+        let region = Rect::new(16.0, 16.0, 17.0, 17.0);
+        assert!(tree.query_region(region).contains(&grandchild));
+
+        // Offscreen children of a clipped parent are pruned.
+        tree.insert(
+            RectNode::from_translation_size(
+                (150.0, 0.0),
+                (10.0, 10.0),
+            )
+            .with_parent(root),
+        );
+        tree.layout(&crate::solvers::PassthroughWorld);
+        let region = Rect::new(140.0, -10.0, 170.0, 20.0);
+        assert!(tree.query_region(region).is_empty());
+
+        // Unless the parent does not clip.
+        let (mut tree, root) = create_grid_tree(false);
+        let offscreen = tree.insert(
+            RectNode::from_translation_size(
+                (150.0, 0.0),
+                (10.0, 10.0),
+            )
+            .with_parent(root),
+        );
+        tree.layout(&crate::solvers::PassthroughWorld);
+        assert_eq!(tree.query_region(region), [offscreen]);
+    }
+
+    #[test]
+    #[cfg(target_has_atomic = "ptr")]
+    fn test_snapshot_rects() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let mut tree = Rectree::new();
+        let root = tree.insert(RectNode::from_rect(Rect::new(
+            0.0, 0.0, 100.0, 100.0,
+        )));
+        let child = tree.insert(
+            RectNode::from_rect(Rect::new(10.0, 10.0, 20.0, 20.0))
+                .with_parent(root),
+        );
+        tree.layout(&crate::solvers::PassthroughWorld);
+
+        let snapshot = tree.snapshot_rects();
+        assert_send_sync(&snapshot);
+        let expected = [
+            (root, Rect::new(0.0, 0.0, 100.0, 100.0), 0),
+            (child, Rect::new(10.0, 10.0, 20.0, 20.0), 1),
+        ];
+        assert_eq!(*snapshot, expected);
+
+        // Mutations do not affect the snapshot.
+        tree.remove(&child);
+        tree.get_mut(&root).translation = Vec2::new(50.0, 50.0);
+        tree.schedule_relayout(root);
+        tree.layout(&crate::solvers::PassthroughWorld);
+        assert_eq!(*snapshot, expected);
+        assert_eq!(tree.snapshot_rects().len(), 1);
+    }
+
+    #[test]
+    fn test_remove_root() {
+        let mut tree = Rectree::new();
+        let root = tree.insert(RectNode::new());
+        let child = tree.insert(RectNode::new().with_parent(root));
+        let other_root = tree.insert(RectNode::new());
+
+        assert!(tree.remove(&root));
+        assert!(!tree.root_ids().contains(&root));
+        assert!(!tree.root_ids().contains(&child));
+        assert!(tree.root_ids().contains(&other_root));
+        assert!(tree.try_get(&child).is_none());
+
+        assert!(tree.remove(&other_root));
+        assert!(tree.root_ids().is_empty());
+        assert_eq!(tree.leaves().count(), 0);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut tree = Rectree::new();
+        assert_eq!(tree.validate(), Ok(()));
+
+        let root = tree.insert(RectNode::new());
+        let child = tree.insert(RectNode::new().with_parent(root));
+        let grandchild =
+            tree.insert(RectNode::new().with_parent(child));
+        let other_root = tree.insert(RectNode::new());
+        assert_eq!(tree.validate(), Ok(()));
+
+        // Stale backpointer.
+        tree.get_mut(&grandchild).parent = Some(other_root);
+        assert_eq!(
+            tree.validate(),
+            Err(ValidationError::ParentMismatch {
+                id: grandchild,
+                expected: Some(child),
+                found: Some(other_root),
+            })
+        );
+        tree.get_mut(&grandchild).parent = Some(child);
+
+        // Inconsistent depth.
+        tree.get_mut(&grandchild).depth = 1;
+        assert_eq!(
+            tree.validate(),
+            Err(ValidationError::DepthMismatch {
+                id: grandchild,
+                expected: 2,
+                found: 1,
+            })
+        );
+        tree.get_mut(&grandchild).depth = 2;
+
+        // Root with a parent.
+        tree.get_mut(&other_root).parent = Some(root);
+        assert_eq!(
+            tree.validate(),
+            Err(ValidationError::ParentMismatch {
+                id: other_root,
+                expected: None,
+                found: Some(root),
+            })
+        );
+        tree.get_mut(&other_root).parent = None;
+
+        // Orphan missing from the roots.
+        tree.root_ids.remove(&other_root);
+        assert_eq!(
+            tree.validate(),
+            Err(ValidationError::UnreachableNodes {
+                reachable: 3,
+                total: 4,
+            })
+        );
+        tree.root_ids.insert(other_root);
+
+        // Parent not listing its child.
+        tree.get_mut(&root).remove_child(&child);
+        assert_eq!(
+            tree.validate(),
+            Err(ValidationError::UnreachableNodes {
+                reachable: 2,
+                total: 4,
+            })
+        );
+        tree.get_mut(&root).children.push(child);
+
+        // Dangling child.
+        tree.nodes.remove(&grandchild);
+        assert_eq!(
+            tree.validate(),
+            Err(ValidationError::MissingNode(grandchild))
+        );
+        tree.get_mut(&child).remove_child(&grandchild);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_remove_node_reparenting() {
+        let mut tree = Rectree::new();
+
+        let root = tree.insert(RectNode::new());
+        let wrapper = tree.insert(RectNode::new().with_parent(root));
+        let child = tree.insert(RectNode::new().with_parent(wrapper));
+        let grandchild =
+            tree.insert(RectNode::new().with_parent(child));
+
+        assert_eq!(tree.get(&grandchild).depth(), 3);
+
+        assert!(tree.remove_node_reparenting(&wrapper));
+        assert!(tree.try_get(&wrapper).is_none());
+        assert!(!tree.remove_node_reparenting(&wrapper));
+
+        assert_eq!(tree.get(&child).parent(), Some(root));
+        assert_eq!(tree.get(&root).children(), [child]);
+        assert_eq!(tree.get(&child).depth(), 1);
+        assert_eq!(tree.get(&grandchild).depth(), 2);
+        assert_eq!(tree.validate(), Ok(()));
+
+        // Removing a root turns its children into roots.
+        assert!(tree.remove_node_reparenting(&root));
+        assert!(tree.root_ids().contains(&child));
+        assert!(!tree.root_ids().contains(&root));
+        assert!(tree.get(&child).is_root());
+        assert_eq!(tree.get(&child).depth(), 0);
+        assert_eq!(tree.get(&grandchild).depth(), 1);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_remove_node_reparenting_order() {
+        let mut tree = Rectree::new();
+
+        let root = tree.insert(RectNode::new());
+        let before = tree.insert(RectNode::new().with_parent(root));
+        let wrapper = tree.insert(RectNode::new().with_parent(root));
+        let after = tree.insert(RectNode::new().with_parent(root));
+        let a = tree.insert(RectNode::new().with_parent(wrapper));
+        let b = tree.insert(RectNode::new().with_parent(wrapper));
+        let a1 = tree.insert(RectNode::new().with_parent(a));
+        tree.layout(&crate::solvers::PassthroughWorld);
+
+        // A descendant scheduled before the removal is re-keyed.
+        tree.schedule_relayout(a1);
+        assert!(tree.remove_node_reparenting(&wrapper));
+
+        // The children take the position of the removed node.
+        assert_eq!(tree.get(&root).children(), [before, a, b, after]);
+        assert_eq!(tree.get(&a1).depth(), 2);
+
+        let scheduled = tree
+            .drain_scheduled_relayout_topdown()
+            .map(|id| (id, tree.get(&id).depth()))
+            .collect::<Vec<_>>();
+        assert_eq!(scheduled, [(root, 0), (a1, 2)]);
+
+        // Same when the children become roots.
+        tree.schedule_relayout(a1);
+        assert!(tree.remove_node_reparenting(&root));
+        assert_eq!(tree.get(&a1).depth(), 1);
+        let scheduled = tree
+            .drain_scheduled_relayout_topdown()
+            .map(|id| tree.get(&id).depth())
+            .collect::<Vec<_>>();
+        assert_eq!(scheduled, [0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_child_count() {
+        let mut tree = Rectree::new();
+
+        let root = tree.insert(RectNode::new());
+        let child = tree.insert(RectNode::new().with_parent(root));
+        let leaf = tree.insert(RectNode::new().with_parent(child));
+        tree.insert(RectNode::new().with_parent(child));
+
+        assert_eq!(tree.child_count(&root), 1);
+        assert_eq!(tree.child_count(&child), 2);
+        assert!(tree.has_children(&child));
+        assert!(!tree.is_leaf(&child));
+
+        assert_eq!(tree.child_count(&leaf), 0);
+        assert!(!tree.has_children(&leaf));
+        assert!(tree.is_leaf(&leaf));
+
+        // Removed nodes have no children.
+        tree.remove(&child);
+        assert_eq!(tree.child_count(&child), 0);
+        assert!(tree.is_leaf(&child));
+        assert!(tree.is_leaf(&root));
+    }
+
+    #[test]
+    fn test_leaves() {
+        let mut tree = Rectree::new();
+
+        let root = tree.insert(RectNode::new());
+        let child = tree.insert(RectNode::new().with_parent(root));
+        let grandchild =
+            tree.insert(RectNode::new().with_parent(child));
+        let sibling = tree.insert(RectNode::new().with_parent(root));
+        let other_root = tree.insert(RectNode::new());
+
+        let sorted = |leaves: &mut dyn Iterator<Item = NodeId>| {
+            let mut leaves = leaves.collect::<Vec<_>>();
+            leaves.sort();
+            leaves
+        };
+        let mut expected = vec![grandchild, sibling, other_root];
+        expected.sort();
+        assert_eq!(sorted(&mut tree.leaves()), expected);
+
+        assert_eq!(sorted(&mut tree.leaves_of(&child)), [grandchild]);
+        assert_eq!(sorted(&mut tree.leaves_of(&sibling)), [sibling]);
+
+        // A node whose children were all removed becomes a leaf.
+        tree.remove(&grandchild);
+        assert_eq!(sorted(&mut tree.leaves_of(&child)), [child]);
+        assert_eq!(sorted(&mut tree.leaves_of(&grandchild)), []);
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let mut tree = Rectree::new();
+        let mut parent = tree.insert(RectNode::new());
+
+        for _ in 0..Rectree::MAX_DEPTH {
+            parent = tree.insert(RectNode::new().with_parent(parent));
+        }
+        assert_eq!(tree.get(&parent).depth(), Rectree::MAX_DEPTH);
+
+        let result =
+            tree.try_insert(RectNode::new().with_parent(parent));
+        assert_eq!(result, Err(InsertError::TooDeep));
+        assert!(tree.is_leaf(&parent));
+    }
+
+    #[test]
+    fn test_try_insert_invalid_parent() {
+        let mut tree = Rectree::new();
+        let removed = tree.insert(RectNode::new());
+        tree.remove(&removed);
+
+        let result =
+            tree.try_insert(RectNode::new().with_parent(removed));
+        assert_eq!(result, Err(InsertError::InvalidParent(removed)));
+    }
+
+    #[test]
+    fn test_max_nodes() {
+        let mut tree = Rectree::new();
+        tree.set_max_nodes(Some(2));
+
+        let root = tree.insert(RectNode::new());
+        let child = tree.insert(RectNode::new().with_parent(root));
+
+        // Full, the tree is left unchanged.
+        let result =
+            tree.try_insert(RectNode::new().with_parent(root));
+        assert_eq!(result, Err(InsertError::CapacityReached(2)));
+        assert_eq!(tree.root_ids().len(), 1);
+        assert_eq!(tree.get(&root).children().len(), 1);
+
+        // Removing a node frees up room.
+        tree.remove(&child);
+        assert!(tree.try_insert(RectNode::new()).is_ok());
+        assert!(tree.try_insert(RectNode::new()).is_err());
+
+        // Unbounded again.
+        tree.set_max_nodes(None);
+        assert!(tree.try_insert(RectNode::new()).is_ok());
+    }
+
+    #[test]
+    fn test_swap() {
+        let mut tree = Rectree::new();
+
+        let root = tree.insert(RectNode::new());
+        let a = tree.insert(RectNode::new().with_parent(root));
+        let a1 = tree.insert(RectNode::new().with_parent(a));
+        let x = tree.insert(RectNode::new().with_parent(root));
+        let b = tree.insert(RectNode::new().with_parent(x));
+        let b1 = tree.insert(RectNode::new().with_parent(b));
+        let b2 = tree.insert(RectNode::new().with_parent(b1));
+
+        // Siblings only swap their indices.
+        tree.layout(&crate::solvers::PassthroughWorld);
+        assert!(tree.swap(&a, &x));
+        assert_eq!(tree.get(&a).parent(), Some(root));
+        assert_eq!(tree.get(&x).parent(), Some(root));
+        assert_eq!(tree.get(&root).children(), [x, a]);
+        assert!(tree.relayout_reason(&root).is_some());
+
+        // Cycles and invalid ids are rejected.
+        assert!(!tree.swap(&x, &b1));
+        assert!(!tree.swap(&b2, &root));
+        let removed = tree.insert(RectNode::new());
+        tree.remove(&removed);
+        assert!(!tree.swap(&a, &removed));
+
+        // Each node takes the index of the other.
+        let x1 = tree.insert(RectNode::new().with_parent(x));
+        assert!(tree.swap(&a, &b));
+        assert_eq!(tree.get(&a).parent(), Some(x));
+        assert_eq!(tree.get(&b).parent(), Some(root));
+        assert_eq!(tree.get(&x).children(), [a, x1]);
+        assert_eq!(tree.get(&root).children(), [x, b]);
+
+        assert_eq!(tree.get(&a).depth(), 2);
+        assert_eq!(tree.get(&a1).depth(), 3);
+        assert_eq!(tree.get(&b).depth(), 1);
+        assert_eq!(tree.get(&b1).depth(), 2);
+        assert_eq!(tree.get(&b2).depth(), 3);
+
+        // Swapping with a root.
+        let other_root = tree.insert(RectNode::new());
+        assert!(tree.swap(&other_root, &a1));
+        assert!(tree.root_ids().contains(&a1));
+        assert!(!tree.root_ids().contains(&other_root));
+        assert_eq!(tree.get(&a1).depth(), 0);
+        assert_eq!(tree.get(&other_root).depth(), 3);
+        assert!(tree.get(&a).children().contains(&other_root));
+
+        // Roots are unordered.
+        assert!(!tree.swap(&root, &a1));
+    }
+
+    #[test]
+    fn test_swap_rekeys_scheduled() {
+        let mut tree = Rectree::new();
+
+        let root = tree.insert(RectNode::new());
+        let a = tree.insert(RectNode::new().with_parent(root));
+        let x = tree.insert(RectNode::new().with_parent(root));
+        let b = tree.insert(RectNode::new().with_parent(x));
+        let b1 = tree.insert(RectNode::new().with_parent(b));
+
+        // Every inserted node is still scheduled with its old depth.
+        assert!(tree.swap(&a, &b1));
+        assert_eq!(tree.get(&a).depth(), 3);
+        assert_eq!(tree.get(&b1).depth(), 1);
+
+        let depths = tree
+            .drain_scheduled_relayout_topdown()
+            .map(|id| tree.get(&id).depth())
+            .collect::<Vec<_>>();
+        assert_eq!(depths, [0, 1, 1, 2, 3]);
+    }
+}
@@ -1,9 +1,11 @@
+use alloc::vec::Vec;
 use bitflags::bitflags;
-use hashbrown::HashSet;
-use kurbo::{Rect, Size, Vec2};
+use kurbo::{Insets, Point, Rect, Size, Vec2};
 
-use crate::NodeId;
-use crate::layout::Constraint;
+use crate::layout::{
+    Constraint, LayoutMemo, LayoutParams, RelayoutReason,
+};
+use crate::{NodeId, Rectree};
 
 /// An axis-aligned rectangle in the layout tree.
 ///
@@ -33,11 +35,50 @@ pub struct RectNode {
     /// See [`Self::parent()`].
     pub(crate) parent: Option<NodeId>,
     /// See [`Self::children()`].
-    pub(crate) children: HashSet<NodeId>,
+    pub(crate) children: Vec<NodeId>,
     /// See [`Self::depth()`].
     pub(crate) depth: u32,
     /// The state of the current node.
     pub(crate) state: NodeState,
+    /// See [`Self::with_memo()`].
+    pub(crate) memo: Option<LayoutMemo>,
+    /// See [`Self::position_mode()`].
+    pub(crate) position_mode: PositionMode,
+    /// See [`Self::margin()`].
+    pub(crate) margin: Insets,
+    /// See [`Self::padding()`].
+    pub(crate) padding: Insets,
+    /// See [`Self::clip_children()`].
+    pub(crate) clip_children: bool,
+    /// See [`Self::layout_params()`].
+    pub(crate) layout_params: LayoutParams,
+    /// See [`Self::prev_size()`].
+    pub(crate) prev_size: Size,
+    /// See [`Self::prev_world_translation()`].
+    pub(crate) prev_world_translation: Vec2,
+    /// Order in which the node was inserted into the tree, used as
+    /// a stable tiebreaker between nodes of equal depth.
+    pub(crate) insertion_order: u64,
+    /// See [`crate::Rectree::relayout_reason()`].
+    pub(crate) relayout_reason: Option<RelayoutReason>,
+    /// Bumped whenever an input of the node's build other than its
+    /// constraint changes, e.g. its solver or its children.
+    pub(crate) version: u64,
+    /// [`Self::version`] and constraint of the last build.
+    pub(crate) built_stamp: Option<(u64, Constraint)>,
+    /// See [`Self::size_override()`].
+    pub(crate) size_override: Option<Size>,
+    /// See [`Self::built_size()`].
+    pub(crate) built_size: Size,
+    /// [`crate::Rectree::current_epoch()`] at the latest change of
+    /// [`Self::size`].
+    pub(crate) size_epoch: u64,
+}
+
+impl From<Rect> for RectNode {
+    fn from(rect: Rect) -> Self {
+        Self::from_rect(rect)
+    }
 }
 
 /// Builders.
@@ -61,13 +102,26 @@ impl RectNode {
         Self::new().with_translation(translation).with_size(size)
     }
 
+    /// Create a node covering `rect`.
+    ///
+    /// The rect is normalized first, so the translation is always
+    /// its top-left corner and the size is always non-negative.
     pub fn from_rect(rect: impl Into<Rect>) -> Self {
-        let rect: Rect = rect.into();
+        let rect = rect.into().abs();
         Self::new()
-            .with_translation(Vec2::new(rect.min_x(), rect.min_y()))
+            .with_translation(rect.origin().to_vec2())
             .with_size(rect.size())
     }
 
+    /// Create a node covering the rect spanned by two opposite
+    /// corners, see [`Self::from_rect()`].
+    pub fn from_points(
+        p0: impl Into<Point>,
+        p1: impl Into<Point>,
+    ) -> Self {
+        Self::from_rect(Rect::from_points(p0, p1))
+    }
+
     pub fn with_translation(
         mut self,
         translation: impl Into<Vec2>,
@@ -85,6 +139,53 @@ impl RectNode {
         self.parent = Some(parent);
         self
     }
+
+    pub fn with_margin(mut self, margin: impl Into<Insets>) -> Self {
+        self.margin = margin.into();
+        self
+    }
+
+    pub fn with_padding(
+        mut self,
+        padding: impl Into<Insets>,
+    ) -> Self {
+        self.padding = padding.into();
+        self
+    }
+
+    pub fn with_position_mode(mut self, mode: PositionMode) -> Self {
+        self.position_mode = mode;
+        self
+    }
+
+    /// See [`Self::layout_params()`].
+    pub fn with_layout_params(
+        mut self,
+        params: LayoutParams,
+    ) -> Self {
+        self.layout_params = params;
+        self
+    }
+
+    /// See [`Self::clip_children()`].
+    pub fn with_clip_children(mut self, clip_children: bool) -> Self {
+        self.clip_children = clip_children;
+        self
+    }
+
+    /// Opt into memoizing the results of
+    /// [`crate::layout::LayoutSolver::build()`] for the last
+    /// `capacity` parent constraints.
+    ///
+    /// When the node is rebuilt with a memoized constraint, the
+    /// build is skipped entirely. This relies on the solver always
+    /// producing the same result given the same constraint. Memos
+    /// are cleared whenever the node or any of its descendants are
+    /// scheduled for relayout.
+    pub fn with_memo(mut self, capacity: usize) -> Self {
+        self.memo = Some(LayoutMemo::new(capacity));
+        self
+    }
 }
 
 /// Getters.
@@ -94,15 +195,82 @@ impl RectNode {
         self.translation
     }
 
-    /// Size of the node.
+    /// Size of the node, including its [`Self::padding`] but
+    /// excluding its [`Self::margin`].
     ///
     /// This is the resolved size after
     /// [`crate::layout::LayoutSolver::build()`].
     pub fn size(&self) -> Size {
+        self.without_margin(self.size)
+    }
+
+    /// Size of the node including its [`Self::margin`], i.e. the
+    /// space it takes up within its parent.
+    ///
+    /// Parents should lay out their children using this size.
+    pub fn outer_size(&self) -> Size {
         self.size
     }
 
-    /// Constraint imposed by the parent onto this node.
+    /// Size built by the node's own solver, including its padding
+    /// and margin like [`Self::outer_size()`], regardless of
+    /// [`Self::size_override()`].
+    ///
+    /// Parents that dictate the sizes of their children should
+    /// measure them using this size rather than [`Self::size()`].
+    pub fn built_size(&self) -> Size {
+        self.built_size
+    }
+
+    /// Size dictated by the parent through
+    /// [`crate::layout::Positioner::set_size()`], including the
+    /// margin like [`Self::outer_size()`], taking precedence
+    /// over the size built by the node's own solver.
+    pub fn size_override(&self) -> Option<Size> {
+        self.size_override
+    }
+
+    /// Outer spacing around the node.
+    ///
+    /// The margin is applied generically during layout: the
+    /// constraint given to the node is deflated by it, the size
+    /// reported to the parent is inflated by it, and the world
+    /// translation is offset by its top-left corner.
+    pub fn margin(&self) -> Insets {
+        self.margin
+    }
+
+    /// Inner spacing between the node and its children.
+    ///
+    /// The padding is applied generically during layout: the
+    /// constraint forwarded to the children is deflated by it,
+    /// translations set via [`crate::layout::Positioner`] are offset
+    /// by its top-left corner, and the built size is inflated by it.
+    ///
+    /// If the constraint is smaller than the padding, the children
+    /// are constrained to zero while the padding is preserved,
+    /// resulting in an [`crate::layout::Overflow`].
+    pub fn padding(&self) -> Insets {
+        self.padding
+    }
+
+    /// Whether the descendants of the node are clipped to its
+    /// [`Self::world_rect()`].
+    ///
+    /// This does not affect layout, only queries such as
+    /// [`crate::Rectree::query_region()`].
+    pub fn clip_children(&self) -> bool {
+        self.clip_children
+    }
+
+    /// Parameters interpreted by the solver of the parent, see
+    /// [`crate::Rectree::set_layout_params()`].
+    pub fn layout_params(&self) -> LayoutParams {
+        self.layout_params
+    }
+
+    /// Constraint imposed by the parent onto this node, deflated by
+    /// the node's [`Self::margin`].
     ///
     /// This is computed during the top-down constraint pass via
     /// [`crate::layout::LayoutSolver::constraint()`].
@@ -113,7 +281,8 @@ impl RectNode {
     /// World-space translation of this node.
     ///
     /// This is the accumulated translation from the root and is
-    /// computed during transform propagation.
+    /// computed during transform propagation. It points to the
+    /// top-left corner inside the [`Self::margin`].
     pub fn world_translation(&self) -> Vec2 {
         self.world_translation
     }
@@ -123,8 +292,10 @@ impl RectNode {
         self.parent
     }
 
-    /// Child nodes of this node.
-    pub fn children(&self) -> &HashSet<NodeId> {
+    /// Child nodes of this node, in document order.
+    ///
+    /// See [`crate::Rectree::set_child_index()`] to reorder them.
+    pub fn children(&self) -> &[NodeId] {
         &self.children
     }
 
@@ -137,20 +308,157 @@ impl RectNode {
     }
 
     /// Compute the world space [`Rect`] from
-    /// [`Self::world_translation`] and [`Self::size`].
+    /// [`Self::world_translation`] and [`Self::size`], excluding the
+    /// [`Self::margin`] but including the [`Self::padding`].
     pub fn world_rect(&self) -> Rect {
-        Rect::new(
-            self.world_translation.x,
-            self.world_translation.y,
-            self.world_translation.x + self.size.width,
-            self.world_translation.y + self.size.height,
+        self.compute_world_rect(self.world_translation, self.size)
+    }
+
+    /// [`Self::size`] before the latest layout pass.
+    ///
+    /// If the node was not affected by the latest layout pass, this
+    /// is equal to [`Self::size`].
+    pub fn prev_size(&self) -> Size {
+        self.without_margin(self.prev_size)
+    }
+
+    /// [`Self::world_translation`] before the latest layout pass.
+    ///
+    /// If the node was not affected by the latest layout pass, this
+    /// is equal to [`Self::world_translation`].
+    pub fn prev_world_translation(&self) -> Vec2 {
+        self.prev_world_translation
+    }
+
+    /// Compute the world space [`Rect`] before the latest layout
+    /// pass from [`Self::prev_world_translation`] and
+    /// [`Self::prev_size`].
+    ///
+    /// See [`Self::world_rect()`].
+    pub fn prev_world_rect(&self) -> Rect {
+        self.compute_world_rect(
+            self.prev_world_translation,
+            self.prev_size,
         )
     }
 
+    /// Invalidates the last build of the node, see
+    /// [`Self::is_up_to_date()`].
+    pub(crate) fn invalidate(&mut self) {
+        self.version = self.version.wrapping_add(1);
+    }
+
+    /// Returns `true` if rebuilding the node would reproduce the
+    /// last build, i.e. neither its constraint nor its version have
+    /// changed since.
+    pub(crate) fn is_up_to_date(&self) -> bool {
+        self.built_stamp
+            == Some((self.version, self.parent_constraint))
+    }
+
+    /// Unlinks a child, returning its index among the children.
+    pub(crate) fn remove_child(
+        &mut self,
+        id: &NodeId,
+    ) -> Option<usize> {
+        let index = self.children.iter().position(|c| c == id)?;
+        self.children.remove(index);
+        Some(index)
+    }
+
+    /// Records why the node has pending layout work.
+    ///
+    /// Explicit scheduling always takes precedence, while other
+    /// reasons only replace [`RelayoutReason::TranslationOnly`].
+    pub(crate) fn mark_relayout(&mut self, reason: RelayoutReason) {
+        match (self.relayout_reason, reason) {
+            (_, RelayoutReason::Scheduled)
+            | (None | Some(RelayoutReason::TranslationOnly), _) => {
+                self.relayout_reason = Some(reason)
+            }
+            _ => {}
+        }
+    }
+
+    fn compute_world_rect(
+        &self,
+        translation: Vec2,
+        size: Size,
+    ) -> Rect {
+        Rect::from_origin_size(
+            translation.to_point(),
+            self.without_margin(size),
+        )
+    }
+
+    /// Deflates an outer size by the [`Self::margin`], clamping it
+    /// to zero.
+    fn without_margin(&self, size: Size) -> Size {
+        let size = size - self.margin.size();
+        Size::new(size.width.max(0.0), size.height.max(0.0))
+    }
+
     /// Returns `true` if [`Self::parent`] is `None`.
     pub fn is_root(&self) -> bool {
         self.parent.is_none()
     }
+
+    /// How this node is positioned, see [`PositionMode`].
+    pub fn position_mode(&self) -> PositionMode {
+        self.position_mode
+    }
+
+    /// Returns `true` if [`Self::position_mode`] is
+    /// [`PositionMode::Absolute`].
+    ///
+    /// Solvers should skip absolute children when computing their
+    /// own size.
+    pub fn is_absolute(&self) -> bool {
+        self.position_mode == PositionMode::Absolute
+    }
+
+    /// Returns `true` if [`Self::world_rect()`] has zero width or
+    /// height, e.g. for a collapsed node.
+    ///
+    /// See [`Rectree::set_skip_zero_area()`].
+    pub fn is_zero_area(&self) -> bool {
+        self.world_rect().is_zero_area()
+    }
+
+    /// Area of [`Self::world_rect()`], excluding the
+    /// [`Self::margin`].
+    pub fn area(&self) -> f64 {
+        self.world_rect().area()
+    }
+
+    /// Width divided by height of [`Self::world_rect()`].
+    ///
+    /// This is infinite or NaN for a node with zero height, see
+    /// [`Self::is_zero_area()`].
+    pub fn aspect_ratio(&self) -> f64 {
+        let rect = self.world_rect();
+        rect.width() / rect.height()
+    }
+
+    /// World space center of [`Self::world_rect()`].
+    pub fn center(&self) -> Point {
+        self.world_rect().center()
+    }
+}
+
+/// Determines how a [`RectNode`] is positioned.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PositionMode {
+    /// The translation is assigned by the parent's
+    /// [`crate::layout::LayoutSolver`] and is relative to the parent.
+    #[default]
+    ParentDriven,
+    /// The translation opts out of the parent's layout and is
+    /// relative to the root of the hierarchy instead.
+    ///
+    /// Translations written through [`crate::layout::Positioner`]
+    /// are ignored for absolute nodes.
+    Absolute,
 }
 
 bitflags! {
@@ -212,3 +520,151 @@ impl NodeState {
         self.insert(Self::BUILT);
     }
 }
+
+/// A node borrowed together with its [`Rectree`], allowing fluent
+/// navigation through the hierarchy.
+///
+/// Created via [`Rectree::node_ref()`] or
+/// [`Rectree::try_node_ref()`]. This is just a pair of a reference
+/// and an id, and is cheap to copy.
+#[derive(Debug, Clone, Copy)]
+pub struct NodeRef<'a> {
+    pub(crate) tree: &'a Rectree,
+    pub(crate) id: NodeId,
+}
+
+impl<'a> NodeRef<'a> {
+    /// Id of the referenced node.
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    /// The referenced node.
+    pub fn node(&self) -> &'a RectNode {
+        self.tree.get(&self.id)
+    }
+
+    /// Parent node in the hierarchy, if any.
+    pub fn parent(&self) -> Option<NodeRef<'a>> {
+        self.node().parent.map(|id| NodeRef {
+            tree: self.tree,
+            id,
+        })
+    }
+
+    /// Child nodes of this node.
+    pub fn children(
+        &self,
+    ) -> impl Iterator<Item = NodeRef<'a>> + use<'a> {
+        let tree = self.tree;
+        self.node()
+            .children
+            .iter()
+            .map(move |&id| NodeRef { tree, id })
+    }
+
+    /// See [`RectNode::world_rect()`].
+    pub fn world_rect(&self) -> Rect {
+        self.node().world_rect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_rect_inverted() {
+        let node =
+            RectNode::from_rect(Rect::new(30.0, 40.0, 10.0, 5.0));
+        assert_eq!(node.translation(), Vec2::new(10.0, 5.0));
+        assert_eq!(node.size(), Size::new(20.0, 35.0));
+
+        let node = RectNode::from_points((30.0, 5.0), (10.0, 40.0));
+        assert_eq!(node.translation(), Vec2::new(10.0, 5.0));
+        assert_eq!(node.size(), Size::new(20.0, 35.0));
+
+        let node = RectNode::from(Rect::new(10.0, 5.0, 30.0, 40.0));
+        assert_eq!(node.translation(), Vec2::new(10.0, 5.0));
+        assert_eq!(node.size(), Size::new(20.0, 35.0));
+    }
+
+    #[test]
+    fn test_node_ref() {
+        let mut tree = Rectree::new();
+        let root = tree.insert(RectNode::new());
+        let a = tree.insert(RectNode::new().with_parent(root));
+        let b = tree.insert(RectNode::new().with_parent(root));
+        let leaf = tree.insert(
+            RectNode::from_translation_size(
+                Vec2::new(5.0, 5.0),
+                Size::new(10.0, 20.0),
+            )
+            .with_parent(a),
+        );
+        tree.layout(&crate::solvers::PassthroughWorld);
+
+        let leaf_ref = tree.node_ref(&leaf);
+        assert_eq!(leaf_ref.id(), leaf);
+        assert_eq!(
+            leaf_ref.world_rect(),
+            tree.get(&leaf).world_rect()
+        );
+
+        let parent = leaf_ref.parent().unwrap();
+        assert_eq!(parent.id(), a);
+        assert_eq!(
+            parent.world_rect(),
+            tree.get(&tree.get(&leaf).parent().unwrap()).world_rect()
+        );
+        assert_eq!(
+            parent.parent().unwrap().world_rect(),
+            tree.get(&root).world_rect()
+        );
+        assert!(parent.parent().unwrap().parent().is_none());
+
+        let mut children = tree
+            .node_ref(&root)
+            .children()
+            .map(|child| child.id())
+            .collect::<alloc::vec::Vec<_>>();
+        children.sort();
+        let mut expected = [a, b];
+        expected.sort();
+        assert_eq!(children, expected);
+        assert!(
+            tree.node_ref(&root).children().all(|child| child
+                .parent()
+                .unwrap()
+                .id()
+                == root)
+        );
+
+        tree.remove(&b);
+        assert!(tree.try_node_ref(&b).is_none());
+    }
+
+    #[test]
+    fn test_area_helpers() {
+        let mut node = RectNode::from_translation_size(
+            (10.0, 20.0),
+            (100.0, 50.0),
+        );
+        node.world_translation = Vec2::new(30.0, 40.0);
+
+        assert_eq!(node.area(), 5000.0);
+        assert_eq!(node.aspect_ratio(), 2.0);
+        assert_eq!(node.center(), Point::new(80.0, 65.0));
+        assert!(!node.is_zero_area());
+
+        // The margin is excluded.
+        let mut node = node.with_margin(Insets::uniform(5.0));
+        node.world_translation = Vec2::new(35.0, 45.0);
+        assert_eq!(node.area(), 90.0 * 40.0);
+        assert_eq!(node.center(), Point::new(80.0, 65.0));
+
+        node.size = Size::new(100.0, 10.0);
+        assert_eq!(node.area(), 0.0);
+        assert!(node.is_zero_area());
+    }
+}
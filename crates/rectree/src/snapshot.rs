@@ -0,0 +1,270 @@
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+use kurbo::{Insets, Size, Vec2};
+
+use crate::layout::{Constraint, DepthNode};
+use crate::node::RectNode;
+use crate::{NodeId, Rectree, ValidationError};
+
+/// A flat copy of the structure and geometry of a [`Rectree`],
+/// e.g. to save and load a tree.
+///
+/// Nodes refer to each other by their index in [`Self::nodes`],
+/// and children are listed in document order, so that their order
+/// survives a round trip. With the `serde` feature, snapshots can
+/// be serialized.
+///
+/// See [`Rectree::snapshot()`] and [`Rectree::from_snapshot()`].
+#[derive(Default, Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct TreeSnapshot {
+    pub nodes: Vec<NodeSnapshot>,
+}
+
+/// A single node within a [`TreeSnapshot`].
+#[derive(Default, Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct NodeSnapshot {
+    /// Index of the parent, or `None` for roots.
+    pub parent: Option<usize>,
+    /// Indices of the children, in document order.
+    pub children: Vec<usize>,
+    /// See [`RectNode::depth()`].
+    pub depth: u32,
+    /// See [`RectNode::translation()`].
+    pub translation: Vec2,
+    /// See [`RectNode::size()`].
+    pub size: Size,
+    /// See [`RectNode::parent_constraint()`].
+    pub constraint: Constraint,
+    /// See [`RectNode::margin()`].
+    pub margin: Insets,
+    /// See [`RectNode::padding()`].
+    pub padding: Insets,
+}
+
+/// Snapshots.
+impl Rectree {
+    /// Takes a [`TreeSnapshot`] of the tree.
+    ///
+    /// Roots are listed in insertion order, each followed by its
+    /// subtree in document order.
+    pub fn snapshot(&self) -> TreeSnapshot {
+        let mut roots =
+            self.root_ids.iter().copied().collect::<Vec<_>>();
+        roots.sort_by_key(|id| {
+            core::cmp::Reverse(self.get(id).insertion_order)
+        });
+
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = roots;
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            stack.extend(self.get(&id).children.iter().rev());
+        }
+
+        let indices = order
+            .iter()
+            .enumerate()
+            .map(|(index, id)| (*id, index))
+            .collect::<HashMap<_, _>>();
+        let nodes = order
+            .iter()
+            .map(|id| {
+                let node = self.get(id);
+                NodeSnapshot {
+                    parent: node
+                        .parent
+                        .map(|parent| indices[&parent]),
+                    children: node
+                        .children
+                        .iter()
+                        .map(|child| indices[child])
+                        .collect(),
+                    depth: node.depth,
+                    translation: node.translation,
+                    size: node.size,
+                    constraint: node.parent_constraint,
+                    margin: node.margin,
+                    padding: node.padding,
+                }
+            })
+            .collect();
+
+        TreeSnapshot { nodes }
+    }
+
+    /// Builds a tree from a [`TreeSnapshot`], e.g. after loading a
+    /// save.
+    ///
+    /// The links and depths of the snapshot are taken as is and
+    /// then checked with [`Self::validate()`], so that a corrupted
+    /// snapshot is rejected instead of producing a broken tree. All
+    /// nodes are scheduled for relayout.
+    ///
+    /// Returns the tree along with the [`NodeId`] of every node, in
+    /// the order of [`TreeSnapshot::nodes`].
+    pub fn from_snapshot(
+        snapshot: &TreeSnapshot,
+    ) -> Result<(Self, Vec<NodeId>), ValidationError> {
+        let mut tree = Self::new();
+        let ids = snapshot
+            .nodes
+            .iter()
+            .map(|node| {
+                let mut rect_node = RectNode::new()
+                    .with_translation(node.translation)
+                    .with_size(node.size)
+                    .with_margin(node.margin)
+                    .with_padding(node.padding);
+                rect_node.parent_constraint = node.constraint;
+                tree.insert(rect_node)
+            })
+            .collect::<Vec<_>>();
+
+        let id_of = |index: usize| {
+            ids.get(index)
+                .copied()
+                .ok_or(ValidationError::InvalidIndex(index))
+        };
+        for (id, node) in ids.iter().zip(snapshot.nodes.iter()) {
+            let parent = node.parent.map(id_of).transpose()?;
+            let children = node
+                .children
+                .iter()
+                .map(|child| id_of(*child))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            // Every node has been inserted as a root.
+            if parent.is_some() {
+                tree.root_ids.remove(id);
+            }
+            let rect_node = Self::get_node_mut(&mut tree.nodes, id);
+            rect_node.parent = parent;
+            rect_node.children = children;
+            rect_node.depth = node.depth;
+        }
+        tree.validate()?;
+
+        // Reschedule with the actual depths.
+        tree.scheduled_relayout = ids
+            .iter()
+            .map(|id| DepthNode::new(tree.get(id), *id))
+            .collect();
+
+        Ok((tree, ids))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::solvers::PassthroughWorld;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut tree = Rectree::new();
+        let root = tree.insert(
+            RectNode::from_size((100.0, 100.0))
+                .with_padding(Insets::uniform(5.0)),
+        );
+        let a = tree.insert(
+            RectNode::from_translation_size(
+                (10.0, 0.0),
+                (20.0, 20.0),
+            )
+            .with_parent(root),
+        );
+        let b = tree.insert(
+            RectNode::from_size((30.0, 30.0)).with_parent(root),
+        );
+        let leaf = tree.insert(RectNode::new().with_parent(a));
+        let other = tree.insert(RectNode::from_size((5.0, 5.0)));
+        // Reorder the children away from their insertion order.
+        assert!(tree.set_child_index(&b, 0));
+        tree.layout(&PassthroughWorld);
+
+        let snapshot = tree.snapshot();
+        assert_eq!(
+            snapshot
+                .nodes
+                .iter()
+                .map(|node| (node.parent, node.children.clone()))
+                .collect::<Vec<_>>(),
+            [
+                (None, vec![1, 2]),
+                (Some(0), vec![]),
+                (Some(0), vec![3]),
+                (Some(2), vec![]),
+                (None, vec![]),
+            ]
+        );
+
+        let (mut loaded, ids) =
+            Rectree::from_snapshot(&snapshot).unwrap();
+        assert_eq!(ids.len(), 5);
+        assert_eq!(loaded.get(&ids[0]).children(), [ids[1], ids[2]]);
+        assert_eq!(loaded.get(&ids[3]).parent(), Some(ids[2]));
+        assert_eq!(loaded.snapshot(), snapshot);
+
+        loaded.layout(&PassthroughWorld);
+        for (id, loaded_id) in
+            [root, b, a, leaf, other].iter().zip(ids)
+        {
+            assert_eq!(
+                loaded.get(&loaded_id).world_rect(),
+                tree.get(id).world_rect()
+            );
+        }
+    }
+
+    #[test]
+    fn test_snapshot_rejects_inconsistent_links() {
+        let mut tree = Rectree::new();
+        let root = tree.insert(RectNode::new());
+        tree.insert(RectNode::new().with_parent(root));
+        let mut snapshot = tree.snapshot();
+
+        // The child no longer points back to the root, which still
+        // lists it.
+        snapshot.nodes[1].parent = None;
+        snapshot.nodes[1].depth = 0;
+        let err = Rectree::from_snapshot(&snapshot).unwrap_err();
+        assert!(matches!(
+            err,
+            ValidationError::ParentMismatch {
+                expected: Some(_),
+                found: None,
+                ..
+            }
+        ));
+
+        // The depth does not follow the parent chain.
+        let mut snapshot = tree.snapshot();
+        snapshot.nodes[1].depth = 3;
+        let err = Rectree::from_snapshot(&snapshot).unwrap_err();
+        assert!(matches!(
+            err,
+            ValidationError::DepthMismatch {
+                expected: 1,
+                found: 3,
+                ..
+            }
+        ));
+
+        let mut snapshot = tree.snapshot();
+        snapshot.nodes[0].children.push(7);
+        assert_eq!(
+            Rectree::from_snapshot(&snapshot).unwrap_err(),
+            ValidationError::InvalidIndex(7)
+        );
+    }
+}
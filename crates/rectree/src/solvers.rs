@@ -0,0 +1,756 @@
+//! Composable building blocks for [`LayoutSolver`]s.
+//!
+//! Wrapping a node's content in padding or alignment would
+//! otherwise cost an extra node per wrapper. A [`Decorated`] solver
+//! folds any number of [`Decorator`]s into a single node instead.
+//!
+//! Trees that only need translation propagation can use
+//! [`PassthroughWorld`] instead of implementing any solver.
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use kurbo::{Insets, Size, Vec2};
+
+use crate::layout::{
+    Constraint, ConstraintValue, LayoutSolver, LayoutWorld,
+    Positioner,
+};
+use crate::node::RectNode;
+use crate::{NodeId, Rectree};
+
+/// A [`LayoutWorld`] that keeps the authored sizes and
+/// translations of every node, see [`PassthroughSolver`].
+#[derive(Default, Debug, Clone, Copy)]
+pub struct PassthroughWorld;
+
+impl LayoutWorld for PassthroughWorld {
+    fn get_solver(&self, _id: &NodeId) -> &dyn LayoutSolver {
+        &PassthroughSolver
+    }
+}
+
+/// A [`LayoutSolver`] that keeps the current size of its node and
+/// the current translations of its children.
+///
+/// Children are left unconstrained, so that no authored size is
+/// ever reported as an overflow.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct PassthroughSolver;
+
+impl LayoutSolver for PassthroughSolver {
+    fn constraint(
+        &self,
+        _parent_constraint: Constraint,
+    ) -> Constraint {
+        Constraint::flexible()
+    }
+
+    fn build(
+        &self,
+        node: &RectNode,
+        tree: &Rectree,
+        positioner: &mut Positioner,
+    ) -> Size {
+        let padding = node.padding();
+        let padding_offset = Vec2::new(padding.x0, padding.y0);
+        for child in tree.layout_children(node).iter() {
+            // Translations are written relative to the padding.
+            positioner.set(
+                child.id,
+                child.node.translation() - padding_offset,
+            );
+        }
+
+        node.built_size() - padding.size() - node.margin().size()
+    }
+}
+
+/// Sizes itself relative to the constraint imposed by its parent,
+/// e.g. 50% of the parent's width.
+///
+/// Resolved axes are tight, while [`ConstraintValue::Flexible`] axes
+/// and percentages of an unconstrained parent keep the parent's
+/// bound and fit the children. Children are placed at the origin.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct PercentBox {
+    pub width: ConstraintValue,
+    pub height: ConstraintValue,
+}
+
+impl PercentBox {
+    pub fn new(
+        width: ConstraintValue,
+        height: ConstraintValue,
+    ) -> Self {
+        Self { width, height }
+    }
+}
+
+impl LayoutSolver for PercentBox {
+    fn constraint(
+        &self,
+        parent_constraint: Constraint,
+    ) -> Constraint {
+        let mut constraint = parent_constraint;
+        if let Some(width) =
+            self.width.resolve(parent_constraint.width)
+        {
+            constraint = constraint.with_fixed_width(width);
+        }
+        if let Some(height) =
+            self.height.resolve(parent_constraint.height)
+        {
+            constraint = constraint.with_fixed_height(height);
+        }
+        constraint
+    }
+
+    fn build(
+        &self,
+        node: &RectNode,
+        tree: &Rectree,
+        positioner: &mut Positioner,
+    ) -> Size {
+        let mut content = Size::ZERO;
+        for child in tree.layout_children(node).iter() {
+            positioner.set(child.id, Vec2::ZERO);
+            let size = child.size();
+            content.width = content.width.max(size.width);
+            content.height = content.height.max(size.height);
+        }
+
+        self.constraint(node.parent_constraint).resolve(content)
+    }
+}
+
+/// Arranges children into rows of [`Self::columns`] cells, in
+/// document order.
+///
+/// If the width of the grid is bounded, each cell is constrained to
+/// an equal share of it. With [`Self::uniform`], all cells are
+/// assumed to have the same size, so only the first child is
+/// measured and its size is reused for every cell, which is much
+/// cheaper for thousands of identical cells. Otherwise, each column
+/// is as wide as its widest child, and each row as tall as its
+/// tallest child.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UniformGrid {
+    pub columns: usize,
+    /// Horizontal and vertical space between cells.
+    pub spacing: Vec2,
+    pub uniform: bool,
+}
+
+impl UniformGrid {
+    /// Creates a uniform grid without spacing.
+    pub fn new(columns: usize) -> Self {
+        Self {
+            columns,
+            spacing: Vec2::ZERO,
+            uniform: true,
+        }
+    }
+
+    pub fn with_spacing(mut self, spacing: Vec2) -> Self {
+        self.spacing = spacing;
+        self
+    }
+
+    pub fn with_uniform(mut self, uniform: bool) -> Self {
+        self.uniform = uniform;
+        self
+    }
+
+    /// [`Self::columns`], with at least a single column.
+    fn column_count(&self) -> usize {
+        self.columns.max(1)
+    }
+}
+
+impl LayoutSolver for UniformGrid {
+    fn constraint(
+        &self,
+        parent_constraint: Constraint,
+    ) -> Constraint {
+        match parent_constraint.width {
+            Some(width) if parent_constraint.has_bounded_width() => {
+                let columns = self.column_count() as f64;
+                let spacing = self.spacing.x * (columns - 1.0);
+                Constraint::loose_width(
+                    ((width - spacing) / columns).max(0.0),
+                )
+            }
+            _ => Constraint::flexible(),
+        }
+    }
+
+    fn build(
+        &self,
+        node: &RectNode,
+        tree: &Rectree,
+        positioner: &mut Positioner,
+    ) -> Size {
+        let children = tree.layout_children(node);
+        if children.is_empty() {
+            return node.parent_constraint.resolve(Size::ZERO);
+        }
+        let columns = self.column_count().min(children.len());
+        let rows = children.len().div_ceil(columns);
+
+        let (widths, heights) = if self.uniform {
+            // Measure once, place many.
+            let cell = children.get(0).map(|c| c.size()).unwrap();
+            (vec![cell.width; columns], vec![cell.height; rows])
+        } else {
+            let mut widths = vec![0.0_f64; columns];
+            let mut heights = vec![0.0_f64; rows];
+            for (index, child) in children.iter().enumerate() {
+                let size = child.size();
+                let (row, column) =
+                    (index / columns, index % columns);
+                widths[column] = widths[column].max(size.width);
+                heights[row] = heights[row].max(size.height);
+            }
+            (widths, heights)
+        };
+
+        // Offsets of each column and row, followed by the total.
+        let offsets = |sizes: Vec<f64>, spacing: f64| {
+            let mut offset = 0.0;
+            let mut offsets = Vec::with_capacity(sizes.len() + 1);
+            for size in sizes {
+                offsets.push(offset);
+                offset += size + spacing;
+            }
+            offsets.push(offset - spacing);
+            offsets
+        };
+        let x = offsets(widths, self.spacing.x);
+        let y = offsets(heights, self.spacing.y);
+
+        for (index, id) in children.ids().iter().enumerate() {
+            let (row, column) = (index / columns, index % columns);
+            positioner.set(*id, Vec2::new(x[column], y[row]));
+        }
+
+        node.parent_constraint
+            .resolve(Size::new(x[columns], y[rows]))
+    }
+}
+
+/// A single stage of a [`Decorated`] solver.
+///
+/// A decorator transforms the constraint on the way down and wraps
+/// the size of the next stage on the way up, the same way a parent
+/// node would wrap a single child.
+pub trait Decorator {
+    /// Transforms the constraint passed on to the next stage.
+    ///
+    /// By default, the constraint is forwarded unchanged.
+    fn constraint(&self, constraint: Constraint) -> Constraint {
+        constraint
+    }
+
+    /// Wraps the size resolved by the next stage.
+    ///
+    /// `constraint` is the one this stage received, before
+    /// [`Self::constraint()`] was applied. Returns the size of this
+    /// stage and the offset of the next stage within it.
+    fn decorate(
+        &self,
+        constraint: Constraint,
+        inner: Size,
+    ) -> (Size, Vec2);
+}
+
+/// A [`LayoutSolver`] wrapped by a chain of [`Decorator`]s.
+///
+/// Decorators are ordered from the outermost to the innermost.
+/// Constraints are transformed outside-in, each decorator seeing the
+/// output of the previous one, before reaching the inner solver.
+/// Sizes are then wrapped inside-out, and the offsets of all stages
+/// are accumulated onto the translations of the node's children.
+pub struct Decorated<S> {
+    decorators: Vec<Box<dyn Decorator>>,
+    inner: S,
+}
+
+impl<S> Decorated<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            decorators: Vec::new(),
+            inner,
+        }
+    }
+
+    /// Appends a decorator inside of the existing ones.
+    pub fn with(
+        mut self,
+        decorator: impl Decorator + 'static,
+    ) -> Self {
+        self.decorators.push(Box::new(decorator));
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+}
+
+impl<S: LayoutSolver> LayoutSolver for Decorated<S> {
+    fn constraint(
+        &self,
+        parent_constraint: Constraint,
+    ) -> Constraint {
+        let constraint = self
+            .decorators
+            .iter()
+            .fold(parent_constraint, |c, d| d.constraint(c));
+        self.inner.constraint(constraint)
+    }
+
+    fn build(
+        &self,
+        node: &RectNode,
+        tree: &Rectree,
+        positioner: &mut Positioner,
+    ) -> Size {
+        build_decorated(
+            &self.decorators,
+            node,
+            positioner,
+            |node, positioner| {
+                self.inner.build(node, tree, positioner)
+            },
+        )
+    }
+
+    fn build_mut(
+        &mut self,
+        node: &RectNode,
+        tree: &Rectree,
+        positioner: &mut Positioner,
+    ) -> Size {
+        build_decorated(
+            &self.decorators,
+            node,
+            positioner,
+            |node, positioner| {
+                self.inner.build_mut(node, tree, positioner)
+            },
+        )
+    }
+}
+
+/// Runs `build` against the decorated constraint and wraps the
+/// resulting size with the decorators, inside-out.
+fn build_decorated(
+    decorators: &[Box<dyn Decorator>],
+    node: &RectNode,
+    positioner: &mut Positioner,
+    build: impl FnOnce(&RectNode, &mut Positioner) -> Size,
+) -> Size {
+    // Constraints received by each decorator.
+    let mut constraints = Vec::with_capacity(decorators.len());
+    let mut constraint = node.parent_constraint;
+    for decorator in decorators.iter() {
+        constraints.push(constraint);
+        constraint = decorator.constraint(constraint);
+    }
+
+    // The inner solver must see the decorated constraint.
+    let scoped;
+    let node = if constraint == node.parent_constraint {
+        node
+    } else {
+        let mut clone = node.clone();
+        clone.parent_constraint = constraint;
+        scoped = clone;
+        &scoped
+    };
+
+    let mut inner_positioner = Positioner::default();
+    let mut size = build(node, &mut inner_positioner);
+
+    let mut offset = Vec2::ZERO;
+    for (decorator, constraint) in
+        decorators.iter().zip(constraints).rev()
+    {
+        let (outer, inner_offset) =
+            decorator.decorate(constraint, size);
+        size = outer;
+        offset += inner_offset;
+    }
+
+    positioner.extend_offset(inner_positioner, offset);
+    size
+}
+
+/// Insets the next stage, see [`Constraint::deflate()`].
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Padding(pub Insets);
+
+impl Decorator for Padding {
+    fn constraint(&self, constraint: Constraint) -> Constraint {
+        constraint.deflate(self.0)
+    }
+
+    fn decorate(
+        &self,
+        _constraint: Constraint,
+        inner: Size,
+    ) -> (Size, Vec2) {
+        let size = Size::new(
+            inner.width + self.0.x_value(),
+            inner.height + self.0.y_value(),
+        );
+        (size, Vec2::new(self.0.x0, self.0.y0))
+    }
+}
+
+/// Fills the bounded dimensions of the constraint and aligns the
+/// next stage within it.
+///
+/// The alignment factors range from `0.0` (left/top) to `1.0`
+/// (right/bottom). The next stage receives a loosened constraint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Align {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Align {
+    pub const TOP_LEFT: Self = Self::new(0.0, 0.0);
+    pub const CENTER: Self = Self::new(0.5, 0.5);
+    pub const BOTTOM_RIGHT: Self = Self::new(1.0, 1.0);
+
+    pub const fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+}
+
+impl Decorator for Align {
+    fn constraint(&self, constraint: Constraint) -> Constraint {
+        constraint.loosen()
+    }
+
+    fn decorate(
+        &self,
+        constraint: Constraint,
+        inner: Size,
+    ) -> (Size, Vec2) {
+        let size = constraint.biggest(inner);
+        let offset = Vec2::new(
+            (size.width - inner.width) * self.x,
+            (size.height - inner.height) * self.y,
+        );
+        (size, offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hashbrown::HashMap;
+    use kurbo::Rect;
+
+    use super::*;
+    use crate::NodeId;
+    use crate::layout::LayoutWorld;
+
+    /// Places all children at the origin and wraps the largest.
+    struct Passthrough;
+
+    impl LayoutSolver for Passthrough {
+        fn build(
+            &self,
+            node: &RectNode,
+            tree: &Rectree,
+            positioner: &mut Positioner,
+        ) -> Size {
+            let mut size = Size::ZERO;
+            for id in node.children() {
+                positioner.set(*id, Vec2::ZERO);
+                let child_size = tree.get(id).outer_size();
+                size.width = size.width.max(child_size.width);
+                size.height = size.height.max(child_size.height);
+            }
+            size
+        }
+    }
+
+    struct Fixed(Size);
+
+    impl LayoutSolver for Fixed {
+        fn build(
+            &self,
+            _node: &RectNode,
+            _tree: &Rectree,
+            _positioner: &mut Positioner,
+        ) -> Size {
+            self.0
+        }
+    }
+
+    #[derive(Default)]
+    struct BoxWorld {
+        solvers: HashMap<NodeId, Box<dyn LayoutSolver>>,
+    }
+
+    impl BoxWorld {
+        fn insert(
+            &mut self,
+            tree: &mut Rectree,
+            parent: Option<NodeId>,
+            solver: impl LayoutSolver + 'static,
+        ) -> NodeId {
+            let mut node = RectNode::new();
+            if let Some(parent) = parent {
+                node = node.with_parent(parent);
+            }
+            let id = tree.insert(node);
+            self.solvers.insert(id, Box::new(solver));
+            id
+        }
+    }
+
+    impl LayoutWorld for BoxWorld {
+        fn get_solver(&self, id: &NodeId) -> &dyn LayoutSolver {
+            self.solvers[id].as_ref()
+        }
+    }
+
+    const PADDING: Padding =
+        Padding(Insets::new(10.0, 5.0, 2.0, 3.0));
+
+    /// Lays out a leaf wrapped by [`PADDING`] and [`Align::CENTER`],
+    /// returning the world rects of the root and the leaf.
+    fn layout_leaf(chained: bool) -> (Rect, Rect) {
+        let mut tree = Rectree::new();
+        let mut world = BoxWorld::default();
+
+        let root = if chained {
+            let solver = Decorated::new(Passthrough)
+                .with(PADDING)
+                .with(Align::CENTER);
+            world.insert(&mut tree, None, solver)
+        } else {
+            let solver = Decorated::new(Passthrough).with(PADDING);
+            let root = world.insert(&mut tree, None, solver);
+            let solver =
+                Decorated::new(Passthrough).with(Align::CENTER);
+            world.insert(&mut tree, Some(root), solver);
+            root
+        };
+        let parent =
+            tree.get(&root).children().iter().next().copied();
+        let leaf = world.insert(
+            &mut tree,
+            Some(parent.unwrap_or(root)),
+            Fixed(Size::new(20.0, 10.0)),
+        );
+
+        tree.set_root_constraint(
+            root,
+            Constraint::fixed(100.0, 80.0),
+        );
+        tree.layout(&world);
+
+        (tree.get(&root).world_rect(), tree.get(&leaf).world_rect())
+    }
+
+    #[test]
+    fn test_decorated_matches_nested_nodes() {
+        let (root_rect, leaf_rect) = layout_leaf(true);
+        assert_eq!(root_rect, Rect::new(0.0, 0.0, 100.0, 80.0));
+        // Centered within the 88x72 padded area.
+        assert_eq!(leaf_rect, Rect::new(44.0, 36.0, 64.0, 46.0));
+
+        assert_eq!(layout_leaf(false), (root_rect, leaf_rect));
+    }
+
+    #[test]
+    fn test_passthrough_world() {
+        let mut tree = Rectree::new();
+        let root = tree.insert(
+            RectNode::from_rect(Rect::new(10.0, 10.0, 110.0, 60.0))
+                .with_padding(Insets::uniform(5.0)),
+        );
+        let child = tree.insert(
+            RectNode::from_rect(Rect::new(20.0, 5.0, 220.0, 25.0))
+                .with_parent(root)
+                .with_margin(Insets::uniform(2.0)),
+        );
+        tree.layout(&PassthroughWorld);
+
+        // Sizes are kept even if the child overflows the root.
+        assert_eq!(tree.get(&root).size(), Size::new(100.0, 50.0));
+        assert_eq!(
+            tree.get(&child).outer_size(),
+            Size::new(200.0, 20.0)
+        );
+        assert!(tree.take_overflows().is_empty());
+        assert_eq!(
+            tree.get(&child).translation(),
+            Vec2::new(20.0, 5.0)
+        );
+        // Offset by the root and the margin.
+        assert_eq!(
+            tree.get(&child).world_translation(),
+            Vec2::new(32.0, 17.0)
+        );
+
+        // Moving the root only repositions.
+        tree.get_mut(&root).translation = Vec2::new(50.0, 50.0);
+        tree.schedule_relayout(root);
+        tree.layout(&PassthroughWorld);
+        assert_eq!(
+            tree.get(&child).outer_size(),
+            Size::new(200.0, 20.0)
+        );
+        assert_eq!(
+            tree.get(&child).world_translation(),
+            Vec2::new(72.0, 57.0)
+        );
+    }
+
+    #[test]
+    fn test_percent_box() {
+        use ConstraintValue::{Flexible, Percent};
+
+        let layout = |constraint: Constraint, width, height| {
+            let mut tree = Rectree::new();
+            let mut world = BoxWorld::default();
+            let root = world.insert(
+                &mut tree,
+                None,
+                PercentBox::new(width, height),
+            );
+            world.insert(
+                &mut tree,
+                Some(root),
+                Fixed(Size::new(40.0, 30.0)),
+            );
+            tree.set_root_constraint(root, constraint);
+            tree.layout(&world);
+            tree.get(&root).size()
+        };
+
+        let bounded = Constraint::fixed(200.0, 100.0);
+        assert_eq!(
+            layout(bounded, Percent(50.0), Percent(100.0)),
+            Size::new(100.0, 100.0)
+        );
+        assert_eq!(
+            layout(bounded, Percent(100.0), Flexible),
+            Size::new(200.0, 100.0)
+        );
+        assert_eq!(
+            layout(bounded.loosen(), Flexible, Percent(50.0)),
+            Size::new(40.0, 50.0)
+        );
+
+        // Percentages of an unconstrained parent fit the content.
+        let unbounded = Constraint::flexible();
+        assert_eq!(
+            layout(unbounded, Percent(50.0), Percent(100.0)),
+            Size::new(40.0, 30.0)
+        );
+        assert_eq!(
+            layout(unbounded, ConstraintValue::Fixed(60.0), Flexible),
+            Size::new(60.0, 30.0)
+        );
+    }
+
+    #[test]
+    fn test_uniform_grid() {
+        let mut tree = Rectree::new();
+        let mut world = BoxWorld::default();
+        let grid =
+            UniformGrid::new(10).with_spacing(Vec2::new(2.0, 4.0));
+        let root = world.insert(&mut tree, None, grid);
+        let cells = (0..1000)
+            .map(|_| {
+                world.insert(
+                    &mut tree,
+                    Some(root),
+                    Fixed(Size::new(20.0, 10.0)),
+                )
+            })
+            .collect::<Vec<_>>();
+        tree.layout(&world);
+
+        // 10 columns of 20 + 9 spacings, 100 rows of 10 + 99.
+        assert_eq!(tree.get(&root).size(), Size::new(218.0, 1396.0));
+        for (index, cell) in cells.iter().enumerate() {
+            let (row, column) = (index / 10, index % 10);
+            assert_eq!(
+                tree.get(cell).translation(),
+                Vec2::new(column as f64 * 22.0, row as f64 * 14.0)
+            );
+        }
+    }
+
+    /// A [`Fixed`] solver counting its builds.
+    #[test]
+    fn test_uniform_grid_measures_once() {
+        let layout = |uniform| {
+            let mut tree = Rectree::new();
+            let mut world = BoxWorld::default();
+            let grid = UniformGrid::new(2).with_uniform(uniform);
+            let root = world.insert(&mut tree, None, grid);
+            let cells = [
+                Size::new(10.0, 10.0),
+                Size::new(30.0, 10.0),
+                Size::new(10.0, 20.0),
+            ]
+            .map(|size| {
+                world.insert(&mut tree, Some(root), Fixed(size))
+            });
+            tree.set_root_constraint(
+                root,
+                Constraint::loose_width(100.0),
+            );
+            tree.layout(&world);
+
+            // Cells are constrained to half of the width.
+            assert_eq!(
+                tree.get(&cells[0]).parent_constraint(),
+                Constraint::loose_width(50.0)
+            );
+            let translations =
+                cells.map(|cell| tree.get(&cell).translation());
+            (tree.get(&root).size(), translations)
+        };
+
+        // Only the first cell is measured.
+        let (size, translations) = layout(true);
+        assert_eq!(size, Size::new(20.0, 20.0));
+        assert_eq!(
+            translations,
+            [(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)].map(Vec2::from)
+        );
+
+        // Mixed sizes are measured per child.
+        let (size, translations) = layout(false);
+        assert_eq!(size, Size::new(40.0, 30.0));
+        assert_eq!(
+            translations,
+            [(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)].map(Vec2::from)
+        );
+    }
+
+    #[test]
+    fn test_decorated_constraint() {
+        let solver = Decorated::new(Passthrough)
+            .with(PADDING)
+            .with(Align::CENTER);
+        let constraint =
+            solver.constraint(Constraint::fixed(100.0, 80.0));
+        assert_eq!(constraint, Constraint::loose(88.0, 72.0));
+    }
+}
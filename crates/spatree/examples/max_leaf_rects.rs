@@ -0,0 +1,62 @@
+//! Compares query times for 10k tiny rects across several
+//! [`Spatree::max_leaf_rects()`].
+//!
+//! Run with `cargo run --release -p spatree --example max_leaf_rects`.
+
+use std::time::Instant;
+
+use kurbo::{Point, Rect};
+use spatree::{QueryScratch, Spatree};
+
+const ITERATIONS: u32 = 100;
+
+fn main() {
+    let mut next = random(7);
+    let rects = (0..10_000)
+        .map(|_| {
+            let (x, y) = (next() * 1000.0, next() * 1000.0);
+            Rect::new(x, y, x + next() * 4.0, y + next() * 4.0)
+        })
+        .collect::<Vec<_>>();
+    let points = (0..1000)
+        .map(|_| Point::new(next() * 1000.0, next() * 1000.0))
+        .collect::<Vec<_>>();
+
+    let mut expected = None;
+    for max_leaf_rects in [1, 4, 8, 16] {
+        let mut tree = Spatree::new();
+        tree.set_max_leaf_rects(max_leaf_rects);
+        for rect in rects.iter() {
+            tree.push_rect(*rect);
+        }
+        tree.build(|r| r.center());
+
+        let mut hits = 0;
+        let mut out = Vec::new();
+        let mut scratch = QueryScratch::new();
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            for point in points.iter() {
+                tree.query_point_into(*point, &mut out, &mut scratch);
+                hits += out.len();
+            }
+        }
+        let elapsed = start.elapsed() / ITERATIONS;
+
+        println!(
+            "max_leaf_rects {max_leaf_rects}: {elapsed:?}, {} nodes",
+            tree.nodes().len()
+        );
+        assert_eq!(*expected.get_or_insert(hits), hits);
+    }
+}
+
+/// Deterministic pseudo random values in `[0, 1)`.
+fn random(mut seed: u64) -> impl FnMut() -> f64 {
+    move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -3,14 +3,15 @@
 
 extern crate alloc;
 
-use core::ops::Deref;
+use core::cmp::{Ordering, Reverse};
+use core::fmt::{Display, Formatter};
+use core::ops::{ControlFlow, Deref};
 
-use alloc::boxed::Box;
-use alloc::vec;
+use alloc::collections::BinaryHeap;
 use alloc::vec::Vec;
-use kurbo::{Point, Rect};
+use kurbo::{Affine, Point, Rect, Vec2};
 
-use crate::morton::{MortonCode, find_split, morton_2d_f64};
+use crate::morton::{MortonBits, MortonCode, MortonPrecision};
 
 pub mod morton;
 
@@ -20,11 +21,48 @@ pub mod morton;
 /// Z-order curve. Sorting these codes ensures spatially close objects
 /// are adjacent in memory, allowing for efficient top-down hierarchy
 /// generation.
+///
+/// Rects with non-finite coordinates (NaN or infinite) are kept so
+/// that [`RectId`]s remain stable, but they are excluded from
+/// [`Spatree::global_bound()`] and from the hierarchy, so queries
+/// never hit them. See [`Spatree::non_finite_count()`].
+///
+/// Removed rects are tombstoned, see [`Spatree::remove_rect()`].
 #[derive(Default)]
 pub struct Spatree {
-    global_bound: Rect,
+    /// See [`Self::global_bound()`], or `None` until the first
+    /// finite rect is pushed.
+    global_bound: Option<Rect>,
     rects: Vec<Rect>,
+    /// Whether each rect has been removed, indexed by [`RectId`].
+    removed: Vec<bool>,
+    /// Number of rects with non-finite coordinates.
+    non_finite: usize,
     nodes: Vec<Node>,
+    /// Index of the internal node that holds each rect as a leaf,
+    /// indexed by [`RectId`].
+    leaf_parents: Vec<Option<usize>>,
+    /// Internal nodes of additional hierarchies, indexed by
+    /// [`IndexId`], or `None` once removed.
+    indices: Vec<Option<Vec<Node>>>,
+    /// See [`Spatree::is_incrementally_modified()`].
+    incrementally_modified: bool,
+    /// See [`Spatree::set_morton_precision()`].
+    morton_precision: MortonPrecision,
+    /// See [`Spatree::set_max_leaf_rects()`].
+    max_leaf_rects: usize,
+    /// Rect indices in Morton order, ranges of which are held by
+    /// [`NodeId::Bucket`]s of the main hierarchy.
+    bucket_rects: Vec<usize>,
+    /// Scratch buffer of [`Spatree::build()`], kept to reuse its
+    /// allocation across rebuilds.
+    morton_codes: Vec<MortonCode>,
+    /// Same as [`Self::morton_codes`], but for
+    /// [`MortonPrecision::Bits64`].
+    morton_codes_64: Vec<MortonCode<u64>>,
+    /// Scratch buffer of [`Spatree::build()`], see
+    /// [`Self::morton_codes`].
+    build_stack: Vec<BuildStack>,
 }
 
 // Builders.
@@ -38,108 +76,802 @@ impl Spatree {
     ///
     /// If this is performed after [`Self::build()`], a rebuild will
     /// be required to cater for the change!
+    ///
+    /// A rect with non-finite coordinates is stored, but never hit
+    /// by queries. Use [`Self::try_push_rect()`] to reject it
+    /// instead.
     pub fn push_rect(&mut self, rect: Rect) -> RectId {
         let index = self.rects.len();
         self.rects.push(rect);
-        // Fit the global bound to the new rect.
-        self.global_bound = self.global_bound.union(rect);
+        self.removed.push(false);
+        if rect.is_finite() {
+            // Fit the global bound to the new rect.
+            self.extend_global_bound(rect);
+        } else {
+            self.non_finite += 1;
+        }
         RectId(index)
     }
 
+    /// Removes all rects and hierarchies, including the ones built
+    /// via [`Self::build_index()`], and resets
+    /// [`Self::global_bound()`]. Previously returned [`RectId`]s
+    /// are invalidated, as ids start over from zero.
+    ///
+    /// The allocations of the main hierarchy are kept, so that
+    /// refilling the tree and rebuilding it with the same number of
+    /// rects does not allocate, see [`Self::capacity()`].
+    pub fn clear(&mut self) {
+        self.global_bound = None;
+        self.rects.clear();
+        self.removed.clear();
+        self.non_finite = 0;
+        self.nodes.clear();
+        self.leaf_parents.clear();
+        self.bucket_rects.clear();
+        self.indices.clear();
+        self.incrementally_modified = false;
+    }
+
+    /// Sets the precision of the Morton codes used by the following
+    /// builds, see [`MortonPrecision`].
+    ///
+    /// [`MortonPrecision::Bits64`] keeps large or fine-grained scenes
+    /// from collapsing onto identical codes, which would otherwise
+    /// degrade the hierarchy and slow down queries.
+    pub fn set_morton_precision(
+        &mut self,
+        precision: MortonPrecision,
+    ) {
+        self.morton_precision = precision;
+    }
+
+    /// See [`Self::set_morton_precision()`].
+    pub fn morton_precision(&self) -> MortonPrecision {
+        self.morton_precision
+    }
+
+    /// Sets the maximum number of rects the following builds store
+    /// in a single [`NodeId::Bucket`] of the main hierarchy, instead
+    /// of splitting them further. Clamped to at least `1`, the
+    /// default, which stores every rect in its own leaf.
+    ///
+    /// In dense scenes with many small rects, buckets keep the
+    /// hierarchy shallow, while queries test every rect within a
+    /// bucket they reach. Hierarchies built via
+    /// [`Self::build_index()`] always use one rect per leaf.
+    pub fn set_max_leaf_rects(&mut self, max_leaf_rects: usize) {
+        self.max_leaf_rects = max_leaf_rects.max(1);
+    }
+
+    /// See [`Self::set_max_leaf_rects()`].
+    pub fn max_leaf_rects(&self) -> usize {
+        self.max_leaf_rects.max(1)
+    }
+
+    /// Number of rects the tree can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.rects.capacity()
+    }
+
+    /// Same as [`Self::push_rect()`], but returns `None` without
+    /// pushing if the rect has non-finite coordinates.
+    pub fn try_push_rect(&mut self, rect: Rect) -> Option<RectId> {
+        rect.is_finite().then(|| self.push_rect(rect))
+    }
+
+    /// Same as [`Self::push_rect()`], but also inserts the rect into
+    /// the existing hierarchy, so queries hit it without a rebuild.
+    ///
+    /// The leaf is placed by descending from the root into the
+    /// child whose bound grows the least, creating one new internal
+    /// node, after which the bounds of its ancestors are refitted.
+    /// This does not balance the hierarchy, see
+    /// [`Self::is_incrementally_modified()`] for when to rebuild.
+    ///
+    /// Hierarchies built via [`Self::build_index()`] are not
+    /// updated, queries on them never hit the rect until they are
+    /// rebuilt via [`Self::rebuild_index()`].
+    pub fn insert_built(&mut self, rect: Rect) -> RectId {
+        let id = self.push_rect(rect);
+        if !rect.is_finite() {
+            return id;
+        }
+        self.leaf_parents.resize(self.rects.len(), None);
+        self.incrementally_modified = true;
+
+        let leaf = NodeId::Leaf(*id);
+        if self.nodes.is_empty() {
+            self.nodes.push(Node {
+                rect,
+                children: [leaf, NodeId::Invalid],
+                ..Node::EMPTY
+            });
+            self.leaf_parents[*id] = Some(0);
+            return id;
+        }
+
+        let mut node_idx = 0;
+        loop {
+            let children = self.nodes[node_idx].children;
+            // Fill an empty slot, e.g. of a single rect root.
+            if let Some(slot) =
+                children.iter().position(|c| *c == NodeId::Invalid)
+            {
+                self.nodes[node_idx].children[slot] = leaf;
+                self.leaf_parents[*id] = Some(node_idx);
+                break;
+            }
+
+            let bound = |child: NodeId| {
+                child_bound(
+                    &self.nodes,
+                    &self.rects,
+                    &self.bucket_rects,
+                    child,
+                )
+                .unwrap_or_else(|| unreachable!())
+            };
+            let growth = |child: NodeId| {
+                let bound = bound(child);
+                bound.union(rect).area() - bound.area()
+            };
+            let slot = usize::from(
+                growth(children[1]) < growth(children[0]),
+            );
+
+            match children[slot] {
+                NodeId::Internal(idx) => node_idx = idx,
+                NodeId::Leaf(_) | NodeId::Bucket { .. } => {
+                    // Pair the existing leaf or bucket with the new
+                    // leaf under a new internal node, which keeps
+                    // children at a higher index than their parents.
+                    let new_idx = self.nodes.len();
+                    self.nodes.push(Node {
+                        rect: bound(children[slot]).union(rect),
+                        parent: Some(node_idx),
+                        children: [children[slot], leaf],
+                    });
+                    self.nodes[node_idx].children[slot] =
+                        NodeId::Internal(new_idx);
+                    for rect_idx in child_leaves(
+                        &self.bucket_rects,
+                        &children[slot],
+                    ) {
+                        self.leaf_parents[*rect_idx] = Some(new_idx);
+                    }
+                    self.leaf_parents[*id] = Some(new_idx);
+                    break;
+                }
+                NodeId::Invalid => unreachable!(),
+            }
+        }
+
+        // The bounds are refitted from the node whose children
+        // changed, up to the root.
+        self.refit_ancestors(Some(node_idx));
+        id
+    }
+
+    /// Returns `true` if rects were inserted via
+    /// [`Self::insert_built()`] since the last [`Self::build()`].
+    ///
+    /// Incremental insertions leave the hierarchy unbalanced, which
+    /// slows down queries over time. A clean rebuild restores it.
+    pub fn is_incrementally_modified(&self) -> bool {
+        self.incrementally_modified
+    }
+
+    /// Removes a rect, returning it if it was not removed already.
+    ///
+    /// The rect is tombstoned: queries skip it right away, while it
+    /// stays in the hierarchy until the next [`Self::build()`].
+    /// [`RectId`]s are never reused.
+    ///
+    /// [`Self::global_bound()`] is not shrunk until the next
+    /// [`Self::build()`], see [`Self::recompute_global_bound()`].
+    pub fn remove_rect(&mut self, id: RectId) -> Option<Rect> {
+        let removed = self.removed.get_mut(*id)?;
+        if *removed {
+            return None;
+        }
+        *removed = true;
+
+        let rect = self.rects[*id];
+        if !rect.is_finite() {
+            self.non_finite -= 1;
+        }
+        if let Some(leaf_parent) = self.leaf_parents.get_mut(*id) {
+            *leaf_parent = None;
+        }
+        Some(rect)
+    }
+
+    /// Replaces a rect, returning the previous one.
+    ///
+    /// If the rect is part of the hierarchy, the bounds of its
+    /// ancestors are refitted right away, so queries stay correct
+    /// without a rebuild. The hierarchy is not reordered, so its
+    /// quality degrades as rects move away from their original
+    /// positions, until the next [`Self::build()`].
+    ///
+    /// Hierarchies built via [`Self::build_index()`] are not
+    /// refitted, queries on them may miss the rect until the next
+    /// [`Self::refit()`] or [`Self::rebuild_index()`].
+    ///
+    /// Returns `None` without replacing if the rect was removed, or
+    /// if the new rect has non-finite coordinates.
+    pub fn update_rect(
+        &mut self,
+        id: RectId,
+        rect: Rect,
+    ) -> Option<Rect> {
+        let previous = self.set_rect(id, rect)?;
+
+        self.refit_ancestors(self.leaf_node_of(id));
+        Some(previous)
+    }
+
+    /// Same as [`Self::update_rect()`], but without refitting any
+    /// bounds.
+    ///
+    /// Queries may miss the rect until the next [`Self::refit()`]
+    /// or [`Self::build()`], which is cheaper when many rects are
+    /// replaced at once.
+    pub fn set_rect(
+        &mut self,
+        id: RectId,
+        rect: Rect,
+    ) -> Option<Rect> {
+        if self.is_removed(id) || !rect.is_finite() {
+            return None;
+        }
+        let previous =
+            core::mem::replace(self.rects.get_mut(*id)?, rect);
+        if !previous.is_finite() {
+            self.non_finite -= 1;
+        }
+        self.extend_global_bound(rect);
+
+        Some(previous)
+    }
+
+    /// Returns `true` if the rect has been removed via
+    /// [`Self::remove_rect()`].
+    pub fn is_removed(&self, id: RectId) -> bool {
+        self.removed.get(*id).copied().unwrap_or(false)
+    }
+
+    /// Number of pushed rects with non-finite coordinates, which
+    /// are excluded from the hierarchy.
+    pub fn non_finite_count(&self) -> usize {
+        self.non_finite
+    }
+
     /// Get a specific [`Rect`] for a given [`RectId`].
+    ///
+    /// Returns `None` for removed rects.
     pub fn get_rect(&self, id: RectId) -> Option<&Rect> {
-        self.rects.get(*id)
+        self.rects.get(*id).filter(|_| !self.is_removed(id))
     }
 
     /// Obtain the global bounding box of the spatial tree.
     /// Thi global bound is accumulated during
-    /// [`Self::push_rect()`] calls.
+    /// [`Self::push_rect()`] calls, starting from the first finite
+    /// rect, or [`Rect::ZERO`] if there is none.
+    ///
+    /// Removing or moving rects does not shrink the bound, which may
+    /// then be larger than needed until the next [`Self::build()`],
+    /// see [`Self::recompute_global_bound()`].
     pub fn global_bound(&self) -> &Rect {
-        &self.global_bound
+        self.global_bound.as_ref().unwrap_or(&Rect::ZERO)
+    }
+
+    /// Recomputes [`Self::global_bound()`] from the remaining
+    /// rects in `O(n)`.
+    ///
+    /// This is done automatically by [`Self::build()`].
+    pub fn recompute_global_bound(&mut self) {
+        self.global_bound = self
+            .rects
+            .iter()
+            .zip(&self.removed)
+            .filter(|(rect, removed)| rect.is_finite() && !**removed)
+            .map(|(rect, _)| *rect)
+            .reduce(|bound, rect| bound.union(rect));
+    }
+
+    /// Unions [`Self::global_bound()`] with a finite rect.
+    fn extend_global_bound(&mut self, rect: Rect) {
+        self.global_bound = Some(
+            self.global_bound.map_or(rect, |bound| bound.union(rect)),
+        );
     }
 
     /// Constructs a spatial hierarchy (LBVH) from the current set of rectangles.
     ///
+    /// Removed rects are left out, see [`Self::remove_rect()`].
+    /// [`Self::global_bound()`] is recomputed first, so that stale
+    /// bounds from removed or moved rects do not waste Morton code
+    /// precision.
+    ///
     /// ### Arguments
     ///
     /// - `point_from_rect`: A closure that determines the stable
     ///   representative point (e.g., center or top-left) of a `Rect`
-    ///   used for Morton encoding.
+    ///   used for Morton encoding, see
+    ///   [`Self::set_morton_precision()`].
     ///
     /// After construction, all internal node bounding boxes are computed.
+    /// A single rect is held by a root node with one child, so that
+    /// queries always go through the hierarchy.
     ///
-    /// If [`Self::global_bound()`] has zero area, the tree is left
-    /// empty since no meaningful spatial ordering can be derived.
+    /// If [`Self::global_bound()`] has zero width or height, e.g.
+    /// for collinear rects, the rects are only ordered along the
+    /// other axis. If it is a single point, no spatial ordering can
+    /// be derived, and the rects are split evenly in index order.
+    ///
+    /// Use [`Self::build_counting_out_of_bounds()`] to detect
+    /// representative points outside of [`Self::global_bound()`].
     pub fn build<F>(&mut self, point_from_rect: F)
     where
         F: Fn(&Rect) -> Point,
     {
-        let bound_size = self.global_bound.size();
-        // There is point in building a spatial tree when there is no
-        // space within the max bound.
-        if bound_size.is_zero_area() {
-            return;
+        self.build_counting_out_of_bounds(point_from_rect);
+    }
+
+    /// Same as [`Self::build()`], but returns the number of
+    /// representative points that fell outside of
+    /// [`Self::global_bound()`]. These points are clamped onto its
+    /// edges, mapping them to the same Morton codes, which degrades
+    /// the balance of the hierarchy.
+    pub fn build_counting_out_of_bounds<F>(
+        &mut self,
+        point_from_rect: F,
+    ) -> usize
+    where
+        F: Fn(&Rect) -> Point,
+    {
+        self.recompute_global_bound();
+        // Rebuild in place to reuse the allocation.
+        let mut nodes = core::mem::take(&mut self.nodes);
+        let out_of_bounds = self.generate_nodes(
+            point_from_rect,
+            &mut nodes,
+            self.max_leaf_rects(),
+        );
+
+        self.nodes = nodes;
+        self.index_leaf_parents();
+        self.incrementally_modified = false;
+
+        out_of_bounds
+    }
+
+    /// Constructs an additional spatial hierarchy over the same set
+    /// of rectangles, using a different representative point.
+    ///
+    /// The rectangles and [`Self::global_bound()`] are shared with
+    /// the main hierarchy built by [`Self::build()`], only the
+    /// hierarchy differs. Query it via the `query_index*` methods,
+    /// e.g. [`Self::query_index()`].
+    ///
+    /// Indices are never updated incrementally, they must be
+    /// rebuilt via [`Self::rebuild_index()`] after rects are pushed
+    /// or replaced. The id of an index removed via
+    /// [`Self::remove_index()`] may be reused.
+    pub fn build_index<F>(&mut self, point_from_rect: F) -> IndexId
+    where
+        F: Fn(&Rect) -> Point,
+    {
+        let mut nodes = Vec::new();
+        self.generate_nodes(point_from_rect, &mut nodes, 1);
+
+        match self.indices.iter().position(Option::is_none) {
+            Some(slot) => {
+                self.indices[slot] = Some(nodes);
+                IndexId(slot)
+            }
+            None => {
+                self.indices.push(Some(nodes));
+                IndexId(self.indices.len() - 1)
+            }
         }
+    }
 
-        let mut morton_codes = self
-            .rects
-            .iter()
-            .enumerate()
-            .map(|(index, rect)| {
-                let point = point_from_rect(rect);
-                let x = point.x / bound_size.width;
-                let y = point.y / bound_size.height;
+    /// Rebuilds an index built via [`Self::build_index()`] in place,
+    /// using a possibly different representative point.
+    ///
+    /// Returns `false` if the [`IndexId`] does not exist.
+    pub fn rebuild_index<F>(
+        &mut self,
+        index: IndexId,
+        point_from_rect: F,
+    ) -> bool
+    where
+        F: Fn(&Rect) -> Point,
+    {
+        let Some(slot) = self.indices.get_mut(index.0) else {
+            return false;
+        };
+        // Rebuild in place to reuse the allocation.
+        let Some(mut nodes) = slot.take() else {
+            return false;
+        };
+        self.generate_nodes(point_from_rect, &mut nodes, 1);
 
-                let code = morton_2d_f64(x, y);
-                MortonCode { code, index }
-            })
-            .collect::<Box<_>>();
+        self.indices[index.0] = Some(nodes);
+        true
+    }
+
+    /// Removes an index built via [`Self::build_index()`], after
+    /// which queries on it hit nothing.
+    ///
+    /// Returns `false` if the [`IndexId`] does not exist.
+    pub fn remove_index(&mut self, index: IndexId) -> bool {
+        self.indices
+            .get_mut(index.0)
+            .and_then(Option::take)
+            .is_some()
+    }
+
+    /// Recomputes the bounds of all internal nodes from the current
+    /// rects, keeping the existing hierarchies, including the ones
+    /// built via [`Self::build_index()`].
+    ///
+    /// This is much cheaper than [`Self::build()`] when rects only
+    /// moved slightly, e.g. during animations. As rects drift away
+    /// from their original positions, sibling bounds grow and
+    /// overlap, making queries slower. A full rebuild is preferable
+    /// once the returned [`Self::area_ratio()`] grows well beyond
+    /// the one right after the last build.
+    pub fn refit(&mut self) -> f64 {
+        Self::calculate_internal_bounds(
+            &mut self.nodes,
+            &self.rects,
+            &self.bucket_rects,
+        );
+        for nodes in self.indices.iter_mut().flatten() {
+            Self::calculate_internal_bounds(nodes, &self.rects, &[]);
+        }
+
+        self.area_ratio()
+    }
+
+    /// Generates the internal nodes into `nodes`, returning the
+    /// number of representative points that fell outside of
+    /// [`Self::global_bound()`].
+    ///
+    /// Buckets are only generated for `max_leaf_rects` above `1`,
+    /// in which case [`Self::bucket_rects`] is overwritten.
+    fn generate_nodes<F>(
+        &mut self,
+        point_from_rect: F,
+        nodes: &mut Vec<Node>,
+        max_leaf_rects: usize,
+    ) -> usize
+    where
+        F: Fn(&Rect) -> Point,
+    {
+        match self.morton_precision {
+            MortonPrecision::Bits32 => {
+                let mut morton_codes =
+                    core::mem::take(&mut self.morton_codes);
+                let out_of_bounds = self.generate_nodes_with(
+                    point_from_rect,
+                    &mut morton_codes,
+                    nodes,
+                    max_leaf_rects,
+                );
+                self.morton_codes = morton_codes;
+                out_of_bounds
+            }
+            MortonPrecision::Bits64 => {
+                let mut morton_codes =
+                    core::mem::take(&mut self.morton_codes_64);
+                let out_of_bounds = self.generate_nodes_with(
+                    point_from_rect,
+                    &mut morton_codes,
+                    nodes,
+                    max_leaf_rects,
+                );
+                self.morton_codes_64 = morton_codes;
+                out_of_bounds
+            }
+        }
+    }
+
+    /// Same as [`Self::generate_nodes()`], using `morton_codes` as
+    /// the scratch buffer of the given precision.
+    fn generate_nodes_with<C, F>(
+        &mut self,
+        point_from_rect: F,
+        morton_codes: &mut Vec<MortonCode<C>>,
+        nodes: &mut Vec<Node>,
+        max_leaf_rects: usize,
+    ) -> usize
+    where
+        C: MortonBits,
+        F: Fn(&Rect) -> Point,
+    {
+        let bound = *self.global_bound();
+        let bound_size = bound.size();
+        // Normalize relative to the min corner of the bound, which is
+        // not necessarily the origin. An axis without extent cannot
+        // order the rects, so only the other axis is quantized. If
+        // both are degenerate, all rects share the same code and are
+        // split evenly in index order.
+        let normalize = |value: f64, min: f64, extent: f64| {
+            if extent > 0.0 {
+                (value - min) / extent
+            } else {
+                0.0
+            }
+        };
+
+        let mut out_of_bounds = 0;
+        morton_codes.clear();
+        morton_codes.extend(
+            self.rects
+                .iter()
+                .enumerate()
+                // Non-finite rects would poison the internal bounds.
+                .filter(|(index, rect)| {
+                    rect.is_finite() && !self.removed[*index]
+                })
+                .map(|(index, rect)| {
+                    let point = point_from_rect(rect);
+                    if !(bound.x0..=bound.x1).contains(&point.x)
+                        || !(bound.y0..=bound.y1).contains(&point.y)
+                    {
+                        out_of_bounds += 1;
+                    }
+
+                    let x = normalize(
+                        point.x,
+                        bound.x0,
+                        bound_size.width,
+                    );
+                    let y = normalize(
+                        point.y,
+                        bound.y0,
+                        bound_size.height,
+                    );
+                    let code = C::encode(x, y);
+                    MortonCode { code, index }
+                }),
+        );
 
         morton_codes.sort_unstable();
+        let bucket_rects = match max_leaf_rects {
+            1 => &[][..],
+            _ => {
+                self.bucket_rects.clear();
+                self.bucket_rects.extend(
+                    morton_codes.iter().map(|code| code.index),
+                );
+                &self.bucket_rects
+            }
+        };
 
         // Build internal nodes.
-        self.nodes = generate_hierarchy(&morton_codes);
-        self.calculate_internal_bounds();
+        match morton_codes[..] {
+            // A binary tree with a single leaf has no internal
+            // nodes, use a root with one child instead.
+            [MortonCode { index, .. }] => {
+                nodes.clear();
+                nodes.push(Node {
+                    children: [NodeId::Leaf(index), NodeId::Invalid],
+                    ..Node::EMPTY
+                });
+            }
+            // All rects fit into a single bucket.
+            _ if (2..=max_leaf_rects)
+                .contains(&morton_codes.len()) =>
+            {
+                nodes.clear();
+                nodes.push(Node {
+                    children: [
+                        NodeId::Bucket {
+                            start: 0,
+                            len: morton_codes.len(),
+                        },
+                        NodeId::Invalid,
+                    ],
+                    ..Node::EMPTY
+                });
+            }
+            _ => generate_hierarchy_into(
+                morton_codes,
+                nodes,
+                &mut self.build_stack,
+                max_leaf_rects,
+            ),
+        }
+        Self::calculate_internal_bounds(
+            nodes,
+            &self.rects,
+            bucket_rects,
+        );
+
+        out_of_bounds
+    }
+
+    /// Build the reverse index from [`RectId`] to the internal node
+    /// that holds it as a leaf.
+    fn index_leaf_parents(&mut self) {
+        self.leaf_parents.clear();
+        self.leaf_parents.resize(self.rects.len(), None);
+
+        for (node_idx, node) in self.nodes.iter().enumerate() {
+            for child in node.children.iter() {
+                for rect_idx in
+                    child_leaves(&self.bucket_rects, child)
+                {
+                    self.leaf_parents[*rect_idx] = Some(node_idx);
+                }
+            }
+        }
     }
 
     /// Calculate the bounds of all the internal nodes.
-    fn calculate_internal_bounds(&mut self) {
-        if self.nodes.is_empty() {
+    fn calculate_internal_bounds(
+        nodes: &mut [Node],
+        rects: &[Rect],
+        bucket_rects: &[usize],
+    ) {
+        if nodes.is_empty() {
             return;
         }
 
         // Because internal nodes were allocated top-down, children
         // always have a higher index than their parents. By iterating
         // backwards, we process the tree bottom-up.
-        for i in (0..self.nodes.len()).rev() {
-            let mut combined_rect = None;
-
-            // Check both children to compute the unioned bounding box
-            for child_id in self.nodes[i].children {
-                let child_rect = match child_id {
-                    NodeId::Leaf(rect_id) => {
-                        // Leaf bounds are already known from the input rects
-                        self.rects[rect_id]
-                    }
-                    NodeId::Internal(idx) => {
-                        // Because idx > i, this child's rect was
-                        // already calculated in a previous iteration of this loop.
-                        self.nodes[idx].rect
-                    }
-                    NodeId::Invalid => Rect::ZERO,
-                };
-
-                // Union the child's rect into the parent's rect
-                combined_rect = Some(match combined_rect {
-                    None => child_rect,
-                    Some(existing) => child_rect.union(existing),
-                });
+        for i in (0..nodes.len()).rev() {
+            // Because children have a higher index, their rects
+            // were already calculated in a previous iteration.
+            if let Some(final_rect) =
+                Self::union_children(nodes, rects, bucket_rects, i)
+            {
+                nodes[i].rect = final_rect;
             }
+        }
+    }
 
-            if let Some(final_rect) = combined_rect {
-                self.nodes[i].rect = final_rect;
+    /// Refits the bounds of the given internal node and its
+    /// ancestors, until a bound stops changing.
+    fn refit_ancestors(&mut self, mut node_idx: Option<usize>) {
+        while let Some(idx) = node_idx {
+            let Some(bound) = Self::union_children(
+                &self.nodes,
+                &self.rects,
+                &self.bucket_rects,
+                idx,
+            ) else {
+                break;
+            };
+            if bound == self.nodes[idx].rect {
+                break;
             }
+            self.nodes[idx].rect = bound;
+            node_idx = self.nodes[idx].parent;
+        }
+    }
+
+    /// Union of the current bounds of an internal node's children.
+    ///
+    /// Returns `None` if the node has no valid children.
+    fn union_children(
+        nodes: &[Node],
+        rects: &[Rect],
+        bucket_rects: &[usize],
+        node_idx: usize,
+    ) -> Option<Rect> {
+        let mut combined_rect = None;
+
+        // Check both children to compute the unioned bounding box
+        for child_id in nodes[node_idx].children {
+            let Some(child_rect) =
+                child_bound(nodes, rects, bucket_rects, child_id)
+            else {
+                continue;
+            };
+
+            // Union the child's rect into the parent's rect
+            combined_rect = Some(match combined_rect {
+                None => child_rect,
+                Some(existing) => child_rect.union(existing),
+            });
+        }
+
+        combined_rect
+    }
+}
+
+/// Hierarchy traversal.
+impl Spatree {
+    /// Get the index of the internal [`Node`] that holds the given
+    /// [`RectId`] as a leaf.
+    ///
+    /// Returns `None` if the rect is not part of the hierarchy, which
+    /// is the case for rects pushed after [`Self::build()`].
+    pub fn leaf_node_of(&self, id: RectId) -> Option<usize> {
+        self.leaf_parents.get(*id).copied().flatten()
+    }
+
+    /// Get the index of the parent of an internal [`Node`].
+    ///
+    /// Returns `None` for the root node or an invalid index.
+    pub fn parent_of(&self, node_idx: usize) -> Option<usize> {
+        self.nodes.get(node_idx).and_then(|node| node.parent)
+    }
+
+    /// Sum of the areas of all internal [`Node`]s relative to the
+    /// area of the root, as a measure of the quality of the main
+    /// hierarchy.
+    ///
+    /// Lower is better: it is proportional to the expected number
+    /// of nodes visited by a query. Returns `0.0` for an empty
+    /// hierarchy or a root with zero area.
+    pub fn area_ratio(&self) -> f64 {
+        let Some(root) = self.nodes.first() else {
+            return 0.0;
+        };
+        let root_area = root.rect.area();
+        if root_area == 0.0 {
+            return 0.0;
         }
+
+        let area = self
+            .nodes
+            .iter()
+            .map(|node| node.rect.area())
+            .sum::<f64>();
+        area / root_area
+    }
+
+    /// Get an internal [`Node`] by its index.
+    pub fn get_node(&self, node_idx: usize) -> Option<&Node> {
+        self.nodes.get(node_idx)
+    }
+
+    /// All internal [`Node`]s of the main hierarchy, e.g. to draw
+    /// their bounds in a debug overlay.
+    ///
+    /// The root is at index `0`, and children always come after
+    /// their parents.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Rects held by a child of a [`Node`], i.e. the rect of a
+    /// [`NodeId::Leaf`] or every rect of a [`NodeId::Bucket`].
+    ///
+    /// Yields nothing for other children.
+    pub fn child_rects(
+        &self,
+        child: NodeId,
+    ) -> impl Iterator<Item = RectId> + '_ {
+        let leaves = match child {
+            NodeId::Bucket { start, len } => {
+                &self.bucket_rects[start..start + len]
+            }
+            _ => &[][..],
+        };
+        let leaf = match child {
+            NodeId::Leaf(rect_idx) => Some(rect_idx),
+            _ => None,
+        };
+
+        leaf.into_iter().chain(leaves.iter().copied()).map(RectId)
+    }
+
+    /// All pushed rects, indexed by [`RectId`].
+    ///
+    /// This includes removed and non-finite rects, which are not
+    /// part of the hierarchy, see [`Self::is_removed()`].
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects
     }
 }
 
@@ -155,47 +887,68 @@ impl Spatree {
         F: Fn(&Rect, &T) -> bool,
     {
         let mut hits = Vec::new();
+        self.query_into(
+            target,
+            hit_condition,
+            &mut hits,
+            &mut QueryScratch::new(),
+        );
+        hits
+    }
 
-        if self.nodes.is_empty() {
-            // There's no tree, if there's just one rect, do a hit
-            // test for it.
-            if let Some(rect) = self.rects.first()
-                && hit_condition(rect, &target)
-            {
-                hits.push(RectId(0));
-            }
-        } else {
-            // Traverse the tree.
-            let mut stack = vec![0];
-
-            while let Some(node_idx) = stack.pop() {
-                let node = self.nodes[node_idx];
-
-                // Skip the tree if it's not a hit.
-                if !hit_condition(&node.rect, &target) {
-                    continue;
-                }
-
-                for child in node.children.iter() {
-                    match child {
-                        NodeId::Internal(child_idx) => {
-                            stack.push(*child_idx)
-                        }
-                        NodeId::Leaf(leaf_idx) => {
-                            if hit_condition(
-                                &self.rects[*leaf_idx],
-                                &target,
-                            ) {
-                                hits.push(RectId(*leaf_idx));
-                            }
-                        }
-                        NodeId::Invalid => continue,
-                    }
-                }
-            }
-        }
+    /// Query for all hits for an arbitrary target, writing the
+    /// results into `out`.
+    ///
+    /// `out` is cleared first, allowing the same buffer to be reused
+    /// across queries without reallocating, along with the
+    /// traversal stack of `scratch`, see [`Self::query_with()`].
+    pub fn query_into<T, F>(
+        &self,
+        target: T,
+        hit_condition: F,
+        out: &mut Vec<RectId>,
+        scratch: &mut QueryScratch,
+    ) where
+        F: Fn(&Rect, &T) -> bool,
+    {
+        out.clear();
+        self.query_with(
+            target,
+            hit_condition,
+            |id| {
+                out.push(id);
+                ControlFlow::Continue(())
+            },
+            scratch,
+        );
+    }
 
-        hits
+    /// Query for an arbitrary target, invoking `visitor` for every
+    /// hit until it returns [`ControlFlow::Break`].
+    ///
+    /// The traversal stack of `scratch` is reused, so that repeated
+    /// queries, e.g. hit testing on every pointer move, do not
+    /// allocate.
+    ///
+    /// Returns `true` if the traversal was aborted by `visitor`.
+    pub fn query_with<T, H, V>(
+        &self,
+        target: T,
+        hit_condition: H,
+        mut visitor: V,
+        scratch: &mut QueryScratch,
+    ) -> bool
+    where
+        H: Fn(&Rect, &T) -> bool,
+        V: FnMut(RectId) -> ControlFlow<()>,
+    {
+        self.traverse(
+            &self.nodes,
+            &target,
+            hit_condition,
+            |id, _| visitor(id),
+            &mut scratch.stack,
+        )
     }
 
     /// Query for a singles hit for an arbitrary target.
@@ -210,60 +963,93 @@ impl Spatree {
         C: Fn(RectId, RectId) -> RectId,
     {
         let mut hit = None;
-        // let mut hits = Vec::new();
-
-        if self.nodes.is_empty() {
-            // There's no tree, if there's just one rect, do a hit
-            // test for it.
-            if let Some(rect) = self.rects.first()
-                && hit_condition(rect, &target)
-            {
-                hit = Some(RectId(0));
-            }
-        } else {
-            // Traverse the tree.
-            let mut stack = vec![0];
-
-            while let Some(node_idx) = stack.pop() {
-                let node = self.nodes[node_idx];
-
-                // Skip the tree if it's not a hit.
-                if !hit_condition(&node.rect, &target) {
-                    continue;
-                }
-
-                for child in node.children.iter() {
-                    match child {
-                        NodeId::Internal(child_idx) => {
-                            stack.push(*child_idx)
-                        }
-                        NodeId::Leaf(leaf_idx) => {
-                            if hit_condition(
-                                &self.rects[*leaf_idx],
-                                &target,
-                            ) {
-                                let new_hit = RectId(*leaf_idx);
-                                match &mut hit {
-                                    Some(hit) => {
-                                        *hit = conflict_resolution(
-                                            *hit, new_hit,
-                                        );
-                                    }
-                                    None => hit = Some(new_hit),
-                                }
-                            }
-                        }
-                        NodeId::Invalid => continue,
-                    }
-                }
-            }
-        }
+        self.query_with(
+            target,
+            hit_condition,
+            |new_hit| {
+                hit = Some(match hit {
+                    Some(hit) => conflict_resolution(hit, new_hit),
+                    None => new_hit,
+                });
+                ControlFlow::Continue(())
+            },
+            &mut QueryScratch::new(),
+        );
 
         hit
     }
 
-    /// Query for all rects that contains the given [`Point`].
-    pub fn query_point(&self, point: Point) -> Vec<RectId> {
+    /// Query for the hit with the maximum key for an arbitrary
+    /// target.
+    ///
+    /// If multiple hits share the same maximum key, the one with the
+    /// smallest [`RectId`] is returned, regardless of the traversal
+    /// order.
+    pub fn query_by<T, H, K, F>(
+        &self,
+        target: T,
+        hit_condition: H,
+        key: F,
+    ) -> Option<RectId>
+    where
+        H: Fn(&Rect, &T) -> bool,
+        K: Ord,
+        F: Fn(RectId) -> K,
+    {
+        self.query_single(target, hit_condition, |a, b| {
+            if (key(b), Reverse(b)) > (key(a), Reverse(a)) {
+                b
+            } else {
+                a
+            }
+        })
+    }
+
+    /// Query for the `k` hits with the highest priority for an
+    /// arbitrary target, sorted by priority in descending order.
+    ///
+    /// Hits sharing the same priority are ordered by the smallest
+    /// [`RectId`]. Only `k` hits are kept around during the
+    /// traversal, but every hit is still visited.
+    pub fn query_top_k<T, H, K, P>(
+        &self,
+        target: T,
+        hit_condition: H,
+        priority: P,
+        k: usize,
+    ) -> Vec<RectId>
+    where
+        H: Fn(&Rect, &T) -> bool,
+        K: Ord,
+        P: Fn(RectId) -> K,
+    {
+        if k == 0 {
+            return Vec::new();
+        }
+
+        // Best hits so far, with the worst of them on top.
+        let mut best = BinaryHeap::with_capacity(k + 1);
+        self.query_with(
+            target,
+            hit_condition,
+            |id| {
+                best.push(Reverse((priority(id), Reverse(id))));
+                if best.len() > k {
+                    best.pop();
+                }
+                ControlFlow::Continue(())
+            },
+            &mut QueryScratch::new(),
+        );
+
+        best.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((_, Reverse(id)))| id)
+            .collect()
+    }
+
+    /// Query for all rects that contains the given [`Point`].
+    pub fn query_point(&self, point: Point) -> Vec<RectId> {
         self.query(
             point,
             #[inline(always)]
@@ -280,6 +1066,132 @@ impl Spatree {
         )
     }
 
+    /// Query for all rects that lie fully inside the given [`Rect`],
+    /// e.g. for rubber-band selection.
+    ///
+    /// Edges are inclusive, so rects touching the boundary from the
+    /// inside, or equal to the given rect, are hits.
+    pub fn query_rect_contained(&self, rect: Rect) -> Vec<RectId> {
+        // Internal nodes can only be pruned by overlap, as their
+        // bounds could exceed the query while their leaves do not.
+        let mut hits = Vec::new();
+        self.query_with(
+            rect,
+            |node_rect, rect| node_rect.overlaps(*rect),
+            |id| {
+                if rect.contains_rect(self.rects[id.0]) {
+                    hits.push(id);
+                }
+                ControlFlow::Continue(())
+            },
+            &mut QueryScratch::new(),
+        );
+        hits
+    }
+
+    /// Query for all rects that fully contain the given [`Rect`],
+    /// e.g. to find drop targets.
+    ///
+    /// Edges are inclusive, so rects sharing a boundary with the
+    /// given rect, or equal to it, are hits.
+    pub fn query_rect_containing(&self, rect: Rect) -> Vec<RectId> {
+        // Internal bounds contain their leaves, which could only
+        // contain the query if the bound does.
+        self.query(
+            rect,
+            #[inline(always)]
+            |node_rect, rect| node_rect.contains_rect(*rect),
+        )
+    }
+
+    /// Same as [`Self::query_point()`], but yields each rect
+    /// alongside its [`RectId`].
+    pub fn query_point_with_rects(
+        &self,
+        point: Point,
+    ) -> Vec<(RectId, Rect)> {
+        self.query_with_rects(
+            point,
+            #[inline(always)]
+            |rect, point| rect.contains(*point),
+        )
+    }
+
+    /// Same as [`Self::query_rect()`], but yields each rect
+    /// alongside its [`RectId`].
+    pub fn query_rect_with_rects(
+        &self,
+        rect: Rect,
+    ) -> Vec<(RectId, Rect)> {
+        self.query_with_rects(
+            rect,
+            #[inline(always)]
+            |rect, target_rect| rect.overlaps(*target_rect),
+        )
+    }
+
+    /// Same as [`Self::query()`], but yields each rect alongside its
+    /// [`RectId`].
+    fn query_with_rects<T, F>(
+        &self,
+        target: T,
+        hit_condition: F,
+    ) -> Vec<(RectId, Rect)>
+    where
+        F: Fn(&Rect, &T) -> bool,
+    {
+        let mut hits = Vec::new();
+        self.traverse(
+            &self.nodes,
+            &target,
+            hit_condition,
+            |id, rect| {
+                hits.push((id, *rect));
+                ControlFlow::Continue(())
+            },
+            &mut Vec::new(),
+        );
+        hits
+    }
+
+    /// Query for all rects that contains the given [`Point`],
+    /// writing the results into `out`.
+    ///
+    /// See [`Self::query_into()`].
+    pub fn query_point_into(
+        &self,
+        point: Point,
+        out: &mut Vec<RectId>,
+        scratch: &mut QueryScratch,
+    ) {
+        self.query_into(
+            point,
+            #[inline(always)]
+            |rect, point| rect.contains(*point),
+            out,
+            scratch,
+        )
+    }
+
+    /// Query for all rects that overlaps the given [`Rect`],
+    /// writing the results into `out`.
+    ///
+    /// See [`Self::query_into()`].
+    pub fn query_rect_into(
+        &self,
+        rect: Rect,
+        out: &mut Vec<RectId>,
+        scratch: &mut QueryScratch,
+    ) {
+        self.query_into(
+            rect,
+            #[inline(always)]
+            |rect, target_rect| rect.overlaps(*target_rect),
+            out,
+            scratch,
+        )
+    }
+
     /// Query for a single rects that contains the given [`Point`].
     pub fn query_point_single<C>(
         &self,
@@ -297,6 +1209,48 @@ impl Spatree {
         )
     }
 
+    /// Query for the rect with the maximum key that contains the
+    /// given [`Point`].
+    ///
+    /// See [`Self::query_by()`].
+    pub fn query_point_by<K, F>(
+        &self,
+        point: Point,
+        key: F,
+    ) -> Option<RectId>
+    where
+        K: Ord,
+        F: Fn(RectId) -> K,
+    {
+        self.query_by(
+            point,
+            #[inline(always)]
+            |rect, point| rect.contains(*point),
+            key,
+        )
+    }
+
+    /// Query for the rect with the maximum key that overlaps the
+    /// given [`Rect`].
+    ///
+    /// See [`Self::query_by()`].
+    pub fn query_rect_by<K, F>(
+        &self,
+        rect: Rect,
+        key: F,
+    ) -> Option<RectId>
+    where
+        K: Ord,
+        F: Fn(RectId) -> K,
+    {
+        self.query_by(
+            rect,
+            #[inline(always)]
+            |rect, target_rect| rect.overlaps(*target_rect),
+            key,
+        )
+    }
+
     /// Query for a single rects that contains the given [`Point`].
     pub fn query_rect_single<C>(
         &self,
@@ -313,344 +1267,3452 @@ impl Spatree {
             conflict_resolution,
         )
     }
-}
 
-/// An internal node within the [`Spatree`].
-#[derive(Debug, Clone, Copy)]
-pub struct Node {
-    pub rect: Rect,
-    pub parent: Option<usize>,
-    pub children: [NodeId; 2],
-}
+    /// Same as [`Self::query_point()`], but with a point in view
+    /// space, which is mapped into the space of the rects via
+    /// `inv_view`, the inverse of the view transform.
+    pub fn query_point_transformed(
+        &self,
+        point: Point,
+        inv_view: Affine,
+    ) -> Vec<RectId> {
+        self.query_point(inv_view * point)
+    }
 
-impl Node {
-    /// Empty node with zero area, no children, and no parent.
-    pub const EMPTY: Self = Self {
-        rect: Rect::ZERO,
-        parent: None,
-        children: [NodeId::Invalid; 2],
-    };
-}
+    /// Same as [`Self::query_rect()`], but with a rect in view
+    /// space, which is mapped into the space of the rects via
+    /// `inv_view`, the inverse of the view transform.
+    ///
+    /// Under rotation or skew, the mapped rect is a parallelogram,
+    /// which is tested exactly rather than by its bounding box.
+    pub fn query_rect_transformed(
+        &self,
+        rect: Rect,
+        inv_view: Affine,
+    ) -> Vec<RectId> {
+        let quad = [
+            Point::new(rect.x0, rect.y0),
+            Point::new(rect.x1, rect.y0),
+            Point::new(rect.x1, rect.y1),
+            Point::new(rect.x0, rect.y1),
+        ]
+        .map(|corner| inv_view * corner);
 
-#[derive(
-    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
-)]
-pub enum NodeId {
-    Internal(usize),
-    Leaf(usize),
-    Invalid,
-}
+        self.query(
+            (inv_view.transform_rect_bbox(rect), quad),
+            |rect, (bbox, quad)| {
+                rect.overlaps(*bbox) && overlaps_quad(rect, quad)
+            },
+        )
+    }
 
-#[derive(
-    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
-)]
-pub struct RectId(usize);
+    /// Lazily query for all hits for an arbitrary target.
+    ///
+    /// Unlike [`Self::query()`], the hierarchy is only traversed as
+    /// far as the returned iterator is advanced.
+    pub fn iter_query<'a, T, F>(
+        &'a self,
+        target: T,
+        hit_condition: F,
+    ) -> QueryIter<'a, T, F>
+    where
+        F: Fn(&Rect, &T) -> bool,
+    {
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(NodeId::Internal(0));
+        }
 
-impl RectId {
-    pub fn into_inner(self) -> usize {
-        self.0
+        QueryIter {
+            tree: self,
+            target,
+            hit_condition,
+            stack,
+        }
     }
-}
 
-impl Deref for RectId {
-    type Target = usize;
+    /// Lazily query for all rects that contains the given
+    /// [`Point`].
+    ///
+    /// See [`Self::iter_query()`].
+    pub fn iter_query_point(
+        &self,
+        point: Point,
+    ) -> QueryIter<'_, Point, fn(&Rect, &Point) -> bool> {
+        self.iter_query(point, |rect, point| rect.contains(*point))
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+    /// Lazily query for all rects that overlaps the given [`Rect`].
+    ///
+    /// See [`Self::iter_query()`].
+    pub fn iter_query_rect(
+        &self,
+        rect: Rect,
+    ) -> QueryIter<'_, Rect, fn(&Rect, &Rect) -> bool> {
+        self.iter_query(rect, |rect, target_rect| {
+            rect.overlaps(*target_rect)
+        })
     }
-}
 
-/// Top down hierarchy building for single threaded algorithm.
-pub fn generate_hierarchy(codes: &[MortonCode]) -> Vec<Node> {
-    let len = codes.len();
-    if len <= 1 {
-        return Vec::new();
+    /// Query for the `k` rects closest to the given [`Point`], along
+    /// with their distances, where rects containing the point are at
+    /// a distance of `0.0`.
+    ///
+    /// Results are sorted by ascending distance, with ties broken by
+    /// [`RectId`]. Fewer than `k` results are returned if the tree
+    /// does not hold enough rects.
+    pub fn query_knn(
+        &self,
+        point: Point,
+        k: usize,
+    ) -> Vec<(RectId, f64)> {
+        if k == 0 || self.nodes.is_empty() {
+            return Vec::new();
+        }
+
+        // Best hits so far, with the worst of them on top.
+        let mut best = BinaryHeap::with_capacity(k + 1);
+        // Pending nodes, with the closest of them on top.
+        let mut pending = BinaryHeap::new();
+        pending.push(Reverse(ByDistance {
+            distance: distance_to_point(&self.nodes[0].rect, point),
+            item: 0,
+        }));
+
+        while let Some(Reverse(node)) = pending.pop() {
+            // Every remaining node is at least as far away. Equally
+            // distant nodes could still hold smaller ids.
+            if best.len() == k
+                && best.peek().is_some_and(
+                    |worst: &ByDistance<RectId>| {
+                        node.distance > worst.distance
+                    },
+                )
+            {
+                break;
+            }
+
+            for child in self.nodes[node.item].children.iter() {
+                match *child {
+                    NodeId::Internal(child_idx) => {
+                        pending.push(Reverse(ByDistance {
+                            distance: distance_to_point(
+                                &self.nodes[child_idx].rect,
+                                point,
+                            ),
+                            item: child_idx,
+                        }));
+                    }
+                    NodeId::Leaf(_) | NodeId::Bucket { .. } => {
+                        for &leaf_idx in
+                            child_leaves(&self.bucket_rects, child)
+                        {
+                            if self.removed[leaf_idx] {
+                                continue;
+                            }
+
+                            best.push(ByDistance {
+                                distance: distance_to_point(
+                                    &self.rects[leaf_idx],
+                                    point,
+                                ),
+                                item: RectId(leaf_idx),
+                            });
+                            if best.len() > k {
+                                best.pop();
+                            }
+                        }
+                    }
+                    NodeId::Invalid => continue,
+                }
+            }
+        }
+
+        best.into_sorted_vec()
+            .into_iter()
+            .map(|hit| (hit.item, hit.distance))
+            .collect()
     }
 
-    // A binary tree with N leaves has exactly N - 1 internal nodes.
-    let mut internal_nodes = vec![Node::EMPTY; len - 1];
+    /// Query for the rect closest to the given [`Point`], along
+    /// with its distance, where rects containing the point are at a
+    /// distance of `0.0`.
+    ///
+    /// Ties are broken by the smallest [`RectId`]. Same as the first
+    /// result of [`Self::query_knn()`], without maintaining a heap.
+    pub fn query_nearest(
+        &self,
+        point: Point,
+    ) -> Option<(RectId, f64)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut best = None::<ByDistance<RectId>>;
+        let mut stack = Vec::new();
+        stack.push(ByDistance {
+            distance: distance_to_point(&self.nodes[0].rect, point),
+            item: 0,
+        });
+
+        while let Some(node) = stack.pop() {
+            // Equally distant nodes could still hold smaller ids.
+            if best.is_some_and(|best| node.distance > best.distance)
+            {
+                continue;
+            }
+
+            let mut internals = [None; 2];
+            for (internal, child) in internals
+                .iter_mut()
+                .zip(self.nodes[node.item].children)
+            {
+                match child {
+                    NodeId::Internal(child_idx) => {
+                        *internal = Some(ByDistance {
+                            distance: distance_to_point(
+                                &self.nodes[child_idx].rect,
+                                point,
+                            ),
+                            item: child_idx,
+                        });
+                    }
+                    NodeId::Leaf(_) | NodeId::Bucket { .. } => {
+                        for &leaf_idx in
+                            child_leaves(&self.bucket_rects, &child)
+                        {
+                            if self.removed[leaf_idx] {
+                                continue;
+                            }
+
+                            let hit = ByDistance {
+                                distance: distance_to_point(
+                                    &self.rects[leaf_idx],
+                                    point,
+                                ),
+                                item: RectId(leaf_idx),
+                            };
+                            if best.is_none_or(|best| hit < best) {
+                                best = Some(hit);
+                            }
+                        }
+                    }
+                    NodeId::Invalid => continue,
+                }
+            }
+
+            // Push the closer child last, so that it is descended
+            // into first.
+            if let [Some(a), Some(b)] = internals
+                && b.distance > a.distance
+            {
+                internals.swap(0, 1);
+            }
+            stack.extend(internals.into_iter().flatten());
+        }
 
-    /// Represents a range to be split and its connection to the tree.
-    struct BuildStack {
-        first: usize,
-        last: usize,
-        parent_idx: Option<usize>,
-        /// `0` for left, `1` for right.
-        child_slot: usize,
+        best.map(|hit| (hit.item, hit.distance))
     }
 
-    let mut stack = Vec::with_capacity(len);
-    let mut node_idx = 0;
+    /// Query for all rects crossed by the line segment from `p0` to
+    /// `p1`.
+    ///
+    /// Rects are treated as closed, so segments touching an edge, or
+    /// lying exactly along one, hit the rect. A degenerate segment,
+    /// where `p0 == p1`, is the same as [`Self::query_point()`].
+    pub fn query_segment(&self, p0: Point, p1: Point) -> Vec<RectId> {
+        if p0 == p1 {
+            return self.query_point(p0);
+        }
 
-    // First build stakc will have the full range.
-    stack.push(BuildStack {
-        first: 0,
-        last: internal_nodes.len(),
-        parent_idx: None,
-        child_slot: 0,
-    });
+        self.query(
+            (p0, p1 - p0),
+            #[inline(always)]
+            |rect, (p0, dir)| {
+                segment_entry(rect, *p0, *dir).is_some()
+            },
+        )
+    }
 
-    while let Some(task) = stack.pop() {
-        let BuildStack {
-            first,
-            last,
-            parent_idx,
-            child_slot,
-        } = task;
+    /// Query for the rect crossed first by the line segment from
+    /// `p0` to `p1`, along with the distance from `p0` to where the
+    /// segment enters it, `0.0` if `p0` lies within.
+    ///
+    /// Ties are broken by the smallest [`RectId`]. See
+    /// [`Self::query_segment()`] for the inclusivity of edges.
+    pub fn query_segment_first(
+        &self,
+        p0: Point,
+        p1: Point,
+    ) -> Option<(RectId, f64)> {
+        let dir = p1 - p0;
+        // Fraction of the segment at which a rect is entered.
+        let entry = |rect: &Rect| {
+            if p0 == p1 {
+                rect.contains(p0).then_some(0.0)
+            } else {
+                segment_entry(rect, p0, dir)
+            }
+        };
 
-        let curr_node_id = if first == last {
-            // Single element range represents a leaf node.
-            NodeId::Leaf(codes[first].index)
-        } else {
-            // Internal node case.
-            let node_id = NodeId::Internal(node_idx);
-            let split = find_split(codes, first, last);
+        let root = self.nodes.first()?;
+        let mut best = None::<ByDistance<RectId>>;
+        let mut stack = Vec::new();
+        stack.extend(entry(&root.rect).map(|t| ByDistance {
+            distance: t,
+            item: 0,
+        }));
 
-            // Push right sub-range then left sub-range (LIFO).
-            stack.push(BuildStack {
-                first,
-                last: split,
-                parent_idx: Some(node_idx),
-                child_slot: 0,
-            });
-            stack.push(BuildStack {
-                first: split + 1,
+        while let Some(node) = stack.pop() {
+            // Nodes entered at the same point could still hold
+            // smaller ids.
+            if best.is_some_and(|best| node.distance > best.distance)
+            {
+                continue;
+            }
+
+            let mut internals = [None; 2];
+            for (internal, child) in internals
+                .iter_mut()
+                .zip(self.nodes[node.item].children)
+            {
+                match child {
+                    NodeId::Internal(child_idx) => {
+                        *internal = entry(
+                            &self.nodes[child_idx].rect,
+                        )
+                        .map(|t| ByDistance {
+                            distance: t,
+                            item: child_idx,
+                        });
+                    }
+                    NodeId::Leaf(_) | NodeId::Bucket { .. } => {
+                        for &leaf_idx in
+                            child_leaves(&self.bucket_rects, &child)
+                        {
+                            if self.removed[leaf_idx] {
+                                continue;
+                            }
+
+                            let Some(t) =
+                                entry(&self.rects[leaf_idx])
+                            else {
+                                continue;
+                            };
+                            let hit = ByDistance {
+                                distance: t,
+                                item: RectId(leaf_idx),
+                            };
+                            if best.is_none_or(|best| hit < best) {
+                                best = Some(hit);
+                            }
+                        }
+                    }
+                    NodeId::Invalid => continue,
+                }
+            }
+
+            // Push the child entered first last, so that it is
+            // descended into first.
+            if let [Some(a), Some(b)] = internals
+                && b.distance > a.distance
+            {
+                internals.swap(0, 1);
+            }
+            stack.extend(internals.into_iter().flatten());
+        }
+
+        best.map(|hit| (hit.item, hit.distance * dir.hypot()))
+    }
+
+    /// Query for all rects overlapping a convex polygon, e.g. from a
+    /// lasso selection, where touching edges count as overlapping.
+    ///
+    /// The vertices can be in either winding order. Returns an error
+    /// if the polygon has fewer than 3 vertices, is not convex, or
+    /// is degenerate, i.e. has zero area or non-finite vertices.
+    pub fn query_convex(
+        &self,
+        polygon: &[Point],
+    ) -> Result<Vec<RectId>, PolygonError> {
+        validate_convex(polygon)?;
+
+        let bbox = polygon.iter().skip(1).fold(
+            Rect::from_points(polygon[0], polygon[0]),
+            |bbox, p| bbox.union_pt(*p),
+        );
+
+        // Internal nodes are only pruned by the bounding box, the
+        // full test is left to the leaves.
+        let mut hits = Vec::new();
+        self.query_with(
+            bbox,
+            |rect, bbox| rect.overlaps(*bbox),
+            |id| {
+                if overlaps_convex(&self.rects[id.0], polygon) {
+                    hits.push(id);
+                }
+                ControlFlow::Continue(())
+            },
+            &mut QueryScratch::new(),
+        );
+        Ok(hits)
+    }
+
+    /// Query for all rects within `radius` of `center`, e.g. to hit
+    /// test touch input with some tolerance.
+    ///
+    /// A radius that is not positive is the same as
+    /// [`Self::query_point()`].
+    pub fn query_circle(
+        &self,
+        center: Point,
+        radius: f64,
+    ) -> Vec<RectId> {
+        let mut hits = Vec::new();
+        self.query_circle_into(
+            center,
+            radius,
+            &mut hits,
+            &mut QueryScratch::new(),
+        );
+        hits
+    }
+
+    /// Query for all rects within `radius` of `center`, writing the
+    /// results into `out`.
+    ///
+    /// See [`Self::query_circle()`] and [`Self::query_into()`].
+    pub fn query_circle_into(
+        &self,
+        center: Point,
+        radius: f64,
+        out: &mut Vec<RectId>,
+        scratch: &mut QueryScratch,
+    ) {
+        if radius > 0.0 {
+            self.query_into(
+                center,
+                #[inline(always)]
+                |rect, center| {
+                    distance_to_point(rect, *center) <= radius
+                },
+                out,
+                scratch,
+            )
+        } else {
+            self.query_point_into(center, out, scratch)
+        }
+    }
+}
+
+/// Distance from a rect to a point, `0.0` if the rect contains it.
+fn distance_to_point(rect: &Rect, point: Point) -> f64 {
+    let dx = (rect.x0 - point.x).max(point.x - rect.x1).max(0.0);
+    let dy = (rect.y0 - point.y).max(point.y - rect.y1).max(0.0);
+    Vec2::new(dx, dy).hypot()
+}
+
+/// Slab test between a closed rect and the segment from `p0` to
+/// `p0 + dir`, returning the fraction of `dir` at which the segment
+/// enters the rect, `0.0` if `p0` lies within.
+fn segment_entry(rect: &Rect, p0: Point, dir: Vec2) -> Option<f64> {
+    let mut t_min = 0.0_f64;
+    let mut t_max = 1.0_f64;
+
+    for (origin, d, min, max) in [
+        (p0.x, dir.x, rect.x0, rect.x1),
+        (p0.y, dir.y, rect.y0, rect.y1),
+    ] {
+        if d == 0.0 {
+            // Parallel to the slab, which must contain the segment.
+            if origin < min || origin > max {
+                return None;
+            }
+            continue;
+        }
+
+        let t0 = (min - origin) / d;
+        let t1 = (max - origin) / d;
+        t_min = t_min.max(t0.min(t1));
+        t_max = t_max.min(t0.max(t1));
+    }
+
+    (t_min <= t_max).then_some(t_min)
+}
+
+/// An item ordered by its distance first, see
+/// [`Spatree::query_knn()`].
+#[derive(Debug, Clone, Copy)]
+struct ByDistance<T> {
+    distance: f64,
+    item: T,
+}
+
+impl<T: Ord> Ord for ByDistance<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then_with(|| self.item.cmp(&other.item))
+    }
+}
+
+impl<T: Ord> PartialOrd for ByDistance<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Ord> PartialEq for ByDistance<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: Ord> Eq for ByDistance<T> {}
+
+/// Broad phase queries.
+impl Spatree {
+    /// Enumerates every pair of overlapping rects, e.g. for the
+    /// broad phase of collision detection, where touching rects
+    /// count as overlapping, see [`Rect::overlaps()`].
+    ///
+    /// Each unordered pair is yielded once, as `(a, b)` with
+    /// `a < b`.
+    pub fn overlapping_pairs(&self) -> Vec<(RectId, RectId)> {
+        let mut pairs = Vec::new();
+        self.try_visit_overlapping_pairs(|a, b| {
+            pairs.push((a, b));
+            ControlFlow::Continue(())
+        });
+        pairs
+    }
+
+    /// Same as [`Self::overlapping_pairs()`], but invokes `f` for
+    /// every pair until it returns [`ControlFlow::Break`].
+    ///
+    /// Returns `true` if the traversal was aborted by `f`.
+    pub fn try_visit_overlapping_pairs<F>(&self, mut f: F) -> bool
+    where
+        F: FnMut(RectId, RectId) -> ControlFlow<()>,
+    {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let rect_of = |id: NodeId| match id {
+            NodeId::Leaf(idx) if self.removed[idx] => None,
+            _ => child_bound(
+                &self.nodes,
+                &self.rects,
+                &self.bucket_rects,
+                id,
+            ),
+        };
+
+        // Pairs of subtrees whose leaves are yet to be paired, where
+        // a subtree paired with itself pairs up its own leaves.
+        let mut stack = Vec::new();
+        stack.push((NodeId::Internal(0), NodeId::Internal(0)));
+
+        while let Some((a, b)) = stack.pop() {
+            if a == b {
+                match a {
+                    NodeId::Internal(idx) => {
+                        let [c0, c1] = self.nodes[idx].children;
+                        stack.extend([(c0, c0), (c1, c1), (c0, c1)]);
+                    }
+                    NodeId::Bucket { .. } => {
+                        let leaves =
+                            child_leaves(&self.bucket_rects, &a);
+                        for (i, x) in leaves.iter().enumerate() {
+                            for y in leaves[i + 1..].iter() {
+                                stack.push((
+                                    NodeId::Leaf(*x),
+                                    NodeId::Leaf(*y),
+                                ));
+                            }
+                        }
+                    }
+                    NodeId::Leaf(_) | NodeId::Invalid => {}
+                }
+                continue;
+            }
+
+            let (Some(a_rect), Some(b_rect)) =
+                (rect_of(a), rect_of(b))
+            else {
+                continue;
+            };
+            if !a_rect.overlaps(b_rect) {
+                continue;
+            }
+
+            // Descend into the larger node that is not a leaf.
+            let (split, other) = match (a, b) {
+                (NodeId::Leaf(a), NodeId::Leaf(b)) => {
+                    let (a, b) = (RectId(a.min(b)), RectId(a.max(b)));
+                    if f(a, b).is_break() {
+                        return true;
+                    }
+                    continue;
+                }
+                (_, NodeId::Leaf(_)) => (a, b),
+                (NodeId::Leaf(_), _) => (b, a),
+                _ if a_rect.area() >= b_rect.area() => (a, b),
+                _ => (b, a),
+            };
+            match split {
+                NodeId::Internal(idx) => {
+                    for child in self.nodes[idx].children {
+                        stack.push((child, other));
+                    }
+                }
+                NodeId::Bucket { .. } => {
+                    for rect_idx in
+                        child_leaves(&self.bucket_rects, &split)
+                    {
+                        stack.push((NodeId::Leaf(*rect_idx), other));
+                    }
+                }
+                NodeId::Leaf(_) | NodeId::Invalid => unreachable!(),
+            }
+        }
+
+        false
+    }
+}
+
+/// Separating axis test between a rect and a parallelogram, along
+/// the normals of the parallelogram edges.
+///
+/// The axes of the rect are covered by its bounding box.
+fn overlaps_quad(rect: &Rect, quad: &[Point; 4]) -> bool {
+    let corners = rect_corners(rect);
+
+    [quad[1] - quad[0], quad[3] - quad[0]]
+        .into_iter()
+        .all(|edge| overlaps_along(&corners, quad, edge.turn_90()))
+}
+
+/// Separating axis test between a rect and a convex polygon, along
+/// the normals of the polygon edges.
+///
+/// The axes of the rect are covered by the bounding box of the
+/// polygon, which must be tested separately.
+fn overlaps_convex(rect: &Rect, polygon: &[Point]) -> bool {
+    let corners = rect_corners(rect);
+
+    (0..polygon.len()).all(|i| {
+        let edge = polygon[(i + 1) % polygon.len()] - polygon[i];
+        overlaps_along(&corners, polygon, edge.turn_90())
+    })
+}
+
+fn rect_corners(rect: &Rect) -> [Point; 4] {
+    [
+        Point::new(rect.x0, rect.y0),
+        Point::new(rect.x1, rect.y0),
+        Point::new(rect.x1, rect.y1),
+        Point::new(rect.x0, rect.y1),
+    ]
+}
+
+/// Returns `true` if the projections of both point sets onto `axis`
+/// overlap.
+fn overlaps_along(a: &[Point], b: &[Point], axis: Vec2) -> bool {
+    let project = |points: &[Point]| {
+        points.iter().fold(
+            (f64::INFINITY, f64::NEG_INFINITY),
+            |(min, max), point| {
+                let d = point.to_vec2().dot(axis);
+                (min.min(d), max.max(d))
+            },
+        )
+    };
+
+    let (a_min, a_max) = project(a);
+    let (b_min, b_max) = project(b);
+    a_min <= b_max && a_max >= b_min
+}
+
+/// Checks that a polygon is convex and not degenerate, see
+/// [`Spatree::query_convex()`].
+fn validate_convex(polygon: &[Point]) -> Result<(), PolygonError> {
+    if polygon.len() < 3 {
+        return Err(PolygonError::TooFewPoints);
+    }
+    if polygon.iter().any(|p| !p.is_finite()) {
+        return Err(PolygonError::Degenerate);
+    }
+
+    let next = |i: usize| polygon[(i + 1) % polygon.len()];
+    let edge = |i: usize| next(i) - polygon[i];
+    // Twice the signed area.
+    let mut area = 0.0;
+    let mut turn = 0.0_f64;
+    // Number of times the edges reverse their horizontal direction,
+    // which is at most 2 for a polygon winding around once.
+    let mut reversals = 0;
+    let mut prev_dx = (0..polygon.len())
+        .rev()
+        .map(|i| edge(i).x)
+        .find(|dx| *dx != 0.0)
+        .unwrap_or(0.0);
+
+    for i in 0..polygon.len() {
+        let (a, b) = (edge(i), edge((i + 1) % polygon.len()));
+        area += polygon[i].to_vec2().cross(next(i).to_vec2());
+
+        let cross = a.cross(b);
+        if cross * turn < 0.0 {
+            return Err(PolygonError::NotConvex);
+        }
+        if cross != 0.0 {
+            turn = cross;
+        }
+
+        if a.x != 0.0 {
+            if a.x * prev_dx < 0.0 {
+                reversals += 1;
+            }
+            prev_dx = a.x;
+        }
+    }
+
+    if area == 0.0 {
+        return Err(PolygonError::Degenerate);
+    }
+    if reversals > 2 {
+        return Err(PolygonError::NotConvex);
+    }
+
+    Ok(())
+}
+
+/// Queries on additional indices, see [`Spatree::build_index()`].
+impl Spatree {
+    /// Query for all hits for an arbitrary target in the given
+    /// index.
+    ///
+    /// See [`Self::query()`].
+    pub fn query_index<T, F>(
+        &self,
+        index: IndexId,
+        target: T,
+        hit_condition: F,
+    ) -> Vec<RectId>
+    where
+        F: Fn(&Rect, &T) -> bool,
+    {
+        let mut hits = Vec::new();
+        self.query_index_into(
+            index,
+            target,
+            hit_condition,
+            &mut hits,
+            &mut QueryScratch::new(),
+        );
+        hits
+    }
+
+    /// Query for all hits for an arbitrary target in the given
+    /// index, writing the results into `out`.
+    ///
+    /// See [`Self::query_into()`]. Nothing is hit for an invalid
+    /// [`IndexId`].
+    pub fn query_index_into<T, F>(
+        &self,
+        index: IndexId,
+        target: T,
+        hit_condition: F,
+        out: &mut Vec<RectId>,
+        scratch: &mut QueryScratch,
+    ) where
+        F: Fn(&Rect, &T) -> bool,
+    {
+        out.clear();
+        let Some(Some(nodes)) = self.indices.get(index.0) else {
+            return;
+        };
+
+        self.traverse(
+            nodes,
+            &target,
+            hit_condition,
+            |id, _| {
+                out.push(id);
+                ControlFlow::Continue(())
+            },
+            &mut scratch.stack,
+        );
+    }
+
+    /// Query for all rects that contains the given [`Point`] in the
+    /// given index.
+    pub fn query_index_point(
+        &self,
+        index: IndexId,
+        point: Point,
+    ) -> Vec<RectId> {
+        self.query_index(
+            index,
+            point,
+            #[inline(always)]
+            |rect, point| rect.contains(*point),
+        )
+    }
+
+    /// Query for all rects that overlaps the given [`Rect`] in the
+    /// given index.
+    pub fn query_index_rect(
+        &self,
+        index: IndexId,
+        rect: Rect,
+    ) -> Vec<RectId> {
+        self.query_index(
+            index,
+            rect,
+            #[inline(always)]
+            |rect, target_rect| rect.overlaps(*target_rect),
+        )
+    }
+
+    /// Traverse a hierarchy, visiting every leaf that is a hit
+    /// along with its rect.
+    ///
+    /// `stack` is cleared first and used to hold the pending nodes.
+    ///
+    /// Returns `true` if the traversal was aborted by `visit`.
+    fn traverse<T, H, V>(
+        &self,
+        nodes: &[Node],
+        target: &T,
+        hit_condition: H,
+        mut visit: V,
+        stack: &mut Vec<NodeId>,
+    ) -> bool
+    where
+        H: Fn(&Rect, &T) -> bool,
+        V: FnMut(RectId, &Rect) -> ControlFlow<()>,
+    {
+        stack.clear();
+        if !nodes.is_empty() {
+            stack.push(NodeId::Internal(0));
+        }
+
+        while let Some((id, rect)) =
+            self.next_hit(nodes, target, &hit_condition, stack)
+        {
+            if visit(id, rect).is_break() {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Advances a traversal of a hierarchy until the next leaf that
+    /// is a hit, returning it along with its rect, and expanding the
+    /// pending nodes and leaves on `stack`.
+    ///
+    /// Shared by [`Self::traverse()`] and [`QueryIter`].
+    fn next_hit<T, H>(
+        &self,
+        nodes: &[Node],
+        target: &T,
+        hit_condition: &H,
+        stack: &mut Vec<NodeId>,
+    ) -> Option<(RectId, &Rect)>
+    where
+        H: Fn(&Rect, &T) -> bool,
+    {
+        while let Some(id) = stack.pop() {
+            match id {
+                NodeId::Internal(node_idx) => {
+                    let node = &nodes[node_idx];
+
+                    // Skip the tree if it's not a hit.
+                    if !hit_condition(&node.rect, target) {
+                        continue;
+                    }
+                    // Reversed to visit the children in order.
+                    stack.extend(node.children.iter().rev());
+                }
+                NodeId::Leaf(leaf_idx) => {
+                    let rect = &self.rects[leaf_idx];
+                    if !self.removed[leaf_idx]
+                        && hit_condition(rect, target)
+                    {
+                        return Some((RectId(leaf_idx), rect));
+                    }
+                }
+                NodeId::Bucket { .. } => {
+                    stack.extend(
+                        child_leaves(&self.bucket_rects, &id)
+                            .iter()
+                            .rev()
+                            .map(|rect_idx| NodeId::Leaf(*rect_idx)),
+                    );
+                }
+                NodeId::Invalid => continue,
+            }
+        }
+
+        None
+    }
+}
+
+/// Reusable buffers for [`Spatree::query_with()`] and the `_into`
+/// queries, e.g. [`Spatree::query_into()`].
+#[derive(Default, Debug, Clone)]
+pub struct QueryScratch {
+    /// Pending nodes and leaves of a traversal.
+    stack: Vec<NodeId>,
+}
+
+impl QueryScratch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Lazy query over a [`Spatree`], see [`Spatree::iter_query()`].
+pub struct QueryIter<'a, T, F> {
+    tree: &'a Spatree,
+    target: T,
+    hit_condition: F,
+    /// Pending nodes and leaves of the traversal.
+    stack: Vec<NodeId>,
+}
+
+impl<T, F> Iterator for QueryIter<'_, T, F>
+where
+    F: Fn(&Rect, &T) -> bool,
+{
+    type Item = RectId;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.tree
+            .next_hit(
+                &self.tree.nodes,
+                &self.target,
+                &self.hit_condition,
+                &mut self.stack,
+            )
+            .map(|(id, _)| id)
+    }
+}
+
+/// An internal node within the [`Spatree`].
+#[derive(Debug, Clone, Copy)]
+pub struct Node {
+    pub rect: Rect,
+    pub parent: Option<usize>,
+    pub children: [NodeId; 2],
+}
+
+impl Node {
+    /// Empty node with zero area, no children, and no parent.
+    pub const EMPTY: Self = Self {
+        rect: Rect::ZERO,
+        parent: None,
+        children: [NodeId::Invalid; 2],
+    };
+}
+
+/// A child of an internal [`Node`].
+///
+/// More kinds of children may be added, e.g. [`Self::Bucket`] was,
+/// so matching on it requires a wildcard arm.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[non_exhaustive]
+pub enum NodeId {
+    Internal(usize),
+    Leaf(usize),
+    /// Several rects stored in a single leaf, see
+    /// [`Spatree::set_max_leaf_rects()`] and
+    /// [`Spatree::child_rects()`].
+    Bucket {
+        start: usize,
+        len: usize,
+    },
+    Invalid,
+}
+
+/// Rect indices held by a leaf or a bucket, empty otherwise.
+fn child_leaves<'a>(
+    bucket_rects: &'a [usize],
+    child: &'a NodeId,
+) -> &'a [usize] {
+    match child {
+        NodeId::Leaf(rect_idx) => core::slice::from_ref(rect_idx),
+        NodeId::Bucket { start, len } => {
+            &bucket_rects[*start..*start + *len]
+        }
+        NodeId::Internal(_) | NodeId::Invalid => &[],
+    }
+}
+
+/// Bound of a child of an internal node, or `None` if invalid.
+fn child_bound(
+    nodes: &[Node],
+    rects: &[Rect],
+    bucket_rects: &[usize],
+    child: NodeId,
+) -> Option<Rect> {
+    match child {
+        // Leaf bounds are already known from the input rects
+        NodeId::Leaf(rect_idx) => Some(rects[rect_idx]),
+        NodeId::Internal(idx) => Some(nodes[idx].rect),
+        NodeId::Bucket { .. } => child_leaves(bucket_rects, &child)
+            .iter()
+            .map(|rect_idx| rects[*rect_idx])
+            .reduce(|a, b| a.union(b)),
+        NodeId::Invalid => None,
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct RectId(usize);
+
+/// Identifier of an additional hierarchy built via
+/// [`Spatree::build_index()`].
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct IndexId(usize);
+
+/// Error returned by [`Spatree::query_convex()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolygonError {
+    /// The polygon has fewer than 3 vertices.
+    TooFewPoints,
+    /// The polygon has zero area or non-finite vertices.
+    Degenerate,
+    /// The polygon is not convex.
+    NotConvex,
+}
+
+impl Display for PolygonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::TooFewPoints => {
+                "Polygon has fewer than 3 vertices."
+            }
+            Self::Degenerate => {
+                "Polygon has zero area or non-finite vertices."
+            }
+            Self::NotConvex => "Polygon is not convex.",
+        })
+    }
+}
+
+impl core::error::Error for PolygonError {}
+
+impl RectId {
+    pub fn into_inner(self) -> usize {
+        self.0
+    }
+}
+
+impl Deref for RectId {
+    type Target = usize;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Top down hierarchy building for single threaded algorithm.
+pub fn generate_hierarchy<C: MortonBits>(
+    codes: &[MortonCode<C>],
+) -> Vec<Node> {
+    let mut internal_nodes = Vec::new();
+    generate_hierarchy_into(
+        codes,
+        &mut internal_nodes,
+        &mut Vec::new(),
+        1,
+    );
+    internal_nodes
+}
+
+/// Represents a range to be split and its connection to the tree.
+struct BuildStack {
+    first: usize,
+    last: usize,
+    parent_idx: Option<usize>,
+    /// `0` for left, `1` for right.
+    child_slot: usize,
+}
+
+/// Same as [`generate_hierarchy()`], but reuses the allocations of
+/// `internal_nodes` and `stack`.
+///
+/// Ranges of up to `max_leaf_rects` codes are stored in a single
+/// [`NodeId::Bucket`], starting at their index within `codes`.
+fn generate_hierarchy_into<C: MortonBits>(
+    codes: &[MortonCode<C>],
+    internal_nodes: &mut Vec<Node>,
+    stack: &mut Vec<BuildStack>,
+    max_leaf_rects: usize,
+) {
+    internal_nodes.clear();
+    stack.clear();
+
+    let len = codes.len();
+    if len <= 1 {
+        return;
+    }
+
+    // A binary tree with N leaves has exactly N - 1 internal nodes.
+    internal_nodes.resize(len - 1, Node::EMPTY);
+    let mut node_idx = 0;
+
+    // First build stakc will have the full range.
+    stack.push(BuildStack {
+        first: 0,
+        last: internal_nodes.len(),
+        parent_idx: None,
+        child_slot: 0,
+    });
+
+    while let Some(task) = stack.pop() {
+        let BuildStack {
+            first,
+            last,
+            parent_idx,
+            child_slot,
+        } = task;
+
+        let curr_node_id = if first == last {
+            // Single element range represents a leaf node.
+            NodeId::Leaf(codes[first].index)
+        } else if last - first < max_leaf_rects {
+            NodeId::Bucket {
+                start: first,
+                len: last - first + 1,
+            }
+        } else {
+            // Internal node case.
+            let node_id = NodeId::Internal(node_idx);
+            let split = C::find_split(codes, first, last);
+
+            // Push right sub-range then left sub-range (LIFO).
+            stack.push(BuildStack {
+                first,
+                last: split,
+                parent_idx: Some(node_idx),
+                child_slot: 0,
+            });
+            stack.push(BuildStack {
+                first: split + 1,
                 last,
                 parent_idx: Some(node_idx),
                 child_slot: 1,
             });
 
-            node_idx += 1;
-            node_id
-        };
+            node_idx += 1;
+            node_id
+        };
+
+        // Link the current node to its parent if it's not the root.
+        if let Some(parent_idx) = parent_idx {
+            internal_nodes[parent_idx].children[child_slot] =
+                curr_node_id;
+
+            // If the current node is internal, set its parent index.
+            if let NodeId::Internal(curr_idx) = curr_node_id {
+                internal_nodes[curr_idx].parent = Some(parent_idx);
+            }
+        }
+    }
+
+    // Buckets take the place of some internal nodes.
+    internal_nodes.truncate(node_idx);
+}
+
+/// An internal node of a hierarchy built over arbitrary bounds.
+///
+/// See [`generate_hierarchy_generic()`].
+#[derive(Debug, Clone, Copy)]
+pub struct GenericNode<B> {
+    pub bound: B,
+    pub parent: Option<usize>,
+    pub children: [NodeId; 2],
+}
+
+/// Builds a hierarchy over arbitrary primitives, reusing the
+/// splitting logic of [`generate_hierarchy()`].
+///
+/// ### Arguments
+///
+/// - `codes`: Sorted Morton codes of the primitives.
+/// - `leaf_bound`: Computes the bound of the primitive at the given
+///   [`MortonCode::index`].
+/// - `union`: Combines two bounds into one enclosing both.
+pub fn generate_hierarchy_generic<B, L, U, C>(
+    codes: &[MortonCode<C>],
+    leaf_bound: L,
+    union: U,
+) -> Vec<GenericNode<B>>
+where
+    B: Clone,
+    L: Fn(usize) -> B,
+    U: Fn(&B, &B) -> B,
+    C: MortonBits,
+{
+    let nodes = generate_hierarchy(codes);
+    let len = nodes.len();
+
+    // Children always have a higher index than their parents, so the
+    // bounds are computed bottom-up and stored in reverse order.
+    let mut bounds = Vec::<B>::with_capacity(len);
+    for node in nodes.iter().rev() {
+        let [left, right] = node.children.map(|child| match child {
+            NodeId::Leaf(index) => leaf_bound(index),
+            NodeId::Internal(idx) => bounds[len - 1 - idx].clone(),
+            NodeId::Bucket { .. } | NodeId::Invalid => {
+                unreachable!("Built nodes always have 2 children.")
+            }
+        });
+        bounds.push(union(&left, &right));
+    }
+
+    nodes
+        .into_iter()
+        .zip(bounds.into_iter().rev())
+        .map(|(node, bound)| GenericNode {
+            bound,
+            parent: node.parent,
+            children: node.children,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    /// Deterministic pseudo random values in `[0, 1)`.
+    fn random(mut seed: u64) -> impl FnMut() -> f64 {
+        move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed >> 11) as f64 / (1u64 << 53) as f64
+        }
+    }
+
+    #[test]
+    fn test_empty_tree() {
+        let mut tree = Spatree::new();
+        tree.build(|r| r.center());
+
+        assert!(tree.rects.is_empty());
+        assert!(tree.nodes.is_empty());
+        assert_eq!(tree.global_bound().area(), 0.0);
+
+        let hits = tree.query_point(Point::new(10.0, 10.0));
+        assert!(hits.is_empty());
+    }
+
+    #[test]
+    fn test_single_item_tree() {
+        let mut tree = Spatree::new();
+        let r1 = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let id = tree.push_rect(r1);
+
+        tree.build(|r| r.center());
+
+        // A single root holds the only item.
+        assert_eq!(tree.nodes.len(), 1);
+        assert_eq!(tree.nodes[0].rect, r1);
+        assert_eq!(tree.leaf_node_of(id), Some(0));
+
+        let hits = tree.query_point(Point::new(5.0, 5.0));
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0], id);
+        assert!(tree.query_point(Point::new(15.0, 5.0)).is_empty());
+    }
+
+    #[test]
+    fn test_query_through_hierarchy() {
+        let rects = [
+            Rect::new(0.0, 0.0, 10.0, 10.0),
+            Rect::new(20.0, 0.0, 30.0, 10.0),
+        ];
+        // Number of internal nodes for 0, 1 and 2 rects.
+        for (len, node_count) in [(0, 0), (1, 1), (2, 1)] {
+            let mut tree = Spatree::new();
+            let ids = rects[..len]
+                .iter()
+                .map(|rect| tree.push_rect(*rect))
+                .collect::<Vec<_>>();
+            tree.build(|r| r.center());
+            assert_eq!(tree.nodes.len(), node_count);
+
+            for (id, rect) in ids.iter().zip(rects) {
+                assert_eq!(tree.query_point(rect.center()), [*id]);
+                assert_eq!(
+                    tree.query_point_single(rect.center(), |a, _| a),
+                    Some(*id)
+                );
+            }
+            let mut hits =
+                tree.query_rect(Rect::new(0.0, 0.0, 30.0, 10.0));
+            hits.sort();
+            assert_eq!(hits, ids);
+            assert!(
+                tree.query_point(Point::new(15.0, 5.0)).is_empty()
+            );
+        }
+
+        // Rects pushed after the build are not hit until rebuilt.
+        let mut tree = Spatree::new();
+        tree.push_rect(rects[0]);
+        tree.build(|r| r.center());
+        let id = tree.push_rect(rects[1]);
+        assert!(tree.query_point(rects[1].center()).is_empty());
+        tree.build(|r| r.center());
+        assert_eq!(tree.query_point(rects[1].center()), [id]);
+    }
+
+    #[test]
+    fn test_zero_area_bound() {
+        // Rects along a line, the bound has no area.
+        let mut tree = Spatree::new();
+        let ids = (0..3)
+            .map(|i| {
+                let x = i as f64 * 20.0;
+                tree.push_rect(Rect::new(x, 0.0, x + 10.0, 0.0))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tree.build_counting_out_of_bounds(|r| r.center()),
+            0
+        );
+        assert_eq!(tree.nodes.len(), 2);
+
+        // Degenerate rects contain no points, query by overlap.
+        for id in ids {
+            let center = tree.get_rect(id).unwrap().center();
+            let target = Rect::from_origin_size(center, (0.0, 0.0));
+            assert_eq!(tree.query_rect(target), [id]);
+        }
+    }
+
+    #[test]
+    fn test_collinear_rects() {
+        for vertical in [false, true] {
+            let mut tree = Spatree::new();
+            // Pushed out of order along the line.
+            let ids = (0..100)
+                .map(|i| {
+                    let t = ((i * 37) % 100) as f64 * 10.0;
+                    let rect = Rect::new(t, 0.0, t + 5.0, 0.0);
+                    let rect = match vertical {
+                        true => Rect::new(0.0, rect.x0, 0.0, rect.x1),
+                        false => rect,
+                    };
+                    (tree.push_rect(rect), rect)
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                tree.build_counting_out_of_bounds(|r| r.center()),
+                0
+            );
+
+            // Ordered along the line, the halves do not overlap.
+            let [a, b] = tree.nodes[0].children.map(|child| {
+                let NodeId::Internal(idx) = child else {
+                    panic!("expected an internal node");
+                };
+                tree.nodes[idx].rect
+            });
+            let [a, b] = [a, b].map(|rect| match vertical {
+                true => (rect.y0, rect.y1),
+                false => (rect.x0, rect.x1),
+            });
+            assert!(a.1 < b.0 || b.1 < a.0);
+
+            for (id, rect) in ids {
+                let target =
+                    Rect::from_origin_size(rect.center(), (0.0, 0.0));
+                assert_eq!(tree.query_rect(target), [id]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_coincident_rects() {
+        let mut tree = Spatree::new();
+        for _ in 0..100 {
+            tree.push_rect(Rect::ZERO);
+        }
+        assert_eq!(
+            tree.build_counting_out_of_bounds(|r| r.center()),
+            0
+        );
+        assert_eq!(tree.nodes.len(), 99);
+
+        // Split evenly, 100 leaves fit within a depth of 7.
+        let depth = |mut idx: usize| {
+            let mut depth = 0;
+            while let Some(parent) = tree.parent_of(idx) {
+                idx = parent;
+                depth += 1;
+            }
+            depth
+        };
+        assert!((0..tree.nodes.len()).all(|idx| depth(idx) < 7));
+        assert_eq!(tree.query_rect(Rect::ZERO).len(), 100);
+    }
+
+    #[test]
+    fn test_hierarchy_structure_and_bounds() {
+        let mut tree = Spatree::new();
+
+        // 4 corners of a 100x100 area.
+        // Top left.
+        let r1 = Rect::new(0.0, 0.0, 10.0, 10.0);
+        // Top right.
+        let r2 = Rect::new(90.0, 0.0, 100.0, 10.0);
+        // Bottom left.
+        let r3 = Rect::new(0.0, 90.0, 10.0, 100.0);
+        // Bottom right.
+        let r4 = Rect::new(90.0, 90.0, 100.0, 100.0);
+
+        tree.push_rect(r1);
+        tree.push_rect(r2);
+        tree.push_rect(r3);
+        tree.push_rect(r4);
+
+        tree.build(|r| r.center());
+
+        // N items = N-1 internal nodes.
+        assert_eq!(tree.nodes.len(), 3);
+
+        // Root is the first node generated in top-down.
+        let root = &tree.nodes[0];
+        let expected_union = r1.union(r2).union(r3).union(r4);
+
+        assert_eq!(root.rect.x0, expected_union.x0);
+        assert_eq!(root.rect.y0, expected_union.y0);
+        assert_eq!(root.rect.x1, expected_union.x1);
+        assert_eq!(root.rect.y1, expected_union.y1);
+    }
+
+    #[test]
+    fn test_nodes_and_rects() {
+        let mut tree = Spatree::new();
+        for i in 0..10 {
+            let x = i as f64 * 20.0;
+            tree.push_rect(Rect::new(x, 0.0, x + 10.0, 10.0));
+        }
+        tree.build(|r| r.center());
+
+        assert_eq!(tree.rects().len(), 10);
+        assert_eq!(
+            tree.nodes().len(),
+            tree.rects().len().saturating_sub(1)
+        );
+        assert_eq!(tree.nodes()[0].rect, *tree.global_bound());
+        for node in tree.nodes() {
+            for child in node.children {
+                if let NodeId::Leaf(idx) = child {
+                    assert!(
+                        node.rect.contains_rect(tree.rects()[idx])
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_leaf_node_of() {
+        let mut tree = Spatree::new();
+
+        let ids = [
+            tree.push_rect(Rect::new(0.0, 0.0, 10.0, 10.0)),
+            tree.push_rect(Rect::new(90.0, 0.0, 100.0, 10.0)),
+            tree.push_rect(Rect::new(0.0, 90.0, 10.0, 100.0)),
+            tree.push_rect(Rect::new(90.0, 90.0, 100.0, 100.0)),
+        ];
+
+        // Not built yet.
+        assert!(tree.leaf_node_of(ids[0]).is_none());
+
+        tree.build(|r| r.center());
+
+        for id in ids {
+            let node_idx = tree.leaf_node_of(id).unwrap();
+            let node = tree.get_node(node_idx).unwrap();
+            assert!(node.children.contains(&NodeId::Leaf(*id)));
+            assert!(
+                node.rect.contains_rect(*tree.get_rect(id).unwrap())
+            );
+
+            // Walk up to the root.
+            let mut node_idx = node_idx;
+            while let Some(parent_idx) = tree.parent_of(node_idx) {
+                assert!(parent_idx < node_idx);
+                node_idx = parent_idx;
+            }
+            assert_eq!(node_idx, 0);
+        }
+
+        assert!(tree.parent_of(0).is_none());
+        assert!(tree.parent_of(100).is_none());
+        assert!(tree.leaf_node_of(RectId(100)).is_none());
+    }
+
+    #[test]
+    fn test_query_point() {
+        let mut tree = Spatree::new();
+        let r1 = Rect::new(10.0, 10.0, 30.0, 30.0);
+        let r2 = Rect::new(20.0, 20.0, 40.0, 40.0);
+
+        let id1 = tree.push_rect(r1);
+        let id2 = tree.push_rect(r2);
+
+        tree.build(|r| r.center());
+
+        // Point inside intersection.
+        let hits = tree.query_point(Point::new(25.0, 25.0));
+        assert_eq!(hits.len(), 2);
+        assert!(hits.contains(&id1));
+        assert!(hits.contains(&id2));
+    }
+
+    #[test]
+    fn test_query_rect() {
+        let mut tree = Spatree::new();
+
+        // Define 3 distinct areas.
+        // Top left.
+        let r1 = Rect::new(0.0, 0.0, 10.0, 10.0);
+        // Top right.
+        let r2 = Rect::new(20.0, 0.0, 30.0, 10.0);
+        // Bottom wide strip.
+        let r3 = Rect::new(0.0, 20.0, 30.0, 30.0);
+
+        let id1 = tree.push_rect(r1);
+        let id2 = tree.push_rect(r2);
+        let id3 = tree.push_rect(r3);
+
+        tree.build(|r| r.center());
+
+        // 1. Overlaps only `r1`.
+        let q1 = Rect::new(-5.0, -5.0, 5.0, 5.0);
+        let hits = tree.query_rect(q1);
+        assert_eq!(hits.len(), 1);
+        assert!(hits.contains(&id1));
+
+        // 2. Overlaps `r1` and `r2` but not `r3`.
+        let q2 = Rect::new(5.0, 2.0, 25.0, 8.0);
+        let hits = tree.query_rect(q2);
+        assert_eq!(hits.len(), 2);
+        assert!(hits.contains(&id1));
+        assert!(hits.contains(&id2));
+        assert!(!hits.contains(&id3));
+
+        // 3. Overlaps all 3.
+        let q3 = Rect::new(5.0, 5.0, 25.0, 25.0);
+        let hits = tree.query_rect(q3);
+        assert_eq!(hits.len(), 3);
+        assert!(hits.contains(&id1));
+        assert!(hits.contains(&id2));
+        assert!(hits.contains(&id3));
+
+        // 4. Complete miss
+        let q4 = Rect::new(100.0, 100.0, 110.0, 110.0);
+        let hits = tree.query_rect(q4);
+        assert!(hits.is_empty());
+    }
+
+    #[test]
+    fn test_query_into() {
+        let mut tree = Spatree::new();
+        tree.push_rect(Rect::new(0.0, 0.0, 10.0, 10.0));
+        tree.push_rect(Rect::new(5.0, 5.0, 15.0, 15.0));
+        tree.push_rect(Rect::new(20.0, 20.0, 30.0, 30.0));
+
+        tree.build(|r| r.center());
+
+        // Stale content must be cleared.
+        let mut out = vec![RectId(42)];
+        let mut scratch = QueryScratch::new();
+
+        let p = Point::new(7.0, 7.0);
+        tree.query_point_into(p, &mut out, &mut scratch);
+        assert_eq!(out, tree.query_point(p));
+
+        let q = Rect::new(8.0, 8.0, 25.0, 25.0);
+        tree.query_rect_into(q, &mut out, &mut scratch);
+        assert_eq!(out, tree.query_rect(q));
+
+        // Complete miss.
+        tree.query_rect_into(
+            Rect::new(50.0, 50.0, 60.0, 60.0),
+            &mut out,
+            &mut scratch,
+        );
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_query_into_trivial_trees() {
+        let mut tree = Spatree::new();
+        let mut out = vec![RectId(42)];
+        let mut scratch = QueryScratch::new();
+
+        // No nodes.
+        tree.build(|r| r.center());
+        tree.query_point_into(Point::ZERO, &mut out, &mut scratch);
+        assert!(out.is_empty());
+
+        // A single rect under the root node.
+        tree.push_rect(Rect::new(0.0, 0.0, 10.0, 10.0));
+        tree.build(|r| r.center());
+        tree.query_point_into(
+            Point::new(5.0, 5.0),
+            &mut out,
+            &mut scratch,
+        );
+        assert_eq!(out, [RectId(0)]);
+        tree.query_rect_into(
+            Rect::new(20.0, 0.0, 30.0, 10.0),
+            &mut out,
+            &mut scratch,
+        );
+        assert!(out.is_empty());
+        tree.query_into(
+            (),
+            |rect, _| rect.width() == 10.0,
+            &mut out,
+            &mut scratch,
+        );
+        assert_eq!(out, [RectId(0)]);
+    }
+
+    /// Largest index win (simulating a stack/z-order).
+    #[inline(always)]
+    fn stack_conflict_resolution(a: RectId, b: RectId) -> RectId {
+        if a > b { a } else { b }
+    }
+
+    #[test]
+    fn test_query_point_single() {
+        let mut tree = Spatree::new();
+
+        // Largest (lowest).
+        let id0 = tree.push_rect(Rect::new(0.0, 0.0, 100.0, 100.0));
+        // Middle (center).
+        let id1 = tree.push_rect(Rect::new(0.0, 0.0, 50.0, 50.0));
+        // Smallest (top).
+        let id2 = tree.push_rect(Rect::new(0.0, 0.0, 10.0, 10.0));
+
+        assert!(id0 < id1 && id1 < id2, "Ids should be incremented!");
+
+        tree.build(|r| r.center());
+
+        // 1. Point hits all 3.
+        let p1 = Point::new(5.0, 5.0);
+        let hit =
+            tree.query_point_single(p1, stack_conflict_resolution);
+        assert_eq!(hit, Some(id2));
+
+        // 2. Point hits `id0` and `id1`, but misses the tiny `id2`.
+        let p2 = Point::new(20.0, 20.0);
+        let hit =
+            tree.query_point_single(p2, stack_conflict_resolution);
+        assert_eq!(hit, Some(id1));
+
+        // 3. Point hits only the large base `id0`.
+        let p3 = Point::new(75.0, 75.0);
+        let hit =
+            tree.query_point_single(p3, stack_conflict_resolution);
+        assert_eq!(hit, Some(id0));
+
+        // 4. Complete miss.
+        let p4 = Point::new(150.0, 150.0);
+        let hit =
+            tree.query_point_single(p4, stack_conflict_resolution);
+        assert!(hit.is_none());
+    }
+
+    #[test]
+    fn test_query_rect_single() {
+        let mut tree = Spatree::new();
+
+        // Largest (lowest).
+        let id0 = tree.push_rect(Rect::new(0.0, 0.0, 100.0, 100.0));
+        // Middle (center).
+        let id1 = tree.push_rect(Rect::new(0.0, 0.0, 50.0, 50.0));
+        // Smallest (top).
+        let id2 = tree.push_rect(Rect::new(0.0, 0.0, 10.0, 10.0));
+
+        tree.build(|r| r.center());
+
+        // 1. Overlaps all 3.
+        let q1 = Rect::new(2.0, 2.0, 8.0, 8.0);
+        let hit =
+            tree.query_rect_single(q1, stack_conflict_resolution);
+        assert_eq!(hit, Some(id2));
+
+        // 2. Overlaps `id0` and `id1`, but misses `id2`.
+        let q2 = Rect::new(15.0, 15.0, 25.0, 25.0);
+        let hit =
+            tree.query_rect_single(q2, stack_conflict_resolution);
+        assert_eq!(hit, Some(id1));
+
+        // 3. Overlaps only the largest base `id0`.
+        let q3 = Rect::new(60.0, 60.0, 70.0, 70.0);
+        let hit =
+            tree.query_rect_single(q3, stack_conflict_resolution);
+        assert_eq!(hit, Some(id0));
+
+        // 4. Complete miss.
+        let q4 = Rect::new(200.0, 200.0, 210.0, 210.0);
+        let hit =
+            tree.query_rect_single(q4, stack_conflict_resolution);
+        assert!(hit.is_none());
+    }
+
+    #[test]
+    fn test_query_with_early_abort() {
+        let mut tree = Spatree::new();
+        for i in 0..10 {
+            let offset = i as f64;
+            tree.push_rect(Rect::new(
+                offset,
+                offset,
+                100.0 + offset,
+                100.0 + offset,
+            ));
+        }
+        tree.build(|r| r.center());
+
+        let point = Point::new(50.0, 50.0);
+        let hit_condition =
+            |rect: &Rect, point: &Point| rect.contains(*point);
+
+        let mut scratch = QueryScratch::new();
+
+        // Stops after the first hit.
+        let mut calls = 0;
+        let aborted = tree.query_with(
+            point,
+            hit_condition,
+            |_| {
+                calls += 1;
+                ControlFlow::Break(())
+            },
+            &mut scratch,
+        );
+        assert!(aborted);
+        assert_eq!(calls, 1);
+
+        // Visits every hit otherwise.
+        let mut calls = 0;
+        let aborted = tree.query_with(
+            point,
+            hit_condition,
+            |_| {
+                calls += 1;
+                ControlFlow::Continue(())
+            },
+            &mut scratch,
+        );
+        assert!(!aborted);
+        assert_eq!(calls, 10);
+    }
+
+    #[test]
+    fn test_iter_query() {
+        let mut tree = Spatree::new();
+        // Empty tree.
+        assert_eq!(tree.iter_query_point(Point::ZERO).next(), None);
+
+        // Single rect.
+        tree.push_rect(Rect::new(0.0, 0.0, 10.0, 10.0));
+        tree.build(|r| r.center());
+        assert_eq!(
+            tree.iter_query_point(Point::new(5.0, 5.0))
+                .collect::<Vec<_>>(),
+            [RectId(0)]
+        );
+        assert_eq!(
+            tree.iter_query_point(Point::new(20.0, 5.0)).count(),
+            0
+        );
+
+        let mut next = random(7);
+        tree.clear();
+        for _ in 0..500 {
+            let (x, y) = (next() * 1000.0, next() * 1000.0);
+            let (w, h) = (next() * 50.0, next() * 50.0);
+            tree.push_rect(Rect::new(x, y, x + w, y + h));
+        }
+        tree.build(|r| r.center());
+        tree.remove_rect(RectId(3));
+
+        let sorted = |mut ids: Vec<RectId>| {
+            ids.sort();
+            ids
+        };
+        for _ in 0..50 {
+            let point = Point::new(next() * 1000.0, next() * 1000.0);
+            assert_eq!(
+                sorted(tree.iter_query_point(point).collect()),
+                sorted(tree.query_point(point))
+            );
+
+            let rect = Rect::from_origin_size(point, (80.0, 80.0));
+            assert_eq!(
+                sorted(tree.iter_query_rect(rect).collect()),
+                sorted(tree.query_rect(rect))
+            );
+        }
+
+        // Stops traversing once enough hits are taken.
+        let tests = core::cell::Cell::new(0);
+        let hit_condition = |rect: &Rect, _: &()| {
+            tests.set(tests.get() + 1);
+            rect.area() >= 0.0
+        };
+        assert_eq!(tree.iter_query((), hit_condition).count(), 499);
+        let full_tests = tests.replace(0);
+        assert!(tree.iter_query((), hit_condition).next().is_some());
+        assert!(tests.get() < full_tests);
+    }
+
+    #[test]
+    fn test_query_top_k() {
+        let mut tree = Spatree::new();
+        let mut next = random(23);
+        for _ in 0..500 {
+            let (x, y) = (next() * 1000.0, next() * 1000.0);
+            let (w, h) = (next() * 80.0, next() * 80.0);
+            tree.push_rect(Rect::new(x, y, x + w, y + h));
+        }
+        tree.build(|r| r.center());
+        tree.remove_rect(RectId(3));
+
+        // The id mimics the z-order.
+        let z_order = |id: RectId| id;
+        for _ in 0..50 {
+            let (x, y) = (next() * 1000.0, next() * 1000.0);
+            let window = Rect::new(x, y, x + 100.0, y + 100.0);
+            let overlaps = |r: &Rect, w: &Rect| r.overlaps(*w);
+
+            let mut expected = tree.query(window, overlaps);
+            expected.sort_by(|a, b| b.cmp(a));
+            for k in [0, 1, 5, expected.len() + 1] {
+                let mut truncated = expected.clone();
+                truncated.truncate(k);
+                assert_eq!(
+                    tree.query_top_k(window, overlaps, z_order, k),
+                    truncated
+                );
+            }
+        }
+
+        // Equal priorities are ordered by the smallest id.
+        let hits = tree.query_rect(Rect::new(0.0, 0.0, 500.0, 500.0));
+        let mut expected = hits.clone();
+        expected.sort();
+        expected.truncate(5);
+        assert_eq!(
+            tree.query_top_k(
+                Rect::new(0.0, 0.0, 500.0, 500.0),
+                |r, w| r.overlaps(*w),
+                |_| 0,
+                5,
+            ),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_query_knn() {
+        let mut tree = Spatree::new();
+        assert!(tree.query_knn(Point::ZERO, 3).is_empty());
+
+        let mut next = random(11);
+        for _ in 0..300 {
+            let (x, y) = (next() * 1000.0, next() * 1000.0);
+            let (w, h) = (next() * 40.0, next() * 40.0);
+            tree.push_rect(Rect::new(x, y, x + w, y + h));
+        }
+        // Duplicates tie on distance.
+        tree.push_rect(Rect::new(500.0, 500.0, 510.0, 510.0));
+        tree.push_rect(Rect::new(500.0, 500.0, 510.0, 510.0));
+        tree.build(|r| r.center());
+        tree.remove_rect(RectId(7));
+
+        let brute_force = |point: Point, k: usize| {
+            let mut hits = tree
+                .rects()
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !tree.is_removed(RectId(*i)))
+                .map(|(i, rect)| {
+                    (RectId(i), distance_to_point(rect, point))
+                })
+                .collect::<Vec<_>>();
+            hits.sort_by(|a, b| {
+                a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0))
+            });
+            hits.truncate(k);
+            hits
+        };
+
+        for k in [1, 5, 20] {
+            for _ in 0..20 {
+                let point =
+                    Point::new(next() * 1100.0, next() * 1100.0);
+                assert_eq!(
+                    tree.query_knn(point, k),
+                    brute_force(point, k)
+                );
+            }
+        }
+
+        let point = Point::new(520.0, 505.0);
+        let hits = tree.query_knn(point, 2);
+        assert_eq!(hits, brute_force(point, 2));
+
+        // More neighbors requested than there are rects.
+        let hits = tree.query_knn(point, 1000);
+        assert_eq!(hits.len(), 301);
+        assert_eq!(hits, brute_force(point, 1000));
+        assert!(tree.query_knn(point, 0).is_empty());
+    }
+
+    #[test]
+    fn test_query_nearest() {
+        let mut tree = Spatree::new();
+        assert_eq!(tree.query_nearest(Point::ZERO), None);
+
+        let mut next = random(13);
+        for _ in 0..300 {
+            let (x, y) = (next() * 1000.0, next() * 1000.0);
+            let (w, h) = (next() * 80.0, next() * 80.0);
+            tree.push_rect(Rect::new(x, y, x + w, y + h));
+        }
+        tree.build(|r| r.center());
+        tree.remove_rect(RectId(4));
+
+        let brute_force = |point: Point| {
+            tree.rects()
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !tree.is_removed(RectId(*i)))
+                .map(|(i, rect)| ByDistance {
+                    distance: distance_to_point(rect, point),
+                    item: RectId(i),
+                })
+                .min()
+                .map(|hit| (hit.item, hit.distance))
+        };
+
+        let mut inside = 0;
+        for _ in 0..200 {
+            let point = Point::new(next() * 1200.0, next() * 1200.0);
+            let nearest = tree.query_nearest(point);
+            assert_eq!(nearest, brute_force(point));
+            assert_eq!(
+                nearest,
+                tree.query_knn(point, 1).first().copied()
+            );
+            if nearest.is_some_and(|(_, distance)| distance == 0.0) {
+                inside += 1;
+            }
+        }
+        assert!(inside > 0);
+
+        // Overlapping rects containing the point, the smallest id
+        // wins.
+        let mut tree = Spatree::new();
+        tree.push_rect(Rect::new(100.0, 100.0, 110.0, 110.0));
+        tree.push_rect(Rect::new(0.0, 0.0, 20.0, 20.0));
+        tree.push_rect(Rect::new(5.0, 5.0, 30.0, 30.0));
+        tree.push_rect(Rect::new(0.0, 0.0, 20.0, 20.0));
+        tree.build(|r| r.center());
+        assert_eq!(
+            tree.query_nearest(Point::new(10.0, 10.0)),
+            Some((RectId(1), 0.0))
+        );
+        assert_eq!(
+            tree.query_nearest(Point::new(115.0, 105.0)),
+            Some((RectId(0), 5.0))
+        );
+    }
+
+    #[test]
+    fn test_query_segment() {
+        let mut tree = Spatree::new();
+        assert!(
+            tree.query_segment(Point::ZERO, Point::new(1.0, 1.0))
+                .is_empty()
+        );
+
+        for y in 0..10 {
+            for x in 0..10 {
+                let (x, y) = (x as f64 * 20.0, y as f64 * 20.0);
+                tree.push_rect(Rect::new(x, y, x + 10.0, y + 10.0));
+            }
+        }
+        tree.build(|r| r.center());
+        let id = |x: usize, y: usize| RectId(y * 10 + x);
+        let sorted = |mut ids: Vec<RectId>| {
+            ids.sort();
+            ids
+        };
+
+        // Diagonal through every cell on the main diagonal.
+        let diagonal = sorted(tree.query_segment(
+            Point::new(-5.0, -5.0),
+            Point::new(195.0, 195.0),
+        ));
+        assert_eq!(
+            diagonal,
+            (0..10).map(|i| id(i, i)).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            tree.query_segment_first(
+                Point::new(195.0, 195.0),
+                Point::new(-5.0, -5.0),
+            ),
+            Some((id(9, 9), 5.0 * 2.0_f64.sqrt()))
+        );
+
+        // Horizontal, passing through the gaps between rows.
+        assert!(
+            tree.query_segment(
+                Point::new(0.0, 15.0),
+                Point::new(200.0, 15.0),
+            )
+            .is_empty()
+        );
+        // Horizontal, lying exactly on the bottom edges of a row.
+        let edge = sorted(tree.query_segment(
+            Point::new(25.0, 50.0),
+            Point::new(85.0, 50.0),
+        ));
+        assert_eq!(edge, [id(1, 2), id(2, 2), id(3, 2), id(4, 2)]);
+        assert_eq!(
+            tree.query_segment_first(
+                Point::new(25.0, 50.0),
+                Point::new(85.0, 50.0),
+            ),
+            Some((id(1, 2), 0.0))
+        );
+        assert_eq!(
+            tree.query_segment_first(
+                Point::new(95.0, 50.0),
+                Point::new(25.0, 50.0),
+            ),
+            Some((id(4, 2), 5.0))
+        );
+
+        // Degenerate segments are point queries.
+        let point = Point::new(45.0, 65.0);
+        assert_eq!(tree.query_segment(point, point), [id(2, 3)]);
+        assert_eq!(
+            tree.query_segment_first(point, point),
+            Some((id(2, 3), 0.0))
+        );
+        let gap = Point::new(15.0, 15.0);
+        assert!(tree.query_segment(gap, gap).is_empty());
+        assert_eq!(tree.query_segment_first(gap, gap), None);
+
+        // Ties on the entry point are broken by id.
+        tree.push_rect(Rect::new(-10.0, 100.0, 10.0, 110.0));
+        tree.build(|r| r.center());
+        assert_eq!(
+            tree.query_segment_first(
+                Point::new(0.0, 95.0),
+                Point::new(0.0, 200.0),
+            ),
+            Some((id(0, 5), 5.0))
+        );
+    }
+
+    #[test]
+    fn test_query_circle() {
+        let mut tree = Spatree::new();
+        // To the right, just inside and just outside of the radius.
+        tree.push_rect(Rect::new(109.9, 95.0, 120.0, 105.0));
+        tree.push_rect(Rect::new(110.1, 95.0, 120.0, 105.0));
+        // Diagonal, with the corner at a distance of 10.0.
+        tree.push_rect(Rect::new(106.0, 108.0, 120.0, 120.0));
+        tree.push_rect(Rect::new(106.1, 108.1, 120.0, 120.0));
+        // Containing the center.
+        tree.push_rect(Rect::new(90.0, 90.0, 101.0, 101.0));
+        tree.build(|r| r.center());
+
+        let center = Point::new(100.0, 100.0);
+        let sorted = |mut ids: Vec<RectId>| {
+            ids.sort();
+            ids
+        };
+        assert_eq!(
+            sorted(tree.query_circle(center, 10.0)),
+            [RectId(0), RectId(2), RectId(4)]
+        );
+        assert_eq!(
+            sorted(tree.query_circle(center, 1000.0)),
+            (0..5).map(RectId).collect::<Vec<_>>()
+        );
+
+        // Degrades to a point query.
+        let mut out = vec![RectId(42)];
+        let mut scratch = QueryScratch::new();
+        for radius in [0.0, -5.0] {
+            tree.query_circle_into(
+                center,
+                radius,
+                &mut out,
+                &mut scratch,
+            );
+            assert_eq!(out, [RectId(4)]);
+            assert_eq!(out, tree.query_point(center));
+        }
+    }
+
+    #[test]
+    fn test_query_convex() {
+        let mut tree = Spatree::new();
+        // 10x10 grid of unit cells.
+        for y in 0..10 {
+            for x in 0..10 {
+                let (x, y) = (x as f64, y as f64);
+                tree.push_rect(Rect::new(x, y, x + 1.0, y + 1.0));
+            }
+        }
+        tree.build(|r| r.center());
+
+        // Square rotated by 45 degrees, centered within cell (5, 5)
+        // and reaching 1.8 along each axis.
+        let c = Point::new(5.5, 5.5);
+        let diamond = [
+            c + Vec2::new(0.0, -1.8),
+            c + Vec2::new(1.8, 0.0),
+            c + Vec2::new(0.0, 1.8),
+            c + Vec2::new(-1.8, 0.0),
+        ];
+        let mut hits = tree.query_convex(&diamond).unwrap();
+        hits.sort();
+
+        // The bounding box covers a 5x5 block of cells, of which
+        // the diamond only overlaps those within a Manhattan distance
+        // of 2 from the center cell.
+        assert_eq!(
+            tree.query_rect(Rect::new(3.7, 3.7, 7.3, 7.3)).len(),
+            25
+        );
+        let expected = (3..8)
+            .flat_map(|y: usize| (3..8).map(move |x: usize| (x, y)))
+            .filter(|(x, y)| x.abs_diff(5) + y.abs_diff(5) <= 2)
+            .map(|(x, y)| RectId(y * 10 + x))
+            .collect::<Vec<_>>();
+        assert_eq!(expected.len(), 13);
+        assert_eq!(hits, expected);
+
+        // Either winding order.
+        let mut reversed = diamond;
+        reversed.reverse();
+        let mut hits = tree.query_convex(&reversed).unwrap();
+        hits.sort();
+        assert_eq!(hits, expected);
+
+        assert_eq!(
+            tree.query_convex(&diamond[..2]),
+            Err(PolygonError::TooFewPoints)
+        );
+        assert_eq!(
+            tree.query_convex(&[
+                Point::new(0.0, 0.0),
+                Point::new(1.0, 1.0),
+                Point::new(2.0, 2.0),
+            ]),
+            Err(PolygonError::Degenerate)
+        );
+        assert_eq!(
+            tree.query_convex(&[
+                Point::new(0.0, 0.0),
+                Point::new(f64::NAN, 1.0),
+                Point::new(2.0, 0.0),
+            ]),
+            Err(PolygonError::Degenerate)
+        );
+        // Concave arrow head.
+        assert_eq!(
+            tree.query_convex(&[
+                Point::new(0.0, 0.0),
+                Point::new(4.0, 2.0),
+                Point::new(0.0, 4.0),
+                Point::new(1.0, 2.0),
+            ]),
+            Err(PolygonError::NotConvex)
+        );
+        // Pentagram, turning in one direction but winding twice.
+        let star = [0, 2, 4, 1, 3].map(|i| {
+            let angle = i as f64 * core::f64::consts::TAU / 5.0;
+            Point::new(
+                5.0 + 3.0 * angle.cos(),
+                5.0 + 3.0 * angle.sin(),
+            )
+        });
+        assert_eq!(
+            tree.query_convex(&star),
+            Err(PolygonError::NotConvex)
+        );
+    }
+
+    #[test]
+    fn test_query_with_rects() {
+        let mut tree = Spatree::new();
+        let mut next = random(17);
+        for _ in 0..200 {
+            let (x, y) = (next() * 500.0, next() * 500.0);
+            let (w, h) = (next() * 60.0, next() * 60.0);
+            tree.push_rect(Rect::new(x, y, x + w, y + h));
+        }
+        tree.build(|r| r.center());
+
+        let point = Point::new(250.0, 250.0);
+        let hits = tree.query_point_with_rects(point);
+        assert_eq!(
+            hits.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            tree.query_point(point)
+        );
+        for (id, rect) in hits.iter() {
+            assert_eq!(tree.get_rect(*id), Some(rect));
+        }
+
+        let target = Rect::new(100.0, 100.0, 200.0, 200.0);
+        let hits = tree.query_rect_with_rects(target);
+        assert!(!hits.is_empty());
+        assert_eq!(
+            hits.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            tree.query_rect(target)
+        );
+        for (id, rect) in hits.iter() {
+            assert_eq!(tree.get_rect(*id), Some(rect));
+        }
+    }
+
+    #[test]
+    fn test_query_rect_containment() {
+        let mut tree = Spatree::new();
+        let outer = tree.push_rect(Rect::new(0.0, 0.0, 100.0, 100.0));
+        let inner = tree.push_rect(Rect::new(20.0, 20.0, 40.0, 40.0));
+        // Touching the edge of the selection from the inside.
+        let edge = tree.push_rect(Rect::new(40.0, 20.0, 50.0, 30.0));
+        // Straddling the edge of the selection.
+        let straddling =
+            tree.push_rect(Rect::new(45.0, 45.0, 55.0, 55.0));
+        let far =
+            tree.push_rect(Rect::new(200.0, 200.0, 210.0, 210.0));
+        tree.build(|r| r.center());
+
+        let sorted = |mut ids: Vec<RectId>| {
+            ids.sort();
+            ids
+        };
+        let selection = Rect::new(10.0, 10.0, 50.0, 50.0);
+        assert_eq!(
+            sorted(tree.query_rect_contained(selection)),
+            [inner, edge]
+        );
+        assert_eq!(
+            sorted(tree.query_rect(selection)),
+            [outer, inner, edge, straddling]
+        );
+        // Equal rects contain each other.
+        assert_eq!(
+            sorted(tree.query_rect_contained(Rect::new(
+                20.0, 20.0, 40.0, 40.0
+            ))),
+            [inner]
+        );
+
+        let target = Rect::new(25.0, 25.0, 40.0, 35.0);
+        assert_eq!(
+            sorted(tree.query_rect_containing(target)),
+            [outer, inner]
+        );
+        // Sharing edges with a rect, and equal to a rect.
+        assert_eq!(
+            sorted(tree.query_rect_containing(Rect::new(
+                40.0, 25.0, 50.0, 30.0
+            ))),
+            [outer, edge]
+        );
+        assert_eq!(
+            tree.query_rect_containing(Rect::new(
+                200.0, 200.0, 210.0, 210.0
+            )),
+            [far]
+        );
+        assert!(
+            tree.query_rect_containing(Rect::new(
+                90.0, 90.0, 110.0, 110.0
+            ))
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_overlapping_pairs() {
+        let brute_force = |tree: &Spatree| {
+            let mut pairs = Vec::new();
+            for (a, a_rect) in tree.rects().iter().enumerate() {
+                for (b, b_rect) in tree.rects().iter().enumerate() {
+                    if a < b
+                        && !tree.is_removed(RectId(a))
+                        && !tree.is_removed(RectId(b))
+                        && a_rect.overlaps(*b_rect)
+                    {
+                        pairs.push((RectId(a), RectId(b)));
+                    }
+                }
+            }
+            pairs
+        };
+        let sorted = |mut pairs: Vec<(RectId, RectId)>| {
+            pairs.sort();
+            pairs
+        };
+
+        let mut tree = Spatree::new();
+        tree.build(|r| r.center());
+        assert!(tree.overlapping_pairs().is_empty());
+
+        tree.push_rect(Rect::new(0.0, 0.0, 10.0, 10.0));
+        tree.build(|r| r.center());
+        assert!(tree.overlapping_pairs().is_empty());
+
+        // Grid with every other cell enlarged into its neighbors.
+        tree.clear();
+        for y in 0..12 {
+            for x in 0..12 {
+                let origin =
+                    Point::new(x as f64 * 20.0, y as f64 * 20.0);
+                let rect =
+                    Rect::from_origin_size(origin, (10.0, 10.0));
+                let rect = match (x + y) % 2 {
+                    0 => rect.inflate(8.0, 8.0),
+                    _ => rect,
+                };
+                tree.push_rect(rect);
+            }
+        }
+        tree.build(|r| r.center());
+        tree.remove_rect(RectId(30));
+
+        let pairs = tree.overlapping_pairs();
+        assert!(pairs.iter().all(|(a, b)| a < b));
+        let expected = brute_force(&tree);
+        assert!(!expected.is_empty());
+        assert_eq!(sorted(pairs), expected);
+
+        let mut next = random(19);
+        tree.clear();
+        for _ in 0..300 {
+            let (x, y) = (next() * 500.0, next() * 500.0);
+            let (w, h) = (next() * 40.0, next() * 40.0);
+            tree.push_rect(Rect::new(x, y, x + w, y + h));
+        }
+        tree.build(|r| r.center());
+        assert_eq!(
+            sorted(tree.overlapping_pairs()),
+            brute_force(&tree)
+        );
+
+        // Early exit.
+        let mut visits = 0;
+        assert!(tree.try_visit_overlapping_pairs(|_, _| {
+            visits += 1;
+            ControlFlow::Break(())
+        }));
+        assert_eq!(visits, 1);
+    }
+
+    #[test]
+    fn test_max_leaf_rects() {
+        let mut next = random(23);
+        let mut plain = Spatree::new();
+        let mut bucketed = Spatree::new();
+        bucketed.set_max_leaf_rects(8);
+        assert_eq!(plain.max_leaf_rects(), 1);
+        assert_eq!(bucketed.max_leaf_rects(), 8);
+
+        for _ in 0..2000 {
+            let (x, y) = (next() * 1000.0, next() * 1000.0);
+            let (w, h) = (next() * 8.0, next() * 8.0);
+            let rect = Rect::new(x, y, x + w, y + h);
+            plain.push_rect(rect);
+            bucketed.push_rect(rect);
+        }
+        for tree in [&mut plain, &mut bucketed] {
+            tree.build(|r| r.center());
+            tree.remove_rect(RectId(11));
+        }
+
+        // Buckets replace the lower levels of the hierarchy.
+        let depth = |tree: &Spatree| {
+            (0..tree.nodes().len())
+                .map(|mut idx| {
+                    let mut depth = 0;
+                    while let Some(parent) = tree.parent_of(idx) {
+                        idx = parent;
+                        depth += 1;
+                    }
+                    depth
+                })
+                .max()
+                .unwrap()
+        };
+        assert_eq!(plain.nodes().len(), 1999);
+        assert!(bucketed.nodes().len() * 4 < plain.nodes().len());
+        assert!(depth(&bucketed) < depth(&plain));
+
+        // Every rect is held exactly once.
+        let mut held = bucketed
+            .nodes()
+            .iter()
+            .flat_map(|node| node.children)
+            .flat_map(|child| bucketed.child_rects(child))
+            .collect::<Vec<_>>();
+        held.sort();
+        assert_eq!(held, (0..2000).map(RectId).collect::<Vec<_>>());
+        for id in
+            held.into_iter().filter(|id| !bucketed.is_removed(*id))
+        {
+            let node =
+                bucketed.get_node(bucketed.leaf_node_of(id).unwrap());
+            assert!(
+                node.unwrap()
+                    .rect
+                    .contains_rect(bucketed.rects()[*id])
+            );
+        }
+
+        let sorted = |mut ids: Vec<RectId>| {
+            ids.sort();
+            ids
+        };
+        for _ in 0..50 {
+            let point = Point::new(next() * 1000.0, next() * 1000.0);
+            let rect = Rect::from_origin_size(point, (40.0, 40.0));
+            let end =
+                point + Vec2::new(next() * 200.0, next() * 200.0);
+
+            for (a, b) in [
+                (
+                    plain.query_point(point),
+                    bucketed.query_point(point),
+                ),
+                (plain.query_rect(rect), bucketed.query_rect(rect)),
+                (
+                    plain.iter_query_rect(rect).collect(),
+                    bucketed.iter_query_rect(rect).collect(),
+                ),
+                (
+                    plain.query_segment(point, end),
+                    bucketed.query_segment(point, end),
+                ),
+            ] {
+                assert_eq!(sorted(a), sorted(b));
+            }
+            assert_eq!(
+                plain.query_knn(point, 5),
+                bucketed.query_knn(point, 5)
+            );
+            assert_eq!(
+                plain.query_nearest(point),
+                bucketed.query_nearest(point)
+            );
+            assert_eq!(
+                plain.query_segment_first(point, end),
+                bucketed.query_segment_first(point, end)
+            );
+        }
+        let mut pairs = bucketed.overlapping_pairs();
+        pairs.sort();
+        assert!(!pairs.is_empty());
+        assert_eq!(pairs, {
+            let mut pairs = plain.overlapping_pairs();
+            pairs.sort();
+            pairs
+        });
+
+        // Updates and insertions keep the bounds of buckets.
+        let moved = Rect::new(2000.0, 2000.0, 2010.0, 2010.0);
+        bucketed.update_rect(RectId(5), moved);
+        assert_eq!(bucketed.query_rect(moved), [RectId(5)]);
+        let inserted = Rect::new(-50.0, -50.0, -40.0, -40.0);
+        let id = bucketed.insert_built(inserted);
+        assert_eq!(bucketed.query_rect(inserted), [id]);
+        assert_eq!(
+            bucketed.query_rect_containing(moved),
+            [RectId(5)]
+        );
+
+        // Few rects fit into a single bucket under the root.
+        let mut tree = Spatree::new();
+        tree.set_max_leaf_rects(4);
+        for i in 0..3 {
+            let offset = i as f64 * 20.0;
+            tree.push_rect(Rect::new(
+                offset,
+                0.0,
+                offset + 10.0,
+                10.0,
+            ));
+        }
+        tree.build(|r| r.center());
+        assert_eq!(tree.nodes().len(), 1);
+        assert_eq!(
+            tree.nodes()[0].children,
+            [NodeId::Bucket { start: 0, len: 3 }, NodeId::Invalid]
+        );
+        assert_eq!(
+            tree.query_point(Point::new(25.0, 5.0)),
+            [RectId(1)]
+        );
+        assert_eq!(
+            tree.nodes()[0].rect,
+            Rect::new(0.0, 0.0, 50.0, 10.0)
+        );
+    }
+
+    #[test]
+    fn test_query_with() {
+        let mut tree = Spatree::new();
+        for i in 0..100 {
+            let offset = i as f64;
+            tree.push_rect(Rect::new(
+                offset,
+                offset,
+                100.0 + offset,
+                100.0 + offset,
+            ));
+        }
+        tree.build(|r| r.center());
+
+        let mut scratch = QueryScratch::new();
+        // Number of nodes and leaves tested.
+        let tests = core::cell::Cell::new(0);
+        let hit_condition = |rect: &Rect, point: &Point| {
+            tests.set(tests.get() + 1);
+            rect.contains(*point)
+        };
+        let point = Point::new(99.5, 99.5);
+
+        let mut hits = vec![];
+        let aborted = tree.query_with(
+            point,
+            hit_condition,
+            |id| {
+                hits.push(id);
+                ControlFlow::Continue(())
+            },
+            &mut scratch,
+        );
+        assert!(!aborted);
+        assert_eq!(hits, tree.query_point(point));
+        assert_eq!(hits.len(), 100);
+        let full_tests = tests.replace(0);
+
+        // Early exit after the first hit, reusing the scratch.
+        let mut visits = 0;
+        let aborted = tree.query_with(
+            point,
+            hit_condition,
+            |_| {
+                visits += 1;
+                ControlFlow::Break(())
+            },
+            &mut scratch,
+        );
+        assert!(aborted);
+        assert_eq!(visits, 1);
+        assert!(tests.get() < full_tests / 4);
+    }
+
+    #[test]
+    fn test_query_point_by() {
+        let mut tree = Spatree::new();
+
+        let id0 = tree.push_rect(Rect::new(0.0, 0.0, 100.0, 100.0));
+        let id1 = tree.push_rect(Rect::new(0.0, 0.0, 50.0, 50.0));
+        let id2 = tree.push_rect(Rect::new(0.0, 0.0, 10.0, 10.0));
+
+        tree.build(|r| r.center());
+
+        // Z-index that does not follow the insertion order.
+        let z_index = |id: RectId| match id {
+            id if id == id0 => 2,
+            id if id == id1 => 0,
+            _ => 1,
+        };
+
+        // 1. Point hits all 3, `id0` is on top.
+        let hit = tree.query_point_by(Point::new(5.0, 5.0), z_index);
+        assert_eq!(hit, Some(id0));
+
+        // 2. Only the smaller rects are compared.
+        let q = Rect::new(2.0, 2.0, 8.0, 8.0);
+        let hit = tree.query_rect_by(q, |id| {
+            if id == id0 { -1 } else { z_index(id) }
+        });
+        assert_eq!(hit, Some(id2));
+
+        // 3. Complete miss.
+        let hit =
+            tree.query_point_by(Point::new(150.0, 150.0), z_index);
+        assert!(hit.is_none());
+
+        // 4. Ties are broken by the smallest id, whatever the
+        // traversal order.
+        for point_from_rect in [
+            |r: &Rect| r.center(),
+            |r: &Rect| Point::new(r.x1, r.y1),
+            |r: &Rect| Point::new(-r.x1, -r.y1),
+        ] {
+            tree.build(point_from_rect);
+            let p = Point::new(5.0, 5.0);
+            assert_eq!(tree.query_point_by(p, |_| 0), Some(id0));
+            let hit = tree.query_point_by(p, |id| id != id0);
+            assert_eq!(hit, Some(id1));
+        }
+    }
+
+    #[test]
+    fn test_non_finite_rects() {
+        let mut tree = Spatree::new();
+        let id0 = tree.push_rect(Rect::new(0.0, 0.0, 10.0, 10.0));
+        let nan = tree.push_rect(Rect::new(f64::NAN, 0.0, 5.0, 5.0));
+        let id1 = tree.push_rect(Rect::new(20.0, 20.0, 30.0, 30.0));
+        let inf =
+            tree.push_rect(Rect::new(0.0, 0.0, f64::INFINITY, 5.0));
+
+        assert_eq!(tree.non_finite_count(), 2);
+        assert_eq!(
+            tree.try_push_rect(Rect::new(0.0, f64::NAN, 1.0, 1.0)),
+            None
+        );
+        assert_eq!(tree.non_finite_count(), 2);
+        assert_eq!(
+            *tree.global_bound(),
+            Rect::new(0.0, 0.0, 30.0, 30.0)
+        );
+
+        tree.build(|r| r.center());
+        assert_eq!(tree.leaf_node_of(nan), None);
+        assert_eq!(tree.leaf_node_of(inf), None);
+
+        assert_eq!(tree.query_point(Point::new(5.0, 5.0)), [id0]);
+        assert_eq!(tree.query_point(Point::new(25.0, 25.0)), [id1]);
+
+        let mut hits =
+            tree.query_rect(Rect::new(0.0, 0.0, 30.0, 30.0));
+        hits.sort();
+        assert_eq!(hits, [id0, id1]);
+
+        // A single finite rect is still hit.
+        let mut tree = Spatree::new();
+        tree.push_rect(Rect::new(f64::NAN, 0.0, 5.0, 5.0));
+        let id = tree.push_rect(Rect::new(0.0, 0.0, 10.0, 10.0));
+        tree.build(|r| r.center());
+        assert_eq!(tree.query_point(Point::new(5.0, 5.0)), [id]);
+    }
+
+    #[test]
+    fn test_remove_rect() {
+        let mut tree = Spatree::new();
+        let ids = (0..4)
+            .map(|i| {
+                let x = i as f64 * 20.0;
+                tree.push_rect(Rect::new(x, 0.0, x + 10.0, 10.0))
+            })
+            .collect::<Vec<_>>();
+        tree.build(|r| r.center());
+        assert_eq!(tree.nodes.len(), 3);
+
+        let last = Rect::new(60.0, 0.0, 70.0, 10.0);
+        assert_eq!(tree.remove_rect(ids[3]), Some(last));
+        assert_eq!(tree.remove_rect(ids[3]), None);
+        assert!(tree.is_removed(ids[3]));
+        assert_eq!(tree.get_rect(ids[3]), None);
+        assert_eq!(tree.leaf_node_of(ids[3]), None);
+
+        // The removed rect is no longer hit, before any rebuild.
+        assert!(tree.query_point(Point::new(65.0, 5.0)).is_empty());
+        let mut hits = tree.query_rect(*tree.global_bound());
+        hits.sort();
+        assert_eq!(hits, ids[..3]);
+
+        // The global bound is stale until recomputed.
+        assert_eq!(
+            *tree.global_bound(),
+            Rect::new(0.0, 0.0, 70.0, 10.0)
+        );
+        tree.recompute_global_bound();
+        assert_eq!(
+            *tree.global_bound(),
+            Rect::new(0.0, 0.0, 50.0, 10.0)
+        );
+
+        tree.build(|r| r.center());
+        assert_eq!(tree.nodes.len(), 2);
+        assert!(tree.query_point(Point::new(65.0, 5.0)).is_empty());
+        assert_eq!(tree.query_point(Point::new(45.0, 5.0)), [ids[2]]);
+
+        // Removing down to a single rect.
+        tree.remove_rect(ids[0]);
+        tree.remove_rect(ids[1]);
+        tree.build(|r| r.center());
+        assert_eq!(tree.nodes.len(), 1);
+        assert_eq!(tree.query_point(Point::new(45.0, 5.0)), [ids[2]]);
+        assert!(tree.query_point(Point::new(5.0, 5.0)).is_empty());
+    }
+
+    #[test]
+    fn test_global_bound_far_from_origin() {
+        let rects = [
+            Rect::new(10000.0, 10000.0, 10040.0, 10020.0),
+            Rect::new(10060.0, 10050.0, 10100.0, 10100.0),
+            Rect::new(10020.0, 10070.0, 10030.0, 10080.0),
+        ];
+        let union = Rect::new(10000.0, 10000.0, 10100.0, 10100.0);
+
+        let mut tree = Spatree::new();
+        assert_eq!(*tree.global_bound(), Rect::ZERO);
+        for rect in rects {
+            tree.push_rect(rect);
+        }
+        // The origin is not part of the bound.
+        assert_eq!(*tree.global_bound(), union);
+        tree.build(|r| r.center());
+        assert_eq!(*tree.global_bound(), union);
+        assert_eq!(tree.nodes[0].rect, union);
+
+        tree.clear();
+        tree.push_rect(rects[2]);
+        assert_eq!(*tree.global_bound(), rects[2]);
+    }
+
+    #[test]
+    fn test_update_rect() {
+        let mut tree = Spatree::new();
+        let ids = (0..4)
+            .map(|i| {
+                let x = i as f64 * 20.0;
+                tree.push_rect(Rect::new(x, 0.0, x + 10.0, 10.0))
+            })
+            .collect::<Vec<_>>();
+        tree.build(|r| r.center());
+
+        let moved = Rect::new(100.0, 50.0, 110.0, 60.0);
+        assert_eq!(
+            tree.update_rect(ids[0], moved),
+            Some(Rect::new(0.0, 0.0, 10.0, 10.0))
+        );
+        assert_eq!(tree.get_rect(ids[0]), Some(&moved));
+        assert_eq!(
+            tree.nodes[0].rect,
+            Rect::new(20.0, 0.0, 110.0, 60.0)
+        );
+        assert!(tree.global_bound().contains_rect(moved));
+
+        // Hit at the new position only, before any rebuild.
+        assert!(tree.query_point(Point::new(5.0, 5.0)).is_empty());
+        assert_eq!(
+            tree.query_point(Point::new(105.0, 55.0)),
+            [ids[0]]
+        );
+
+        // Non-finite and removed rects are not replaced.
+        let nan = Rect::new(f64::NAN, 0.0, 1.0, 1.0);
+        assert_eq!(tree.update_rect(ids[1], nan), None);
+        tree.remove_rect(ids[2]);
+        assert_eq!(tree.update_rect(ids[2], moved), None);
+        assert_eq!(tree.update_rect(RectId(4), moved), None);
+    }
+
+    #[test]
+    fn test_update_rect_matches_rebuild() {
+        let mut next = random(0x2545_f491_4f6c_dd1d_u64);
 
-        // Link the current node to its parent if it's not the root.
-        if let Some(parent_idx) = parent_idx {
-            internal_nodes[parent_idx].children[child_slot] =
-                curr_node_id;
+        let mut tree = Spatree::new();
+        let ids = (0..1000)
+            .map(|_| {
+                let origin =
+                    Point::new(next() * 990.0, next() * 990.0);
+                let size = (next() * 10.0 + 1.0, next() * 10.0 + 1.0);
+                tree.push_rect(Rect::from_origin_size(origin, size))
+            })
+            .collect::<Vec<_>>();
+        tree.build(|r| r.center());
 
-            // If the current node is internal, set its parent index.
-            if let NodeId::Internal(curr_idx) = curr_node_id {
-                internal_nodes[curr_idx].parent = Some(parent_idx);
+        for _ in 0..4 {
+            for id in &ids {
+                let rect = *tree.get_rect(*id).unwrap();
+                let offset = Vec2::new(
+                    (next() - 0.5) * 8.0,
+                    (next() - 0.5) * 8.0,
+                );
+                tree.update_rect(*id, rect + offset);
+            }
+
+            let mut rebuilt = Spatree::new();
+            for id in &ids {
+                rebuilt.push_rect(*tree.get_rect(*id).unwrap());
+            }
+            rebuilt.build(|r| r.center());
+
+            for _ in 0..1000 {
+                let point =
+                    Point::new(next() * 1000.0, next() * 1000.0);
+                let mut hits = tree.query_point(point);
+                hits.sort();
+                let mut expected = rebuilt.query_point(point);
+                expected.sort();
+                assert_eq!(hits, expected);
             }
         }
     }
 
-    internal_nodes
-}
+    #[test]
+    fn test_refit() {
+        let mut tree = Spatree::new();
+        let ids = (0..4)
+            .map(|i| {
+                let x = i as f64 * 20.0;
+                tree.push_rect(Rect::new(x, 0.0, x + 10.0, 10.0))
+            })
+            .collect::<Vec<_>>();
+        tree.build(|r| r.center());
+        let index = tree.build_index(|r| r.origin());
+        // Root of 70x10 with two pairs of 30x10.
+        assert_eq!(tree.area_ratio(), 1300.0 / 700.0);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        // Swap the outermost rects.
+        let first = *tree.get_rect(ids[0]).unwrap();
+        let moved = *tree.get_rect(ids[3]).unwrap();
+        assert_eq!(tree.set_rect(ids[0], moved), Some(first));
+        tree.set_rect(ids[3], first);
+        // Stale bounds until refitted.
+        assert!(tree.query_point(moved.center()).is_empty());
+
+        // Both pairs now span 50x10.
+        let ratio = tree.refit();
+        assert_eq!(ratio, 1700.0 / 700.0);
+        assert_eq!(ratio, tree.area_ratio());
+        let mut hits = tree.query_point(moved.center());
+        hits.sort();
+        assert_eq!(hits, [ids[0]]);
+        assert_eq!(
+            tree.query_index_point(index, moved.center()),
+            [ids[0]]
+        );
+
+        assert_eq!(Spatree::new().refit(), 0.0);
+    }
 
     #[test]
-    fn test_empty_tree() {
+    fn test_refit_matches_rebuild() {
+        let mut next = random(0x9e37_79b9_7f4a_7c15_u64);
+
         let mut tree = Spatree::new();
+        let ids = (0..500)
+            .map(|_| {
+                let origin =
+                    Point::new(next() * 480.0, next() * 480.0);
+                let size = (next() * 20.0 + 1.0, next() * 20.0 + 1.0);
+                tree.push_rect(Rect::from_origin_size(origin, size))
+            })
+            .collect::<Vec<_>>();
         tree.build(|r| r.center());
+        let built_ratio = tree.area_ratio();
 
-        assert!(tree.rects.is_empty());
-        assert!(tree.nodes.is_empty());
-        assert_eq!(tree.global_bound().area(), 0.0);
+        for id in &ids {
+            let rect = *tree.get_rect(*id).unwrap();
+            let offset = Vec2::new(
+                (next() - 0.5) * 20.0,
+                (next() - 0.5) * 20.0,
+            );
+            tree.set_rect(*id, rect + offset);
+        }
+        let ratio = tree.refit();
+        assert!(ratio >= built_ratio);
 
-        let hits = tree.query_point(Point::new(10.0, 10.0));
-        assert!(hits.is_empty());
+        let mut rebuilt = Spatree::new();
+        for id in &ids {
+            rebuilt.push_rect(*tree.get_rect(*id).unwrap());
+        }
+        rebuilt.build(|r| r.center());
+
+        for _ in 0..500 {
+            let origin = Point::new(next() * 500.0, next() * 500.0);
+            let target = Rect::from_origin_size(origin, (5.0, 5.0));
+            for query in [
+                |tree: &Spatree, target: Rect| {
+                    tree.query_point(target.origin())
+                },
+                |tree: &Spatree, target: Rect| {
+                    tree.query_rect(target)
+                },
+            ] {
+                let mut hits = query(&tree, target);
+                hits.sort();
+                let mut expected = query(&rebuilt, target);
+                expected.sort();
+                assert_eq!(hits, expected);
+            }
+        }
     }
 
     #[test]
-    fn test_single_item_tree() {
+    fn test_insert_built() {
         let mut tree = Spatree::new();
-        let r1 = Rect::new(0.0, 0.0, 10.0, 10.0);
-        let id = tree.push_rect(r1);
+        let a = tree.insert_built(Rect::new(0.0, 0.0, 10.0, 10.0));
+        // The first rect creates a root.
+        assert_eq!(tree.nodes.len(), 1);
+        assert!(tree.is_incrementally_modified());
+
+        let b = tree.insert_built(Rect::new(20.0, 0.0, 30.0, 10.0));
+        // Fills the empty slot of the root.
+        assert_eq!(tree.nodes.len(), 1);
+        let c = tree.insert_built(Rect::new(20.0, 20.0, 30.0, 30.0));
+        // Paired with the closest leaf.
+        assert_eq!(tree.nodes.len(), 2);
+        assert_eq!(tree.leaf_node_of(b), tree.leaf_node_of(c));
+        assert_eq!(tree.leaf_node_of(a), Some(0));
+        assert_eq!(
+            tree.nodes[0].rect,
+            Rect::new(0.0, 0.0, 30.0, 30.0)
+        );
+        assert_eq!(tree.query_point(Point::new(25.0, 25.0)), [c]);
+
+        let nan =
+            tree.insert_built(Rect::new(f64::NAN, 0.0, 1.0, 1.0));
+        assert_eq!(tree.leaf_node_of(nan), None);
+        assert_eq!(tree.nodes.len(), 2);
 
         tree.build(|r| r.center());
+        assert!(!tree.is_incrementally_modified());
+    }
 
-        // Single item means N-1 = 0 internal nodes.
-        assert!(tree.nodes.is_empty());
+    #[test]
+    fn test_insert_built_queries() {
+        let mut next = random(0x4f1b_bcdc_bfa5_3e0b);
+        let mut random_rect = || {
+            let origin = Point::new(next() * 480.0, next() * 480.0);
+            let size = (next() * 20.0 + 1.0, next() * 20.0 + 1.0);
+            Rect::from_origin_size(origin, size)
+        };
 
-        let hits = tree.query_point(Point::new(5.0, 5.0));
-        assert_eq!(hits.len(), 1);
-        assert_eq!(hits[0], id);
+        let mut tree = Spatree::new();
+        for _ in 0..100 {
+            tree.push_rect(random_rect());
+        }
+        tree.build(|r| r.center());
+        assert!(!tree.is_incrementally_modified());
+
+        let inserted = (0..10)
+            .map(|_| tree.insert_built(random_rect()))
+            .collect::<Vec<_>>();
+        assert!(tree.is_incrementally_modified());
+        assert_eq!(tree.nodes.len(), 109);
+
+        let mut rebuilt = Spatree::new();
+        for rect in &tree.rects {
+            rebuilt.push_rect(*rect);
+        }
+        rebuilt.build(|r| r.center());
+
+        // Every rect is hit, including the inserted ones.
+        for (index, rect) in tree.rects.iter().enumerate() {
+            assert!(
+                tree.query_point(rect.center())
+                    .contains(&RectId(index))
+            );
+            assert!(tree.query_rect(*rect).contains(&RectId(index)));
+        }
+        for id in inserted {
+            assert!(tree.leaf_node_of(id).is_some());
+        }
+        assert_eq!(tree.query_rect(*tree.global_bound()).len(), 110);
+
+        for _ in 0..500 {
+            let rect = random_rect();
+            let mut hits = tree.query_rect(rect);
+            hits.sort();
+            let mut expected = rebuilt.query_rect(rect);
+            expected.sort();
+            assert_eq!(hits, expected);
+        }
     }
 
     #[test]
-    fn test_hierarchy_structure_and_bounds() {
+    fn test_clear() {
         let mut tree = Spatree::new();
+        for i in 0..8 {
+            let x = i as f64 * 20.0;
+            tree.push_rect(Rect::new(x, 0.0, x + 10.0, 10.0));
+        }
+        tree.push_rect(Rect::new(f64::NAN, 0.0, 5.0, 5.0));
+        tree.build(|r| r.center());
+        tree.build_index(|r| r.origin());
+        let capacity = tree.capacity();
 
-        // 4 corners of a 100x100 area.
-        // Top left.
-        let r1 = Rect::new(0.0, 0.0, 10.0, 10.0);
-        // Top right.
-        let r2 = Rect::new(90.0, 0.0, 100.0, 10.0);
-        // Bottom left.
-        let r3 = Rect::new(0.0, 90.0, 10.0, 100.0);
-        // Bottom right.
-        let r4 = Rect::new(90.0, 90.0, 100.0, 100.0);
+        tree.clear();
+        assert_eq!(tree.capacity(), capacity);
+        assert_eq!(*tree.global_bound(), Rect::ZERO);
+        assert_eq!(tree.non_finite_count(), 0);
+        assert!(tree.nodes.is_empty());
+        assert!(tree.indices.is_empty());
+        assert!(tree.query_point(Point::new(5.0, 5.0)).is_empty());
 
-        tree.push_rect(r1);
-        tree.push_rect(r2);
-        tree.push_rect(r3);
-        tree.push_rect(r4);
+        // Ids start over.
+        let id = tree.push_rect(Rect::new(40.0, 0.0, 50.0, 10.0));
+        assert_eq!(id, RectId(0));
+        tree.build(|r| r.center());
+        assert_eq!(tree.query_point(Point::new(45.0, 5.0)), [id]);
+        assert_eq!(tree.leaf_node_of(id), Some(0));
+    }
 
+    #[test]
+    fn test_query_transformed() {
+        let mut tree = Spatree::new();
+        let ids = (0..4)
+            .map(|i| {
+                let x = i as f64 * 20.0;
+                tree.push_rect(Rect::new(x, 0.0, x + 10.0, 10.0))
+            })
+            .collect::<Vec<_>>();
         tree.build(|r| r.center());
 
-        // N items = N-1 internal nodes.
-        assert_eq!(tree.nodes.len(), 3);
+        // Identity matches the plain queries.
+        for point in [Point::new(5.0, 5.0), Point::new(15.0, 5.0)] {
+            assert_eq!(
+                tree.query_point_transformed(point, Affine::IDENTITY),
+                tree.query_point(point)
+            );
+        }
+        let rect = Rect::new(5.0, 5.0, 25.0, 8.0);
+        let mut hits =
+            tree.query_rect_transformed(rect, Affine::IDENTITY);
+        hits.sort();
+        assert_eq!(hits, tree.query_rect(rect));
 
-        // Root is the first node generated in top-down.
-        let root = &tree.nodes[0];
-        let expected_union = r1.union(r2).union(r3).union(r4);
+        // View zoomed in 2x and panned by (100, 50).
+        let view =
+            Affine::translate((100.0, 50.0)) * Affine::scale(2.0);
+        let inv_view = view.inverse();
+        assert_eq!(
+            tree.query_point_transformed(
+                Point::new(150.0, 60.0),
+                inv_view
+            ),
+            [ids[1]]
+        );
+        let mut hits = tree.query_rect_transformed(
+            Rect::new(150.0, 50.0, 190.0, 60.0),
+            inv_view,
+        );
+        hits.sort();
+        assert_eq!(hits, [ids[1], ids[2]]);
 
-        assert_eq!(root.rect.x0, expected_union.x0);
-        assert_eq!(root.rect.y0, expected_union.y0);
-        assert_eq!(root.rect.x1, expected_union.x1);
-        assert_eq!(root.rect.y1, expected_union.y1);
+        // A diamond above the gap between the first two rects,
+        // whose bounding box overlaps both.
+        let inv_view = Affine::translate((15.0, 15.0))
+            * Affine::rotate(core::f64::consts::FRAC_PI_4);
+        let half = 4.0 * core::f64::consts::SQRT_2;
+        let diamond = Rect::new(-half, -half, half, half);
+        let bbox = inv_view.transform_rect_bbox(diamond);
+        assert!(bbox.overlaps(Rect::new(0.0, 0.0, 10.0, 10.0)));
+        assert!(bbox.overlaps(Rect::new(20.0, 0.0, 30.0, 10.0)));
+        assert!(
+            tree.query_rect_transformed(diamond, inv_view).is_empty()
+        );
+        // Moving it down reaches both.
+        let inv_view = Affine::translate((0.0, -3.0)) * inv_view;
+        let mut hits = tree.query_rect_transformed(diamond, inv_view);
+        hits.sort();
+        assert_eq!(hits, [ids[0], ids[1]]);
     }
 
     #[test]
-    fn test_query_point() {
+    fn test_morton_precision() {
+        let mut next = random(0x1234_5678_9abc_def1);
+
+        // A dense cluster within a huge extent, which collapses onto
+        // few codes with 16 bits per axis.
         let mut tree = Spatree::new();
-        let r1 = Rect::new(10.0, 10.0, 30.0, 30.0);
-        let r2 = Rect::new(20.0, 20.0, 40.0, 40.0);
+        for _ in 0..10_000 {
+            let origin = Point::new(next() * 1000.0, next() * 1000.0);
+            tree.push_rect(Rect::from_origin_size(
+                origin,
+                (4.0, 4.0),
+            ));
+        }
+        tree.push_rect(Rect::new(1e7, 1e7, 1e7 + 4.0, 1e7 + 4.0));
+        let points = (0..100)
+            .map(|_| Point::new(next() * 1000.0, next() * 1000.0))
+            .collect::<Vec<_>>();
 
-        let id1 = tree.push_rect(r1);
-        let id2 = tree.push_rect(r2);
+        // Number of nodes and leaves tested by all queries.
+        let visits = |tree: &Spatree| {
+            let visits = core::cell::Cell::new(0);
+            for point in &points {
+                tree.query(*point, |rect, point| {
+                    visits.set(visits.get() + 1);
+                    rect.contains(*point)
+                });
+            }
+            visits.get()
+        };
 
+        assert_eq!(tree.morton_precision(), MortonPrecision::Bits32);
         tree.build(|r| r.center());
+        let visits_32 = visits(&tree);
+        let hits_32 = points
+            .iter()
+            .map(|point| {
+                let mut hits = tree.query_point(*point);
+                hits.sort();
+                hits
+            })
+            .collect::<Vec<_>>();
 
-        // Point inside intersection.
-        let hits = tree.query_point(Point::new(25.0, 25.0));
-        assert_eq!(hits.len(), 2);
-        assert!(hits.contains(&id1));
-        assert!(hits.contains(&id2));
+        tree.set_morton_precision(MortonPrecision::Bits64);
+        tree.build(|r| r.center());
+        let visits_64 = visits(&tree);
+
+        assert!(
+            visits_64 * 4 < visits_32,
+            "{visits_64} visits with 64 bits vs {visits_32} with 32 bits"
+        );
+        for (point, expected) in points.iter().zip(hits_32) {
+            let mut hits = tree.query_point(*point);
+            hits.sort();
+            assert_eq!(hits, expected);
+        }
     }
 
     #[test]
-    fn test_query_rect() {
+    fn test_build_shrinks_bound() {
         let mut tree = Spatree::new();
+        let ids = (0..4)
+            .map(|i| {
+                let x = i as f64 * 20.0;
+                tree.push_rect(Rect::new(x, 0.0, x + 10.0, 10.0))
+            })
+            .collect::<Vec<_>>();
+        tree.build(|r| r.center());
 
-        // Define 3 distinct areas.
-        // Top left.
-        let r1 = Rect::new(0.0, 0.0, 10.0, 10.0);
-        // Top right.
-        let r2 = Rect::new(20.0, 0.0, 30.0, 10.0);
-        // Bottom wide strip.
-        let r3 = Rect::new(0.0, 20.0, 30.0, 30.0);
+        // Removing and moving outermost rects.
+        tree.remove_rect(ids[3]);
+        tree.set_rect(ids[2], Rect::new(40.0, 0.0, 50.0, 50.0));
+        tree.set_rect(ids[2], Rect::new(40.0, 0.0, 50.0, 20.0));
+        assert_eq!(
+            *tree.global_bound(),
+            Rect::new(0.0, 0.0, 70.0, 50.0)
+        );
 
-        let id1 = tree.push_rect(r1);
-        let id2 = tree.push_rect(r2);
-        let id3 = tree.push_rect(r3);
+        assert_eq!(
+            tree.build_counting_out_of_bounds(|r| r.center()),
+            0
+        );
+        assert_eq!(
+            *tree.global_bound(),
+            Rect::new(0.0, 0.0, 50.0, 20.0)
+        );
+        assert_eq!(tree.nodes[0].rect, *tree.global_bound());
+    }
 
-        tree.build(|r| r.center());
+    #[test]
+    fn test_remove_non_finite_rect() {
+        let mut tree = Spatree::new();
+        let nan = tree.push_rect(Rect::new(f64::NAN, 0.0, 5.0, 5.0));
+        assert_eq!(tree.non_finite_count(), 1);
+        assert!(tree.remove_rect(nan).is_some());
+        assert_eq!(tree.non_finite_count(), 0);
+        assert_eq!(tree.remove_rect(RectId(1)), None);
+    }
 
-        // 1. Overlaps only `r1`.
-        let q1 = Rect::new(-5.0, -5.0, 5.0, 5.0);
-        let hits = tree.query_rect(q1);
-        assert_eq!(hits.len(), 1);
-        assert!(hits.contains(&id1));
+    #[test]
+    fn test_build_negative_bound() {
+        let mut tree = Spatree::new();
 
-        // 2. Overlaps `r1` and `r2` but not `r3`.
-        let q2 = Rect::new(5.0, 2.0, 25.0, 8.0);
-        let hits = tree.query_rect(q2);
-        assert_eq!(hits.len(), 2);
-        assert!(hits.contains(&id1));
-        assert!(hits.contains(&id2));
-        assert!(!hits.contains(&id3));
+        // 4 corners of a 100x100 area in the negative quadrant,
+        // pushed out of Z-order.
+        let top_left = Rect::new(-100.0, -100.0, -90.0, -90.0);
+        let top_right = Rect::new(-10.0, -100.0, 0.0, -90.0);
+        let bottom_left = Rect::new(-100.0, -10.0, -90.0, 0.0);
+        let bottom_right = Rect::new(-10.0, -10.0, 0.0, 0.0);
+        tree.push_rect(top_left);
+        let br = tree.push_rect(bottom_right);
+        tree.push_rect(top_right);
+        tree.push_rect(bottom_left);
 
-        // 3. Overlaps all 3.
-        let q3 = Rect::new(5.0, 5.0, 25.0, 25.0);
-        let hits = tree.query_rect(q3);
-        assert_eq!(hits.len(), 3);
-        assert!(hits.contains(&id1));
-        assert!(hits.contains(&id2));
-        assert!(hits.contains(&id3));
+        assert_eq!(
+            tree.build_counting_out_of_bounds(|r| r.center()),
+            0
+        );
+        assert_eq!(tree.nodes.len(), 3);
 
-        // 4. Complete miss
-        let q4 = Rect::new(100.0, 100.0, 110.0, 110.0);
-        let hits = tree.query_rect(q4);
-        assert!(hits.is_empty());
+        // Each half of the tree holds a spatially coherent pair.
+        let [left, right] = tree.nodes[0].children.map(|child| {
+            let NodeId::Internal(idx) = child else {
+                panic!("expected a balanced tree");
+            };
+            tree.nodes[idx].rect
+        });
+        assert!(!left.overlaps(right));
+        let mut halves = [left, right];
+        halves.sort_by(|a, b| a.y0.total_cmp(&b.y0));
+        assert_eq!(halves[0], top_left.union(top_right));
+        assert_eq!(halves[1], bottom_left.union(bottom_right));
+
+        assert_eq!(tree.query_point(Point::new(-5.0, -5.0)), [br]);
     }
 
-    /// Largest index win (simulating a stack/z-order).
-    #[inline(always)]
-    fn stack_conflict_resolution(a: RectId, b: RectId) -> RectId {
-        if a > b { a } else { b }
+    #[test]
+    fn test_build_straddling_origin() {
+        let mut tree = Spatree::new();
+
+        // 4x4 grid centered on the origin, pushed in reverse.
+        let mut ids = vec![];
+        for i in (0..16).rev() {
+            let x = (i % 4) as f64 * 20.0 - 40.0;
+            let y = (i / 4) as f64 * 20.0 - 40.0;
+            let rect = Rect::new(x, y, x + 10.0, y + 10.0);
+            ids.push((tree.push_rect(rect), rect));
+        }
+        assert_eq!(
+            *tree.global_bound(),
+            Rect::new(-40.0, -40.0, 30.0, 30.0)
+        );
+        assert_eq!(
+            tree.build_counting_out_of_bounds(|r| r.center()),
+            0
+        );
+
+        // Every node partitions its rects, no child spans the whole
+        // bound of its parent.
+        for node in &tree.nodes {
+            for child in node.children {
+                let rect = match child {
+                    NodeId::Internal(idx) => tree.nodes[idx].rect,
+                    NodeId::Leaf(idx) => tree.rects[idx],
+                    NodeId::Bucket { .. } | NodeId::Invalid => {
+                        panic!("expected a full tree")
+                    }
+                };
+                assert_ne!(rect, node.rect);
+            }
+        }
+
+        for (id, rect) in ids {
+            assert_eq!(tree.query_point(rect.center()), [id]);
+        }
     }
 
     #[test]
-    fn test_query_point_single() {
+    fn test_build_out_of_bounds() {
         let mut tree = Spatree::new();
+        for i in 0..4 {
+            let x = i as f64 * 20.0;
+            tree.push_rect(Rect::new(x, 0.0, x + 10.0, 10.0));
+        }
 
-        // Largest (lowest).
-        let id0 = tree.push_rect(Rect::new(0.0, 0.0, 100.0, 100.0));
-        // Middle (center).
-        let id1 = tree.push_rect(Rect::new(0.0, 0.0, 50.0, 50.0));
-        // Smallest (top).
-        let id2 = tree.push_rect(Rect::new(0.0, 0.0, 10.0, 10.0));
+        // All points are within the global bound.
+        assert_eq!(
+            tree.build_counting_out_of_bounds(|r| r.center()),
+            0
+        );
+        assert_eq!(
+            tree.build_counting_out_of_bounds(|r| Point::new(
+                r.x1, r.y1
+            )),
+            0
+        );
 
-        assert!(id0 < id1 && id1 < id2, "Ids should be incremented!");
+        // Anchoring past the right edge pushes the last rect out.
+        let out_of_bounds = tree.build_counting_out_of_bounds(|r| {
+            Point::new(r.x1 + 5.0, r.center().y)
+        });
+        assert_eq!(out_of_bounds, 1);
+
+        let out_of_bounds = tree
+            .build_counting_out_of_bounds(|r| Point::new(r.x0, -1.0));
+        assert_eq!(out_of_bounds, 4);
+    }
+
+    #[test]
+    fn test_build_index() {
+        let mut tree = Spatree::new();
+        let rects = [
+            Rect::new(0.0, 0.0, 40.0, 40.0),
+            Rect::new(30.0, 30.0, 60.0, 60.0),
+            Rect::new(70.0, 0.0, 100.0, 30.0),
+            Rect::new(0.0, 70.0, 30.0, 100.0),
+        ];
+        for rect in rects {
+            tree.push_rect(rect);
+        }
 
         tree.build(|r| r.center());
+        let top_left = tree.build_index(|r| r.origin());
+        let bottom_right =
+            tree.build_index(|r| Point::new(r.x1, r.y1));
 
-        // 1. Point hits all 3.
-        let p1 = Point::new(5.0, 5.0);
-        let hit =
-            tree.query_point_single(p1, stack_conflict_resolution);
-        assert_eq!(hit, Some(id2));
+        for point in [
+            Point::new(35.0, 35.0),
+            Point::new(80.0, 10.0),
+            Point::new(10.0, 80.0),
+            Point::new(65.0, 65.0),
+        ] {
+            let mut expected = tree.query_point(point);
+            expected.sort();
 
-        // 2. Point hits `id0` and `id1`, but misses the tiny `id2`.
-        let p2 = Point::new(20.0, 20.0);
-        let hit =
-            tree.query_point_single(p2, stack_conflict_resolution);
-        assert_eq!(hit, Some(id1));
+            for index in [top_left, bottom_right] {
+                let mut hits = tree.query_index_point(index, point);
+                hits.sort();
+                assert_eq!(hits, expected);
+            }
+        }
 
-        // 3. Point hits only the large base `id0`.
-        let p3 = Point::new(75.0, 75.0);
-        let hit =
-            tree.query_point_single(p3, stack_conflict_resolution);
-        assert_eq!(hit, Some(id0));
+        // The overlapping pair is hit by both indices.
+        let mut hits =
+            tree.query_index_point(top_left, Point::new(35.0, 35.0));
+        hits.sort();
+        assert_eq!(hits, [RectId(0), RectId(1)]);
 
-        // 4. Complete miss.
-        let p4 = Point::new(150.0, 150.0);
-        let hit =
-            tree.query_point_single(p4, stack_conflict_resolution);
-        assert!(hit.is_none());
+        // Invalid indices hit nothing.
+        let invalid = IndexId(2);
+        assert!(
+            tree.query_index_point(invalid, Point::ZERO).is_empty()
+        );
     }
 
     #[test]
-    fn test_query_rect_single() {
+    fn test_rebuild_index() {
         let mut tree = Spatree::new();
+        let a = tree.push_rect(Rect::new(0.0, 0.0, 10.0, 10.0));
+        tree.build(|r| r.center());
+        let index = tree.build_index(|r| r.origin());
 
-        // Largest (lowest).
-        let id0 = tree.push_rect(Rect::new(0.0, 0.0, 100.0, 100.0));
-        // Middle (center).
-        let id1 = tree.push_rect(Rect::new(0.0, 0.0, 50.0, 50.0));
-        // Smallest (top).
-        let id2 = tree.push_rect(Rect::new(0.0, 0.0, 10.0, 10.0));
+        // Indices are not updated incrementally.
+        let b = tree.insert_built(Rect::new(20.0, 0.0, 30.0, 10.0));
+        let point = Point::new(25.0, 5.0);
+        assert_eq!(tree.query_point(point), [b]);
+        assert!(tree.query_index_point(index, point).is_empty());
 
-        tree.build(|r| r.center());
+        assert!(tree.rebuild_index(index, |r| r.origin()));
+        assert_eq!(tree.query_index_point(index, point), [b]);
+        assert_eq!(
+            tree.query_index_point(index, Point::new(5.0, 5.0)),
+            [a]
+        );
 
-        // 1. Overlaps all 3.
-        let q1 = Rect::new(2.0, 2.0, 8.0, 8.0);
-        let hit =
-            tree.query_rect_single(q1, stack_conflict_resolution);
-        assert_eq!(hit, Some(id2));
+        assert!(tree.remove_index(index));
+        assert!(!tree.remove_index(index));
+        assert!(!tree.rebuild_index(index, |r| r.origin()));
+        assert!(tree.query_index_point(index, point).is_empty());
 
-        // 2. Overlaps `id0` and `id1`, but misses `id2`.
-        let q2 = Rect::new(15.0, 15.0, 25.0, 25.0);
-        let hit =
-            tree.query_rect_single(q2, stack_conflict_resolution);
-        assert_eq!(hit, Some(id1));
+        // The freed slot is reused.
+        assert_eq!(tree.build_index(|r| r.center()), index);
+        assert_eq!(tree.indices.len(), 1);
+    }
 
-        // 3. Overlaps only the largest base `id0`.
-        let q3 = Rect::new(60.0, 60.0, 70.0, 70.0);
-        let hit =
-            tree.query_rect_single(q3, stack_conflict_resolution);
-        assert_eq!(hit, Some(id0));
+    #[test]
+    fn test_generate_hierarchy_generic() {
+        /// A 1D bound.
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        struct Interval {
+            min: f64,
+            max: f64,
+        }
 
-        // 4. Complete miss.
-        let q4 = Rect::new(200.0, 200.0, 210.0, 210.0);
-        let hit =
-            tree.query_rect_single(q4, stack_conflict_resolution);
-        assert!(hit.is_none());
+        let intervals = [
+            Interval {
+                min: 10.0,
+                max: 11.0,
+            },
+            Interval { min: 0.0, max: 1.0 },
+            Interval {
+                min: 12.0,
+                max: 13.0,
+            },
+            Interval { min: 2.0, max: 3.0 },
+        ];
+
+        let mut codes = intervals
+            .iter()
+            .enumerate()
+            .map(|(index, interval)| MortonCode {
+                code: interval.min as u32,
+                index,
+            })
+            .collect::<Vec<_>>();
+        codes.sort_unstable();
+
+        let nodes = generate_hierarchy_generic(
+            &codes,
+            |index| intervals[index],
+            |a, b| Interval {
+                min: a.min.min(b.min),
+                max: a.max.max(b.max),
+            },
+        );
+
+        assert_eq!(nodes.len(), 3);
+        // The root encloses everything.
+        assert!(nodes[0].parent.is_none());
+        assert_eq!(
+            nodes[0].bound,
+            Interval {
+                min: 0.0,
+                max: 13.0
+            }
+        );
+
+        // Nearby intervals are clustered together.
+        let mut child_bounds = nodes[0].children.map(|child| {
+            let NodeId::Internal(idx) = child else {
+                panic!("Expected an internal node.");
+            };
+            assert_eq!(nodes[idx].parent, Some(0));
+            nodes[idx].bound
+        });
+        child_bounds.sort_by(|a, b| a.min.total_cmp(&b.min));
+        assert_eq!(
+            child_bounds,
+            [
+                Interval { min: 0.0, max: 3.0 },
+                Interval {
+                    min: 10.0,
+                    max: 13.0
+                },
+            ]
+        );
     }
 }
@@ -2,19 +2,76 @@
 ///
 /// This struct is optimized for ordering based only on
 /// [`Self::code`] without any consideration for [`Self::index`].
+///
+/// Codes are [`u32`] by default, or [`u64`] for
+/// [`MortonPrecision::Bits64`], see [`MortonBits`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct MortonCode {
-    pub code: u32,
+pub struct MortonCode<C = u32> {
+    pub code: C,
     pub index: usize,
 }
 
-impl Ord for MortonCode {
+/// Number of bits of the Morton codes used to order rects.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MortonPrecision {
+    /// 16 bits per axis via [`morton_2d_f64()`], which is cheaper
+    /// to encode, but points closer than 1/65535th of the bound
+    /// collapse onto the same code.
+    #[default]
+    Bits32,
+    /// 32 bits per axis via [`morton_2d_u64()`], for large or
+    /// fine-grained scenes.
+    Bits64,
+}
+
+/// Integer type of a [`MortonCode`], one per [`MortonPrecision`].
+pub trait MortonBits: Copy + Ord {
+    /// Encodes a point normalized within the `0..=1` range.
+    fn encode(x: f64, y: f64) -> Self;
+
+    /// See [`find_split()`].
+    fn find_split(
+        morton_codes: &[MortonCode<Self>],
+        first: usize,
+        last: usize,
+    ) -> usize;
+}
+
+impl MortonBits for u32 {
+    fn encode(x: f64, y: f64) -> Self {
+        morton_2d_f64(x, y)
+    }
+
+    fn find_split(
+        morton_codes: &[MortonCode<Self>],
+        first: usize,
+        last: usize,
+    ) -> usize {
+        find_split(morton_codes, first, last)
+    }
+}
+
+impl MortonBits for u64 {
+    fn encode(x: f64, y: f64) -> Self {
+        morton_2d_u64(x, y)
+    }
+
+    fn find_split(
+        morton_codes: &[MortonCode<Self>],
+        first: usize,
+        last: usize,
+    ) -> usize {
+        find_split_64(morton_codes, first, last)
+    }
+}
+
+impl<C: Ord> Ord for MortonCode<C> {
     fn cmp(&self, other: &Self) -> core::cmp::Ordering {
         self.code.cmp(&other.code)
     }
 }
 
-impl PartialOrd for MortonCode {
+impl<C: Ord> PartialOrd for MortonCode<C> {
     fn partial_cmp(
         &self,
         other: &Self,
@@ -33,6 +90,23 @@ pub fn morton_2d_f64(x: f64, y: f64) -> u32 {
     morton_2d(x, y)
 }
 
+/// Same as [`morton_2d_f64()`], but with 32 bits per axis.
+pub fn morton_2d_u64(x: f64, y: f64) -> u64 {
+    const MAX: f64 = u32::MAX as f64;
+    let x = (x.clamp(0.0, 1.0) * MAX) as u32;
+    let y = (y.clamp(0.0, 1.0) * MAX) as u32;
+
+    fn expand(mut v: u64) -> u64 {
+        v = (v | (v << 16)) & 0x0000FFFF0000FFFF;
+        v = (v | (v << 8)) & 0x00FF00FF00FF00FF;
+        v = (v | (v << 4)) & 0x0F0F0F0F0F0F0F0F;
+        v = (v | (v << 2)) & 0x3333333333333333;
+        v = (v | (v << 1)) & 0x5555555555555555;
+        v
+    }
+    expand(x as u64) | (expand(y as u64) << 1)
+}
+
 /// Combine 2 [`u16`] integers into a [`u32`] morton code.
 pub fn morton_2d(x: u16, y: u16) -> u32 {
     fn expand(mut v: u32) -> u32 {
@@ -49,11 +123,18 @@ pub fn morton_2d(x: u16, y: u16) -> u32 {
 ///
 /// Locate the position where the shared bit prefix changes and
 /// return the index used to divide the range into two clusters.
+///
+/// The range `first..=last` must hold at least two codes, i.e.
+/// `first < last < morton_codes.len()`, otherwise this panics or
+/// returns a meaningless split. See [`checked_find_split()`] for a
+/// variant that validates the range.
 pub const fn find_split(
     morton_codes: &[MortonCode],
     first: usize,
     last: usize,
 ) -> usize {
+    debug_assert!(first < last && last < morton_codes.len());
+
     let first_code = morton_codes[first].code;
     let last_code = morton_codes[last].code;
     // Split the range in the middle for identical Morton codes.
@@ -91,12 +172,68 @@ pub const fn find_split(
     split
 }
 
+/// Same as [`find_split()`], but returns [`None`] instead of
+/// panicking if the range does not hold at least two codes.
+pub const fn checked_find_split(
+    morton_codes: &[MortonCode],
+    first: usize,
+    last: usize,
+) -> Option<usize> {
+    if first < last && last < morton_codes.len() {
+        Some(find_split(morton_codes, first, last))
+    } else {
+        None
+    }
+}
+
+/// Same as [`find_split()`], but for 64-bit Morton codes.
+pub const fn find_split_64(
+    morton_codes: &[MortonCode<u64>],
+    first: usize,
+    last: usize,
+) -> usize {
+    debug_assert!(first < last && last < morton_codes.len());
+
+    let first_code = morton_codes[first].code;
+    let last_code = morton_codes[last].code;
+    if first_code == last_code {
+        return (first + last) >> 1;
+    };
+
+    let common_prefix = calc_common_prefix_64(first_code, last_code);
+
+    let mut split = first;
+    let mut step = last - first;
+    while step > 1 {
+        step = (step + 1) >> 1;
+        let new_split = split + step;
+
+        if new_split < last {
+            let split_code = morton_codes[new_split].code;
+            let split_prefix =
+                calc_common_prefix_64(first_code, split_code);
+
+            if split_prefix > common_prefix {
+                split = new_split
+            };
+        }
+    }
+
+    split
+}
+
 /// Measures the common prefix of two morton codes.
 #[inline]
 pub const fn calc_common_prefix(code_a: u32, code_b: u32) -> u32 {
     (code_a ^ code_b).leading_zeros()
 }
 
+/// Same as [`calc_common_prefix()`], but for 64-bit Morton codes.
+#[inline]
+pub const fn calc_common_prefix_64(code_a: u64, code_b: u64) -> u32 {
+    (code_a ^ code_b).leading_zeros()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -111,4 +248,66 @@ mod tests {
         // x=1 (01), y=1 (01) -> 11 (binary) -> 3
         assert_eq!(morton_2d(1, 1), 3);
     }
+
+    #[test]
+    fn test_checked_find_split() {
+        let codes = [0b00, 0b01, 0b10, 0b11]
+            .map(|code| MortonCode { code, index: 0 });
+
+        assert_eq!(checked_find_split(&[], 0, 0), None);
+        assert_eq!(checked_find_split(&codes, 2, 2), None);
+        assert_eq!(checked_find_split(&codes, 3, 1), None);
+        assert_eq!(checked_find_split(&codes, 0, 4), None);
+        assert_eq!(checked_find_split(&codes[..1], 0, 0), None);
+
+        assert_eq!(checked_find_split(&codes, 0, 3), Some(1));
+        assert_eq!(checked_find_split(&codes, 2, 3), Some(2));
+        assert_eq!(
+            checked_find_split(&codes, 0, 3),
+            Some(find_split(&codes, 0, 3))
+        );
+    }
+
+    #[test]
+    fn test_morton_u64() {
+        assert_eq!(morton_2d_u64(0.0, 0.0), 0);
+        assert_eq!(morton_2d_u64(1.0, 1.0), u64::MAX);
+        assert_eq!(morton_2d_u64(1.0, 0.0), 0x5555555555555555);
+        assert_eq!(morton_2d_u64(0.0, 1.0), 0xAAAAAAAAAAAAAAAA);
+
+        // Neighbors that collapse with 16 bits per axis.
+        let (a, b) = (0.5, 0.5 + 1.0 / 1_000_000.0);
+        assert_eq!(morton_2d_f64(a, 0.0), morton_2d_f64(b, 0.0));
+        assert!(morton_2d_u64(a, 0.0) < morton_2d_u64(b, 0.0));
+
+        // Both precisions preserve the order of distinct codes.
+        for (x, y) in [(0.1, 0.7), (0.4, 0.2), (0.9, 0.9)] {
+            assert!(
+                u32::encode(x, y) < u32::encode(x + 0.05, y + 0.05)
+            );
+            assert!(
+                u64::encode(x, y) < u64::encode(x + 0.05, y + 0.05)
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_split_64() {
+        // Same layout as 32-bit codes, shifted into the upper bits.
+        let codes = [0b00, 0b01, 0b10, 0b11, 0b11];
+        let codes_32 =
+            codes.map(|code| MortonCode { code, index: 0 });
+        let codes_64 = codes.map(|code| MortonCode {
+            code: (code as u64) << 32,
+            index: 0,
+        });
+
+        for (first, last) in [(0, 3), (0, 4), (1, 3), (2, 4), (3, 4)]
+        {
+            assert_eq!(
+                find_split_64(&codes_64, first, last),
+                find_split(&codes_32, first, last)
+            );
+        }
+    }
 }
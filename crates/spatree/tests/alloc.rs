@@ -0,0 +1,75 @@
+//! Allocation counts of rebuilding a [`Spatree`].
+//!
+//! This lives in its own test binary, as it replaces the global
+//! allocator.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use kurbo::Rect;
+use spatree::Spatree;
+
+/// Counts the allocations made by the current thread, so that the
+/// test harness does not interfere.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ =
+            ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(
+        &self,
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> *mut u8 {
+        let _ =
+            ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+fn fill(tree: &mut Spatree, frame: usize) {
+    tree.clear();
+    for i in 0..256 {
+        let x = ((i * 37 + frame * 3) % 500) as f64;
+        let y = ((i * 91 + frame * 7) % 500) as f64;
+        tree.push_rect(Rect::new(x, y, x + 10.0, y + 10.0));
+    }
+    tree.build(|r| r.center());
+}
+
+#[test]
+fn test_rebuild_does_not_allocate() {
+    let mut tree = Spatree::new();
+
+    // Warm up.
+    fill(&mut tree, 0);
+    let capacity = tree.capacity();
+    assert!(capacity >= 256);
+
+    let before = allocations();
+    for frame in 1..10 {
+        fill(&mut tree, frame);
+    }
+    assert_eq!(allocations(), before);
+    assert_eq!(tree.capacity(), capacity);
+}
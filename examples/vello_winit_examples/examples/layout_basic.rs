@@ -1,7 +1,7 @@
 use std::any::Any;
 
 use hashbrown::HashMap;
-use kurbo::{Affine, Circle, Rect, Size, Stroke, Vec2};
+use kurbo::{Affine, Circle, Size, Stroke, Vec2};
 use rectree::layout::{
     Constraint, LayoutSolver, LayoutWorld, Positioner,
 };
@@ -21,29 +21,27 @@ fn main() {
     let create_column = |b: &mut Builder| {
         Vertical::new(10.0).show(b, |b| {
             const WIDTH: f64 = 200.0;
-            vec![
-                FixedSizeWidget::new(Size::new(WIDTH, 40.0))
-                    .with_color(css::RED)
-                    .show(b),
-                FixedSizeWidget::new(Size::new(WIDTH, 60.0))
-                    .with_color(css::ORANGE)
-                    .show(b),
-                FixedSizeWidget::new(Size::new(WIDTH, 80.0))
-                    .with_color(css::YELLOW)
-                    .show(b),
-                FixedSizeWidget::new(Size::new(WIDTH, 100.0))
-                    .with_color(css::GREEN)
-                    .show(b),
-                FixedSizeWidget::new(Size::new(WIDTH, 80.0))
-                    .with_color(css::BLUE)
-                    .show(b),
-                FixedSizeWidget::new(Size::new(WIDTH, 60.0))
-                    .with_color(css::VIOLET)
-                    .show(b),
-                FixedSizeWidget::new(Size::new(WIDTH, 40.0))
-                    .with_color(css::PURPLE)
-                    .show(b),
-            ]
+            FixedSizeWidget::new(Size::new(WIDTH, 40.0))
+                .with_color(css::RED)
+                .show(b);
+            FixedSizeWidget::new(Size::new(WIDTH, 60.0))
+                .with_color(css::ORANGE)
+                .show(b);
+            FixedSizeWidget::new(Size::new(WIDTH, 80.0))
+                .with_color(css::YELLOW)
+                .show(b);
+            FixedSizeWidget::new(Size::new(WIDTH, 100.0))
+                .with_color(css::GREEN)
+                .show(b);
+            FixedSizeWidget::new(Size::new(WIDTH, 80.0))
+                .with_color(css::BLUE)
+                .show(b);
+            FixedSizeWidget::new(Size::new(WIDTH, 60.0))
+                .with_color(css::VIOLET)
+                .show(b);
+            FixedSizeWidget::new(Size::new(WIDTH, 40.0))
+                .with_color(css::PURPLE)
+                .show(b);
         })
     };
 
@@ -57,30 +55,24 @@ fn main() {
                 Padding::all(20.0).show(b, |b| {
                     Vertical::new(20.0).show(b, |b| {
                         const HEIGHT: f64 = 60.0;
-                        vec![
-                            Horizontal::new(50.0).show(b, |b| {
-                                vec![
-                                    create_column(b),
-                                    create_column(b),
-                                    create_column(b),
-                                ]
-                            }),
-                            FixedSizeWidget::new(Size::new(
-                                50.0, HEIGHT,
-                            ))
+                        Horizontal::new(50.0).show(b, |b| {
+                            create_column(b);
+                            create_column(b);
+                            create_column(b);
+                        });
+                        FixedSizeWidget::new(Size::new(50.0, HEIGHT))
                             .with_color(css::CYAN)
-                            .show(b),
-                            FixedSizeWidget::new(Size::new(
-                                200.0, HEIGHT,
-                            ))
-                            .with_color(css::SALMON)
-                            .show(b),
-                            FixedSizeWidget::new(Size::new(
-                                800.0, HEIGHT,
-                            ))
-                            .with_color(css::RED)
-                            .show(b),
-                        ]
+                            .show(b);
+                        FixedSizeWidget::new(Size::new(
+                            200.0, HEIGHT,
+                        ))
+                        .with_color(css::SALMON)
+                        .show(b);
+                        FixedSizeWidget::new(Size::new(
+                            800.0, HEIGHT,
+                        ))
+                        .with_color(css::RED)
+                        .show(b);
                     })
                 });
             });
@@ -172,26 +164,17 @@ impl LayoutDemo {
     fn draw_tree(&self, scene: &mut Scene, transform: Affine) {
         // Start traversal from the root IDs provided by the tree.
         for root_id in self.tree.root_ids() {
-            let mut stack = vec![*root_id];
+            let mut stack = vec![self.tree.node_ref(root_id)];
 
-            while let Some(node_id) = stack.pop() {
-                // Get node from tree.
-                let node = self.tree.get(&node_id);
-
-                // Get world_translation.
-                let world_pos = node.world_translation();
-
-                // Reconstruct rect from world pos and size.
-                let world_rect = Rect::from_origin_size(
-                    world_pos.to_point(),
-                    node.size(),
-                );
+            while let Some(node) = stack.pop() {
+                // World space rect, excluding the margin.
+                let world_rect = node.world_rect();
 
                 // Hack to get the color of `FixedSizeWidget`.
                 // In real world scenario, you would want to
                 // implement a `draw` method for your `Widget` trait.
                 if let Some(color) =
-                    self.world.widgets.get(&node_id).and_then(
+                    self.world.widgets.get(&node.id()).and_then(
                         |widget| {
                             let widget: &dyn Any = widget.as_ref();
                             widget
@@ -229,9 +212,7 @@ impl LayoutDemo {
                 );
 
                 // Traverse to children.
-                for child_id in node.children().iter() {
-                    stack.push(*child_id);
-                }
+                stack.extend(node.children());
             }
         }
     }
@@ -280,6 +261,13 @@ impl VelloDemo for LayoutDemo {
         // Perform layouting.
         self.tree.layout(&self.world);
 
+        for overflow in self.tree.take_overflows() {
+            println!(
+                "{} overflowed {:?} with {:?}",
+                overflow.id, overflow.constraint, overflow.size
+            );
+        }
+
         self.draw_tree(scene, Affine::scale(scale_factor));
     }
 }
@@ -331,6 +319,15 @@ impl PlaceWidget {
 }
 
 impl LayoutSolver for PlaceWidget {
+    fn constraint(
+        &self,
+        parent_constraint: Constraint,
+    ) -> Constraint {
+        // The child can be placed anywhere within the available
+        // space, allowing it to choose a smaller size.
+        parent_constraint.loosen()
+    }
+
     fn build(
         &self,
         node: &RectNode,
@@ -338,6 +335,8 @@ impl LayoutSolver for PlaceWidget {
         positioner: &mut Positioner,
     ) -> Size {
         let constraint = node.parent_constraint();
+        // Unbounded axes are left unaligned.
+        let bound = constraint.biggest(Size::ZERO);
         let (halign, valign) = match self.alignment {
             Alignment::Both { h, v } => (Some(h), Some(v)),
             Alignment::Horizontal(halign) => (Some(halign), None),
@@ -347,13 +346,14 @@ impl LayoutSolver for PlaceWidget {
         for (id, child) in
             node.children().iter().map(|id| (id, tree.get(id)))
         {
-            let child_size = child.size();
+            let child_size = child.outer_size();
             let mut translation = Vec2::ZERO;
             let mut should_position = false;
 
             if let Some(halign) = halign
-                && let Some(width) = constraint.width
+                && constraint.has_bounded_width()
             {
+                let width = bound.width;
                 should_position = true;
                 translation.x = match halign {
                     HAlign::Left => 0.0,
@@ -365,8 +365,9 @@ impl LayoutSolver for PlaceWidget {
             }
 
             if let Some(valign) = valign
-                && let Some(height) = constraint.height
+                && constraint.has_bounded_height()
             {
+                let height = bound.height;
                 should_position = true;
                 translation.y = match valign {
                     VAlign::Top => 0.0,
@@ -400,11 +401,11 @@ impl Horizontal {
     pub fn show(
         self,
         builder: &mut Builder,
-        add_content: impl FnOnce(&mut Builder) -> Vec<NodeId>,
+        add_content: impl FnOnce(&mut Builder),
     ) -> NodeId {
-        builder.add_widget(|b| HorizontalWidget {
-            style: self,
-            children: add_content(b),
+        builder.add_widget(|b| {
+            add_content(b);
+            HorizontalWidget { style: self }
         })
     }
 }
@@ -413,24 +414,23 @@ impl Horizontal {
 #[derive(Debug, Clone)]
 pub struct HorizontalWidget {
     pub style: Horizontal,
-    pub children: Vec<NodeId>,
 }
 
 impl LayoutSolver for HorizontalWidget {
     fn build(
         &self,
-        _node: &RectNode,
+        node: &RectNode,
         tree: &Rectree,
         positioner: &mut Positioner,
     ) -> Size {
         let mut max_height = 0.0;
         let mut x_cursor = 0.0;
 
-        for id in self.children.iter() {
-            let child_node = tree.get(id);
-            let child_size = child_node.size();
+        // Absolute children are not part of the stack.
+        for child in tree.layout_children(node).iter() {
+            let child_size = child.size();
 
-            positioner.set(*id, Vec2::new(x_cursor, 0.0));
+            positioner.set(child.id, Vec2::new(x_cursor, 0.0));
             x_cursor += child_size.width + self.style.spacing;
 
             // Track the tallest child
@@ -439,7 +439,7 @@ impl LayoutSolver for HorizontalWidget {
             }
         }
         // Remove the last added spacing
-        if !self.children.is_empty() {
+        if x_cursor > 0.0 {
             x_cursor -= self.style.spacing;
         }
 
@@ -460,11 +460,11 @@ impl Vertical {
     pub fn show(
         self,
         builder: &mut Builder,
-        add_content: impl FnOnce(&mut Builder) -> Vec<NodeId>,
+        add_content: impl FnOnce(&mut Builder),
     ) -> NodeId {
-        builder.add_widget(|b| VerticalWidget {
-            style: self,
-            children: add_content(b),
+        builder.add_widget(|b| {
+            add_content(b);
+            VerticalWidget { style: self }
         })
     }
 }
@@ -473,24 +473,23 @@ impl Vertical {
 #[derive(Debug, Clone)]
 pub struct VerticalWidget {
     pub style: Vertical,
-    pub children: Vec<NodeId>,
 }
 
 impl LayoutSolver for VerticalWidget {
     fn build(
         &self,
-        _node: &RectNode,
+        node: &RectNode,
         tree: &Rectree,
         positioner: &mut Positioner,
     ) -> Size {
         let mut max_width = 0.0;
         let mut y_cursor = 0.0;
 
-        for id in self.children.iter() {
-            let child_node = tree.get(id);
-            let child_size = child_node.size();
+        // Absolute children are not part of the stack.
+        for child in tree.layout_children(node).iter() {
+            let child_size = child.size();
 
-            positioner.set(*id, Vec2::new(0.0, y_cursor));
+            positioner.set(child.id, Vec2::new(0.0, y_cursor));
 
             y_cursor += child_size.height + self.style.spacing;
             // Track the widest child
@@ -499,7 +498,7 @@ impl LayoutSolver for VerticalWidget {
             }
         }
         // Remove the last added spacing
-        if !self.children.is_empty() {
+        if y_cursor > 0.0 {
             y_cursor -= self.style.spacing;
         }
 
@@ -557,16 +556,17 @@ impl LayoutSolver for PaddingWidget {
             bottom,
         } = self.style;
 
-        Constraint {
-            // Subtract horizontal padding from width
-            width: parent_constraint
-                .width
-                .map(|w| (w - (left + right)).max(0.0)),
-            // Subtract vertical padding from height
-            height: parent_constraint
-                .height
-                .map(|h| (h - (top + bottom)).max(0.0)),
-        }
+        // Preserve the looseness of the parent.
+        let mut constraint = parent_constraint;
+        // Subtract horizontal padding from width
+        constraint.width = parent_constraint
+            .width
+            .map(|w| (w - (left + right)).max(0.0));
+        // Subtract vertical padding from height
+        constraint.height = parent_constraint
+            .height
+            .map(|h| (h - (top + bottom)).max(0.0));
+        constraint
     }
 
     /// Determines the final size and position of the padding widget and its child.
@@ -589,7 +589,7 @@ impl LayoutSolver for PaddingWidget {
         } = self.style;
 
         let child_node = tree.get(&self.child);
-        let child_size = child_node.size();
+        let child_size = child_node.outer_size();
 
         // Position the child with the specified padding offsets
         positioner.set(self.child, Vec2::new(left, top));
@@ -601,7 +601,8 @@ impl LayoutSolver for PaddingWidget {
     }
 }
 
-/// A widget that forces a specific size that ignore parent constraints.
+/// A widget that forces a specific size that ignore tight parent
+/// constraints, while staying within loose parent constraints.
 #[derive(Debug, Clone)]
 pub struct FixedSizeWidget {
     pub size: Size,
@@ -609,22 +610,36 @@ pub struct FixedSizeWidget {
 }
 
 impl LayoutSolver for FixedSizeWidget {
-    fn constraint(&self, _parent: Constraint) -> Constraint {
+    fn constraint(&self, parent: Constraint) -> Constraint {
         // Fixed size yield fixed contraint.
-        Constraint::fixed(self.size.width, self.size.height)
+        let size = self.resolve_size(parent);
+        Constraint::fixed(size.width, size.height)
     }
 
     fn build(
         &self,
-        _node: &RectNode,
+        node: &RectNode,
         _tree: &Rectree,
         _positioner: &mut Positioner,
     ) -> Size {
-        self.size
+        self.resolve_size(node.parent_constraint())
     }
 }
 
 impl FixedSizeWidget {
+    /// Resolve [`Self::size`] within the loose bounds of the parent
+    /// constraint.
+    fn resolve_size(&self, parent: Constraint) -> Size {
+        let mut size = self.size;
+        if parent.is_loose_width() {
+            size.width = parent.resolve_width(size.width);
+        }
+        if parent.is_loose_height() {
+            size.height = parent.resolve_height(size.height);
+        }
+        size
+    }
+
     pub fn new(size: Size) -> Self {
         Self {
             size,